@@ -0,0 +1,285 @@
+// The append-only file: every write is logged in the RESP form replicas receive, and the
+// log is replayed through the command dispatcher on startup. A new log starts with an RDB
+// preamble of the dataset it was opened with, like Redis with aof-use-rdb-preamble.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/aof.c
+
+use super::cache_store::CacheStore;
+use super::codec::RespCodec;
+use super::commands::{self, CommandOrigin, CommandResponse};
+use super::config::AppendFsync;
+use super::connection::ServerState;
+use super::model::RespValue;
+use super::rdb;
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task;
+
+// How often `appendfsync everysec` flushes the log to the disk
+const FSYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The AOF, open for appending.
+#[derive(Debug)]
+pub struct AppendOnlyFile {
+    file: Arc<File>,
+    fsync: AppendFsync,
+    // Writes appended since the last fsync
+    unsynced: bool,
+    last_fsync: Instant,
+    fsync_in_progress: Arc<AtomicBool>,
+    pub last_write_ok: bool,
+    // Commands appended while a rewrite writes the new file, which follow its preamble
+    rewrite_buffer: Option<Vec<u8>>,
+    // A rewrite was asked for while another was running
+    rewrite_scheduled: bool,
+    pub last_rewrite_ok: bool,
+}
+
+impl AppendOnlyFile {
+    fn new(file: File, fsync: AppendFsync) -> Self {
+        AppendOnlyFile {
+            file: Arc::new(file),
+            fsync,
+            unsynced: false,
+            last_fsync: Instant::now(),
+            fsync_in_progress: Arc::new(AtomicBool::new(false)),
+            last_write_ok: true,
+            rewrite_buffer: None,
+            rewrite_scheduled: false,
+            last_rewrite_ok: true,
+        }
+    }
+
+    pub fn rewrite_in_progress(&self) -> bool {
+        self.rewrite_buffer.is_some()
+    }
+
+    /// Appends one encoded command. With `appendfsync always` it is on the disk by the time
+    /// this returns, so before the client gets its reply.
+    pub fn append(&mut self, command: &[u8]) {
+        let result = (&*self.file).write_all(command).and_then(|()| {
+            if self.fsync == AppendFsync::Always {
+                self.file.sync_data()
+            } else {
+                self.unsynced = true;
+                Ok(())
+            }
+        });
+        if let Err(e) = &result {
+            eprintln!("Error writing to the AOF file: {}", e);
+        }
+        self.last_write_ok = result.is_ok();
+        if let Some(buffer) = &mut self.rewrite_buffer {
+            buffer.extend_from_slice(command);
+        }
+    }
+
+    /// Switches to the file a rewrite just wrote at `path`, once the commands appended
+    /// meanwhile are added to it.
+    fn finish_rewrite(&mut self, path: &Path) -> io::Result<()> {
+        let buffer = self.rewrite_buffer.take().unwrap_or_default();
+        let mut file = OpenOptions::new().append(true).open(path)?;
+        file.write_all(&buffer)?;
+        if self.fsync == AppendFsync::Always {
+            file.sync_data()?;
+        } else {
+            self.unsynced = true;
+        }
+        self.file = Arc::new(file);
+        Ok(())
+    }
+
+    /// With `appendfsync everysec`, flushes what was appended during the last second to the
+    /// disk from a blocking task. An fsync still running, on a slow disk, postpones the next.
+    fn fsync_every_second(&mut self) {
+        if self.fsync != AppendFsync::EverySec || !self.unsynced || self.last_fsync.elapsed() < FSYNC_INTERVAL {
+            return;
+        }
+        if self.fsync_in_progress.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.unsynced = false;
+        self.last_fsync = Instant::now();
+        let file = Arc::clone(&self.file);
+        let in_progress = Arc::clone(&self.fsync_in_progress);
+        task::spawn_blocking(move || {
+            if let Err(e) = file.sync_data() {
+                eprintln!("Error fsyncing the AOF file: {}", e);
+            }
+            in_progress.store(false, Ordering::Release);
+        });
+    }
+}
+
+/// Opens the AOF for appending, once the dataset is loaded and before any client is
+/// served. A missing file is created with the dataset as its preamble, so that from then on
+/// the AOF alone restores it.
+pub async fn start(state: &ServerState) -> io::Result<()> {
+    let path = state.config.aof_path();
+    if !path.exists() {
+        println!("Creating AOF file {}", path.display());
+        let snapshot = rdb::Snapshot::capture(&state.data_store.lock().unwrap());
+        write_preamble(path.clone(), snapshot).await?;
+    }
+    let file = OpenOptions::new().append(true).open(&path)?;
+    *state.aof.lock().unwrap() = Some(AppendOnlyFile::new(file, state.config.appendfsync));
+    Ok(())
+}
+
+async fn write_preamble(path: PathBuf, snapshot: rdb::Snapshot) -> io::Result<()> {
+    task::spawn_blocking(move || rdb::write_file(&path, &snapshot.encode()))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Starts the AOF over from the dataset, which a replica just replaced with the one of its
+/// master. Called with the store locked: the preamble is written from a snapshot in a
+/// blocking task, and the writes that come in meanwhile are appended to it once it is done.
+pub fn rewrite(state: &Arc<ServerState>, store: &CacheStore) {
+    let mut guard = state.aof.lock().unwrap();
+    let Some(aof) = guard.as_mut() else {
+        return;
+    };
+    if aof.rewrite_in_progress() {
+        aof.rewrite_scheduled = true;
+        return;
+    }
+    aof.rewrite_buffer = Some(Vec::new());
+    aof.rewrite_scheduled = false;
+    let snapshot = rdb::Snapshot::capture(store);
+    let state = Arc::clone(state);
+    task::spawn(async move {
+        let path = state.config.aof_path();
+        let result = write_preamble(path.clone(), snapshot).await;
+        let mut guard = state.aof.lock().unwrap();
+        let Some(aof) = guard.as_mut() else {
+            return;
+        };
+        let result = result.and_then(|()| aof.finish_rewrite(&path));
+        aof.last_rewrite_ok = result.is_ok();
+        match result {
+            Ok(()) => println!("Background AOF rewrite terminated with success"),
+            Err(e) => {
+                // Appends keep going to the previous file
+                aof.rewrite_buffer = None;
+                eprintln!("Background AOF rewrite error writing {}: {}", path.display(), e);
+            }
+        }
+    });
+    println!("Background append only file rewriting started");
+}
+
+/// Fsyncs the AOF according to `appendfsync`, and starts the rewrite asked for while the
+/// previous one ran. Called from the server cron with the store locked.
+pub fn cron(state: &Arc<ServerState>, store: &CacheStore) {
+    let scheduled = match state.aof.lock().unwrap().as_mut() {
+        Some(aof) => {
+            aof.fsync_every_second();
+            aof.rewrite_scheduled && !aof.rewrite_in_progress()
+        }
+        None => false,
+    };
+    if scheduled {
+        rewrite(state, store);
+    }
+}
+
+/// Replays the AOF at `path`: loads its RDB preamble, if any, then executes every command
+/// through the dispatcher like a client would. A last command cut short, as when the server
+/// died while appending it, is dropped from the file when `aof-load-truncated` is on, and
+/// stops the server otherwise. Returns the number of commands replayed.
+pub async fn load(state: &ServerState, path: &Path) -> io::Result<usize> {
+    let data = std::fs::read(path)?;
+    let mut offset = 0;
+    if data.starts_with(b"REDIS") {
+        let mut store = state.data_store.lock().unwrap();
+        let (keys, len) = rdb::load_prefix(&data, &mut store)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("Bad RDB preamble in the append only file: {}", e)))?;
+        println!("Reading RDB preamble from AOF file: {} keys", keys);
+        offset = len;
+    }
+
+    let bad_format = |offset: usize| {
+        io::Error::new(ErrorKind::InvalidData, format!("Bad file format reading the append only file at offset {}", offset))
+    };
+    let mut reader = &data[offset..];
+    let mut replayed = 0;
+    while !reader.is_empty() {
+        let before = reader.len();
+        let argv = match RespCodec::decode(&mut reader).await {
+            Ok(RespValue::Array(items)) if !items.is_empty() => commands::to_argv(&items).map_err(|_| bad_format(offset))?,
+            Ok(_) => return Err(bad_format(offset)),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                load_truncated(state, path, offset)?;
+                break;
+            }
+            Err(_) => return Err(bad_format(offset)),
+        };
+        if commands::lookup_command(&argv[0]).is_none() {
+            let message = format!("Unknown command '{}' reading the append only file", String::from_utf8_lossy(&argv[0]));
+            return Err(io::Error::new(ErrorKind::InvalidData, message));
+        }
+        // Like Redis, a command that fails is skipped: it failed the same way when logged
+        if let CommandResponse::Normal(RespValue::Error(e)) = commands::dispatch(state, &argv, CommandOrigin::AppendOnlyFile) {
+            eprintln!("Error replaying a command from the AOF: {}", e);
+        }
+        offset += before - reader.len();
+        replayed += 1;
+    }
+    Ok(replayed)
+}
+
+// Cuts the incomplete command at `valid_len` off the AOF, so new writes do not follow it
+fn load_truncated(state: &ServerState, path: &Path, valid_len: usize) -> io::Result<()> {
+    if !state.config.aof_load_truncated {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "Unexpected end of file reading the append only file. Set aof-load-truncated to yes to load it anyway, dropping the incomplete last command",
+        ));
+    }
+    eprintln!("!!! Warning: short read while loading the AOF file {}!!!", path.display());
+    OpenOptions::new().write(true).open(path)?.set_len(valid_len as u64)?;
+    eprintln!("AOF loaded anyway because aof-load-truncated is enabled, truncated to {} bytes", valid_len);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::config::ServerConfig;
+    use bytes::Bytes;
+
+    fn argv(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())).collect()
+    }
+
+    fn encode(argv: &[Bytes]) -> Vec<u8> {
+        RespCodec::encode(&RespValue::Array(argv.iter().cloned().map(RespValue::BinaryBulkString).collect()))
+    }
+
+    #[test]
+    fn served_xread_is_not_logged() {
+        let path = std::env::temp_dir().join(format!("served-xread-{}.aof", std::process::id()));
+        let file = OpenOptions::new().create(true).truncate(true).read(true).write(true).open(&path).unwrap();
+        let state = ServerState::new(ServerConfig::default(), None);
+        *state.aof.lock().unwrap() = Some(AppendOnlyFile::new(file, AppendFsync::Always));
+
+        let xread = argv(&["XREAD", "BLOCK", "0", "STREAMS", "stream", "$"]);
+        let CommandResponse::Blocked(mut blocked) = commands::dispatch(&state, &xread, CommandOrigin::Client) else {
+            panic!("XREAD BLOCK on a missing stream did not block");
+        };
+        let xadd = argv(&["XADD", "stream", "1-1", "field", "value"]);
+        let set = argv(&["SET", "key", "value"]);
+        commands::dispatch(&state, &xadd, CommandOrigin::Client);
+        assert!(blocked.receiver.try_recv().is_ok());
+        commands::dispatch(&state, &set, CommandOrigin::Client);
+
+        // Replaying the XREAD served in between would be harmless but it is not a write
+        let logged = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(logged, [encode(&xadd), encode(&set)].concat());
+    }
+}
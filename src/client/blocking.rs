@@ -0,0 +1,98 @@
+// Bookkeeping of clients parked by blocking commands such as BLPOP.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/blocked.c
+
+use super::model::RespValue;
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet, VecDeque};
+use tokio::sync::oneshot;
+
+/// A client waiting for data on one or more keys. Once one of them receives a value of
+/// `value_type` the command in `argv` is executed again, and its reply is sent to the
+/// client task through `sender`.
+#[derive(Debug)]
+pub struct BlockedClient {
+    pub argv: Vec<Bytes>,
+    pub value_type: &'static str,
+    keys: Vec<Bytes>,
+    sender: oneshot::Sender<RespValue>,
+}
+
+impl BlockedClient {
+    pub fn reply(self, value: RespValue) {
+        // The client may have disconnected in the meantime, there is nobody left to tell
+        let _ = self.sender.send(value);
+    }
+}
+
+/// The blocked clients of a keyspace, queued per key in the order they blocked so the
+/// longest waiting client is served first.
+#[derive(Debug, Default)]
+pub struct BlockedClients {
+    next_id: u64,
+    clients: HashMap<u64, BlockedClient>,
+    waiting: HashMap<Bytes, VecDeque<u64>>,
+    // Keys that were added to while clients waited on them, in signalling order
+    ready_keys: Vec<Bytes>,
+    ready_set: HashSet<Bytes>,
+}
+
+impl BlockedClients {
+    /// Parks a client on `keys` and returns its id together with the receiving end of its
+    /// reply.
+    pub fn block(
+        &mut self,
+        argv: Vec<Bytes>,
+        keys: Vec<Bytes>,
+        value_type: &'static str,
+    ) -> (u64, oneshot::Receiver<RespValue>) {
+        self.next_id += 1;
+        let id = self.next_id;
+        let (sender, receiver) = oneshot::channel();
+        for key in &keys {
+            let queue = self.waiting.entry(key.clone()).or_default();
+            // BLPOP k k waits once on k
+            if !queue.contains(&id) {
+                queue.push_back(id);
+            }
+        }
+        self.clients.insert(id, BlockedClient { argv, value_type, keys, sender });
+        (id, receiver)
+    }
+
+    /// Removes a client from every queue it waits in. Returns None if it was already served.
+    pub fn unblock(&mut self, id: u64) -> Option<BlockedClient> {
+        let client = self.clients.remove(&id)?;
+        for key in &client.keys {
+            if let Some(queue) = self.waiting.get_mut(key) {
+                queue.retain(|&waiting| waiting != id);
+                if queue.is_empty() {
+                    self.waiting.remove(key);
+                }
+            }
+        }
+        Some(client)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&BlockedClient> {
+        self.clients.get(&id)
+    }
+
+    /// Notes that `key` received a value, if anyone is waiting for it.
+    pub fn signal_key_as_ready(&mut self, key: &Bytes) {
+        if self.waiting.contains_key(key) && self.ready_set.insert(key.clone()) {
+            self.ready_keys.push(key.clone());
+        }
+    }
+
+    pub fn take_ready_keys(&mut self) -> Vec<Bytes> {
+        self.ready_set.clear();
+        std::mem::take(&mut self.ready_keys)
+    }
+
+    /// The clients waiting on `key`, longest waiting first.
+    pub fn waiting_on(&self, key: &[u8]) -> Vec<u64> {
+        self.waiting
+            .get(key)
+            .map_or_else(Vec::new, |queue| queue.iter().copied().collect())
+    }
+}
@@ -1,39 +1,705 @@
-use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use super::blocking::BlockedClients;
+use super::dict::Dict;
+use super::error::CommandError;
+use super::types::hash::Hash;
+use super::types::set::Set;
+use super::types::stream::Stream;
+use super::types::zset::SortedSet;
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+/// Current wall-clock time in milliseconds since the Unix epoch. Expiry deadlines are kept
+/// in this unit so they survive restarts and can be reported by EXPIRETIME.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
 
-#[derive(Debug)]
-struct CacheValue {
-    value: String,
-    expires_at: Option<Instant>,
+// Active expire cycle tuning, see activeExpireCycle() in Redis' expire.c
+const ACTIVE_EXPIRE_EFFORT: usize = 0; // 0..=9, the `active-expire-effort` config minus one
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20 + 20 / 4 * ACTIVE_EXPIRE_EFFORT;
+const ACTIVE_EXPIRE_ACCEPTABLE_STALE: usize = 10 - ACTIVE_EXPIRE_EFFORT; // percent
+const ACTIVE_EXPIRE_SLOW_TIME_PERC: u32 = 25 + 2 * ACTIVE_EXPIRE_EFFORT as u32;
+const ACTIVE_EXPIRE_FAST_DURATION: Duration = Duration::from_micros(1000 + 1000 / 4 * ACTIVE_EXPIRE_EFFORT as u64);
+
+/// The two flavours of active expiration: the slow cycle runs from the server cron with a
+/// budget of a quarter of the cron period, the fast cycle runs in between whenever the last
+/// cycle left too many stale keys behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireCycle {
+    Slow { cron_period: Duration },
+    Fast,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExpireCycleReport {
+    pub sampled: usize,
+    pub expired: usize,
+    pub timed_out: bool,
+}
+
+impl ExpireCycleReport {
+    /// Whether another cycle should follow soon because expired keys are still piling up.
+    pub fn needs_more(&self) -> bool {
+        self.timed_out
+            || (self.sampled > 0 && self.expired * 100 / self.sampled > ACTIVE_EXPIRE_ACCEPTABLE_STALE)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExpireStats {
+    pub expired_keys: u64,
+    pub expired_subkeys: u64, // hash fields
+    pub expired_stale_perc: f64,
+    pub expired_time_cap_reached_count: u64,
+}
+
+// How many expired keys RANDOMKEY may draw on a replica before returning one of them
+const RANDOM_KEY_MAX_HIDDEN: usize = 100;
+
+/// What a lookup does with a key whose deadline has passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LazyExpire {
+    /// Delete it and queue a DEL for replicas, as a master does.
+    Delete,
+    /// Report it as missing but leave it in place, as a replica does for its own clients
+    /// until the DEL of its master arrives.
+    Hide,
+    /// Treat it as live, for the commands a master streams: it has not deleted the key yet.
+    Keep,
+}
+
+#[derive(Debug, Clone)]
+pub enum CacheValue {
+    String(Bytes),
+    List(VecDeque<Bytes>),
+    Hash(Hash),
+    Set(Set),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl CacheValue {
+    /// The name TYPE reports for this value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            CacheValue::String(_) => "string",
+            CacheValue::List(_) => "list",
+            CacheValue::Hash(_) => "hash",
+            CacheValue::Set(_) => "set",
+            CacheValue::SortedSet(_) => "zset",
+            CacheValue::Stream(_) => "stream",
+        }
+    }
+
+    /// Collections never stay in the keyspace once their last element is gone. Streams are
+    /// the exception, they keep their last ID and consumer groups.
+    fn is_empty_collection(&self) -> bool {
+        match self {
+            CacheValue::String(_) | CacheValue::Stream(_) => false,
+            CacheValue::List(list) => list.is_empty(),
+            CacheValue::Hash(hash) => hash.is_empty(),
+            CacheValue::Set(set) => set.is_empty(),
+            CacheValue::SortedSet(zset) => zset.is_empty(),
+        }
+    }
+}
+
+/// Generates the typed accessors of one value type: a shared view, a mutable view, and a
+/// mutable view that creates an empty value when the key is missing. All of them fail with
+/// WRONGTYPE when the key holds a different type. `before` names a method that gets to
+/// tidy the value up first, such as reclaiming expired hash fields.
+macro_rules! typed_accessors {
+    ($variant:ident, $ty:ty, $get:ident, $get_mut:ident, $get_or_create:ident $(, before: $before:ident)?) => {
+        pub fn $get(&mut self, key: &[u8]) -> Result<Option<&$ty>, CommandError> {
+            $(self.$before(key);)?
+            match self.value(key) {
+                Some(CacheValue::$variant(value)) => Ok(Some(value)),
+                Some(_) => Err(CommandError::WrongType),
+                None => Ok(None),
+            }
+        }
+
+        pub fn $get_mut(&mut self, key: &[u8]) -> Result<Option<&mut $ty>, CommandError> {
+            $(self.$before(key);)?
+            match self.value_mut(key) {
+                Some(CacheValue::$variant(value)) => Ok(Some(value)),
+                Some(_) => Err(CommandError::WrongType),
+                None => Ok(None),
+            }
+        }
+
+        pub fn $get_or_create(&mut self, key: &Bytes) -> Result<&mut $ty, CommandError> {
+            $(self.$before(key);)?
+            if !self.contains_key(key) {
+                // A hidden expired key is written over, deadline included
+                self.expires.remove(key);
+                self.data.insert(key.clone(), Arc::new(CacheValue::$variant(Default::default())));
+                self.blocked.signal_key_as_ready(key);
+            }
+            match self.value_mut(key) {
+                Some(CacheValue::$variant(value)) => Ok(value),
+                _ => Err(CommandError::WrongType),
+            }
+        }
+    };
 }
 
 #[derive(Debug)]
 pub struct CacheStore {
-    data: HashMap<String, CacheValue>,
+    // Values are shared with the snapshots taken by BGSAVE and full resyncs, and copied
+    // only when written while a snapshot still holds them
+    data: Dict<Bytes, Arc<CacheValue>>,
+    // Deadlines (Unix milliseconds) of the keys that have a TTL, sampled by the active expire cycle
+    expires: Dict<Bytes, u64>,
+    expire_cursor: u64,
+    // Earliest field deadline of every hash that has fields with a TTL
+    hash_field_expires: Dict<Bytes, u64>,
+    hash_field_expire_cursor: u64,
+    expire_stats: ExpireStats,
+    lazy_expire: LazyExpire,
+    // Writes the keyspace made on its own, such as deleting expired keys and hash fields,
+    // that replicas still have to hear about
+    pending_propagation: Vec<Vec<Bytes>>,
+    blocked: BlockedClients,
 }
 
 impl CacheStore {
     pub fn new() -> Self {
         CacheStore {
-            data: HashMap::new(),
+            data: Dict::new(),
+            expires: Dict::new(),
+            expire_cursor: 0,
+            hash_field_expires: Dict::new(),
+            hash_field_expire_cursor: 0,
+            expire_stats: ExpireStats::default(),
+            lazy_expire: LazyExpire::Delete,
+            pending_propagation: Vec::new(),
+            blocked: BlockedClients::default(),
+        }
+    }
+
+    /// Deletes every key, as a replica does before loading the dataset of its master.
+    /// Clients blocked on keys stay blocked.
+    pub fn flush(&mut self) {
+        self.data = Dict::new();
+        self.expires = Dict::new();
+        self.expire_cursor = 0;
+        self.hash_field_expires = Dict::new();
+        self.hash_field_expire_cursor = 0;
+        self.pending_propagation.clear();
+    }
+
+    pub fn set(&mut self, key: Bytes, value: Bytes, expires_at: Option<u64>) {
+        self.insert_entry(key, CacheValue::String(value), expires_at);
+    }
+
+    /// Replaces the value of a key while keeping whatever TTL it already had.
+    pub fn set_keep_ttl(&mut self, key: Bytes, value: Bytes) {
+        if self.expire_if_needed(&key) {
+            self.expires.remove(&key);
+        }
+        self.hash_field_expires.remove(&key);
+        self.data.insert(key, Arc::new(CacheValue::String(value)));
+    }
+
+    /// The string stored at `key`, or WRONGTYPE if the key holds another type.
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Bytes>, CommandError> {
+        match self.value(key) {
+            Some(CacheValue::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(CommandError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    typed_accessors!(List, VecDeque<Bytes>, list, list_mut, list_or_create);
+    typed_accessors!(Hash, Hash, hash, hash_mut, hash_or_create, before: expire_hash_fields);
+    typed_accessors!(Set, Set, set_value, set_value_mut, set_value_or_create);
+    typed_accessors!(SortedSet, SortedSet, zset, zset_mut, zset_or_create);
+    typed_accessors!(Stream, Stream, stream, stream_mut, stream_or_create);
+
+    /// The sets stored at `keys`, for commands that read several at once such as SINTER.
+    /// Fails with WRONGTYPE if any of the keys holds another type.
+    pub fn set_values(&mut self, keys: &[Bytes]) -> Result<Vec<Option<&Set>>, CommandError> {
+        self.values(keys)
+            .into_iter()
+            .map(|value| match value {
+                Some(CacheValue::Set(set)) => Ok(Some(set)),
+                Some(_) => Err(CommandError::WrongType),
+                None => Ok(None),
+            })
+            .collect()
+    }
+
+    /// The values stored at `keys`, all borrowed at once.
+    pub fn values(&mut self, keys: &[Bytes]) -> Vec<Option<&CacheValue>> {
+        for key in keys {
+            self.expire_if_needed(key);
+        }
+        keys.iter().map(|key| self.data.get(key).map(Arc::as_ref)).collect()
+    }
+
+    /// Sets or clears the deadline of a hash field, see [`Hash::set_expires_at`]. Goes
+    /// through the keyspace so the active expire cycle learns about the hash.
+    pub fn set_hash_field_expires_at(
+        &mut self,
+        key: &Bytes,
+        field: &[u8],
+        expires_at: Option<u64>,
+    ) -> Result<bool, CommandError> {
+        let Some(hash) = self.hash_mut(key)? else {
+            return Ok(false);
+        };
+        let updated = hash.set_expires_at(field, expires_at);
+        let next_expiry = hash.next_expiry();
+        self.index_hash_field_expiry(key, next_expiry);
+        Ok(updated)
+    }
+
+    /// Lazy expiration of hash fields: deletes the expired fields of the hash at `key`, and
+    /// the key itself once no field is left. Only a master reclaims fields, a replica keeps
+    /// serving them until the HDEL of its master arrives.
+    fn expire_hash_fields(&mut self, key: &[u8]) {
+        if self.lazy_expire == LazyExpire::Delete && !self.expire_if_needed(key) {
+            self.delete_expired_hash_fields(key);
+        }
+    }
+
+    /// Deletes the expired fields of the hash at `key`, queueing them for propagation as
+    /// HDEL. Returns the number of fields deleted.
+    fn delete_expired_hash_fields(&mut self, key: &[u8]) -> usize {
+        let now = now_ms();
+        // Checked before borrowing the hash mutably, which copies it if a snapshot holds it
+        let due = match self.data.get(key).map(Arc::as_ref) {
+            Some(CacheValue::Hash(hash)) => hash.next_expiry().is_some_and(|expires_at| expires_at <= now),
+            _ => false,
+        };
+        if !due {
+            return 0;
+        }
+        let Some(CacheValue::Hash(hash)) = self.data.get_mut(key).map(Arc::make_mut) else {
+            return 0;
+        };
+        let fields = hash.remove_expired(now);
+        let next_expiry = hash.next_expiry();
+        let empty = hash.is_empty();
+
+        let key = Bytes::copy_from_slice(key);
+        let deleted = fields.len();
+        self.expire_stats.expired_subkeys += deleted as u64;
+        self.pending_propagation
+            .push([Bytes::from_static(b"HDEL"), key.clone()].into_iter().chain(fields).collect());
+        if empty {
+            self.expires.remove(&key);
+            self.data.remove(&key);
+        }
+        self.index_hash_field_expiry(&key, next_expiry);
+        deleted
+    }
+
+    fn index_hash_field_expiry(&mut self, key: &Bytes, next_expiry: Option<u64>) {
+        match next_expiry {
+            Some(expires_at) => {
+                self.hash_field_expires.insert(key.clone(), expires_at);
+            }
+            None => {
+                self.hash_field_expires.remove(key);
+            }
+        }
+    }
+
+    /// Deletes `key` if it holds a collection that has become empty.
+    pub fn remove_if_empty(&mut self, key: &[u8]) {
+        if self.data.get(key).is_some_and(|value| value.is_empty_collection()) {
+            self.expires.remove(key);
+            self.hash_field_expires.remove(key);
+            self.data.remove(key);
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> bool {
+        self.take_entry(key).is_some()
+    }
+
+    pub fn contains_key(&mut self, key: &[u8]) -> bool {
+        !self.expire_if_needed(key) && self.data.contains_key(key)
+    }
+
+    pub fn value(&mut self, key: &[u8]) -> Option<&CacheValue> {
+        if self.expire_if_needed(key) {
+            return None;
+        }
+        self.data.get(key).map(Arc::as_ref)
+    }
+
+    pub fn value_mut(&mut self, key: &[u8]) -> Option<&mut CacheValue> {
+        if self.expire_if_needed(key) {
+            return None;
+        }
+        self.data.get_mut(key).map(Arc::make_mut)
+    }
+
+    /// Removes a key and hands back its value together with its expiry deadline.
+    pub fn take_entry(&mut self, key: &[u8]) -> Option<(CacheValue, Option<u64>)> {
+        if self.expire_if_needed(key) {
+            return None;
+        }
+        let value = Arc::unwrap_or_clone(self.data.remove(key)?);
+        self.hash_field_expires.remove(key);
+        Some((value, self.expires.remove(key)))
+    }
+
+    /// Copies a key's value together with its expiry deadline.
+    pub fn clone_entry(&mut self, key: &[u8]) -> Option<(CacheValue, Option<u64>)> {
+        if self.expire_if_needed(key) {
+            return None;
         }
+        let value = CacheValue::clone(self.data.get(key)?);
+        Some((value, self.expires.get(key).copied()))
     }
 
-    pub fn set(&mut self, key: String, value: String, expiry: Option<Duration>) {
-        let expires_at = match expiry {
-            Some(expiry) => Some(Instant::now() + expiry),
-            None => None,
+    /// Stores a value under `key`, replacing any previous value and TTL.
+    pub fn insert_entry(&mut self, key: Bytes, value: CacheValue, expires_at: Option<u64>) {
+        match expires_at {
+            Some(expires_at) => {
+                self.expires.insert(key.clone(), expires_at);
+            }
+            None => {
+                self.expires.remove(&key);
+            }
+        }
+        let next_field_expiry = match &value {
+            CacheValue::Hash(hash) => hash.next_expiry(),
+            _ => None,
         };
-        self.data.insert(key, CacheValue { value, expires_at });
+        self.index_hash_field_expiry(&key, next_field_expiry);
+        // Clients blocked on the key get a chance at the new value, whatever it replaced
+        self.blocked.signal_key_as_ready(&key);
+        self.data.insert(key, Arc::new(value));
+    }
+
+    /// Wakes the clients blocked on `key` after a write that added to its value in place,
+    /// such as XADD. Writes that create the value signal it on their own.
+    pub fn signal_key_as_ready(&mut self, key: &Bytes) {
+        self.blocked.signal_key_as_ready(key);
+    }
+
+    /// The clients parked on keys of this keyspace by blocking commands.
+    pub fn blocked_clients(&mut self) -> &mut BlockedClients {
+        &mut self.blocked
+    }
+
+    /// A random live key. Expired keys that get picked are reclaimed on the way, so every
+    /// miss shrinks the keyspace and the loop terminates. Hidden keys are not reclaimed, so
+    /// like Redis on a replica it settles for an expired key after enough misses.
+    pub fn random_key(&mut self) -> Option<Bytes> {
+        let mut misses = 0;
+        loop {
+            let key = self.data.random_entry()?.0.clone();
+            if !self.expire_if_needed(&key) {
+                return Some(key);
+            }
+            misses += 1;
+            if self.lazy_expire == LazyExpire::Hide && misses >= RANDOM_KEY_MAX_HIDDEN {
+                return Some(key);
+            }
+        }
+    }
+
+    /// `None` when the key does not exist, `Some(None)` when it exists without a TTL.
+    pub fn expires_at(&mut self, key: &[u8]) -> Option<Option<u64>> {
+        if self.expire_if_needed(key) || !self.data.contains_key(key) {
+            return None;
+        }
+        Some(self.expires.get(key).copied())
     }
-    pub fn get(&self, key: &str) -> Option<String> {
-        self.data.get(key).and_then(|cache_value| {
-            match cache_value.expires_at {
-                Some(expiry) if expiry > Instant::now() => Some(cache_value.value.clone()),
-                None => Some(cache_value.value.clone()),
-                _ => None,
+
+    /// Sets or clears the expiry deadline of an existing key. Returns false if the key is missing.
+    pub fn set_expires_at(&mut self, key: &[u8], expires_at: Option<u64>) -> bool {
+        if self.expire_if_needed(key) {
+            return false;
+        }
+        let key = match self.data.get_key_value(key) {
+            Some((key, _)) => key.clone(),
+            None => return false,
+        };
+        match expires_at {
+            Some(expires_at) => {
+                self.expires.insert(key, expires_at);
+            }
+            None => {
+                self.expires.remove(&key);
+            }
+        }
+        true
+    }
+
+    /// Every key that has not logically expired, in no particular order.
+    pub fn keys(&self) -> Vec<Bytes> {
+        let now = now_ms();
+        self.data
+            .keys()
+            .filter(|key| !self.is_expired(key, now))
+            .cloned()
+            .collect()
+    }
+
+    /// Every key that has not logically expired with its value and deadline, in no
+    /// particular order.
+    pub fn entries(&self) -> impl Iterator<Item = (&Bytes, &CacheValue, Option<u64>)> {
+        self.shared_entries()
+            .map(|(key, value, expires_at)| (key, value.as_ref(), expires_at))
+    }
+
+    /// Like [`CacheStore::entries`], with the values in the form they are shared in: a clone
+    /// keeps the value as it is now, without copying it, whatever writes follow.
+    pub fn shared_entries(&self) -> impl Iterator<Item = (&Bytes, &Arc<CacheValue>, Option<u64>)> {
+        let now = now_ms();
+        self.data
+            .iter()
+            .filter(move |(key, _)| !self.is_expired(key, now))
+            .map(|(key, value)| (key, value, self.expires.get(key.as_ref()).copied()))
+    }
+
+    /// One SCAN step over the keyspace, see [`Dict::scan`]. Entries that have logically
+    /// expired but were not reclaimed yet are skipped.
+    pub fn scan<F>(&self, cursor: u64, mut visit: F) -> u64
+    where
+        F: FnMut(&Bytes, &CacheValue),
+    {
+        let now = now_ms();
+        self.data.scan(cursor, |key, value| {
+            if !self.is_expired(key, now) {
+                visit(key, value);
             }
         })
     }
+
+    fn is_expired(&self, key: &[u8], now: u64) -> bool {
+        self.expires.get(key).is_some_and(|&expires_at| expires_at <= now)
+    }
+
+    pub fn expire_stats(&self) -> ExpireStats {
+        self.expire_stats
+    }
+
+    /// Sets how lookups treat keys past their deadline until it is set again, see [`LazyExpire`].
+    pub fn set_lazy_expire(&mut self, mode: LazyExpire) {
+        self.lazy_expire = mode;
+    }
+
+    /// Takes the writes the keyspace made on its own since the last call, so they reach the
+    /// replicas in the order they happened.
+    pub fn take_pending_propagation(&mut self) -> Vec<Vec<Bytes>> {
+        std::mem::take(&mut self.pending_propagation)
+    }
+
+    /// Lazy expiration: returns true if the key is past its deadline, which the caller must
+    /// then treat as missing. Whether the key is also deleted depends on the [`LazyExpire`] mode.
+    fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        if self.lazy_expire == LazyExpire::Keep {
+            return false;
+        }
+        if self.is_expired(key, now_ms()) {
+            if self.lazy_expire == LazyExpire::Delete {
+                self.delete_expired(key);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Deletes a key whose deadline has passed, queueing a DEL so that replicas do not have
+    /// to expire it on their own clock.
+    fn delete_expired(&mut self, key: &[u8]) {
+        self.expires.remove(key);
+        self.hash_field_expires.remove(key);
+        self.data.remove(key);
+        self.expire_stats.expired_keys += 1;
+        self.pending_propagation
+            .push(vec![Bytes::from_static(b"DEL"), Bytes::copy_from_slice(key)]);
+    }
+
+    /// Active expiration: walks the expires table from where the previous cycle stopped,
+    /// deleting what has expired, and keeps going while more than the acceptable share of
+    /// the sampled keys turned out to be stale and the time budget allows.
+    pub fn active_expire_cycle(&mut self, cycle: ExpireCycle) -> ExpireCycleReport {
+        let time_limit = match cycle {
+            ExpireCycle::Slow { cron_period } => cron_period * ACTIVE_EXPIRE_SLOW_TIME_PERC / 100,
+            ExpireCycle::Fast => ACTIVE_EXPIRE_FAST_DURATION,
+        };
+        let start = Instant::now();
+        let mut report = ExpireCycleReport::default();
+
+        loop {
+            if self.expires.is_empty() {
+                self.expire_cursor = 0;
+                break;
+            }
+
+            let now = now_ms();
+            let mut sampled = 0;
+            let mut stale: Vec<Bytes> = Vec::new();
+            // Bound the number of (possibly empty) buckets visited per iteration
+            let max_buckets = ACTIVE_EXPIRE_KEYS_PER_LOOP * 20;
+            let mut checked_buckets = 0;
+            while sampled < ACTIVE_EXPIRE_KEYS_PER_LOOP && checked_buckets < max_buckets {
+                self.expire_cursor = self.expires.scan(self.expire_cursor, |key, &expires_at| {
+                    sampled += 1;
+                    if expires_at <= now {
+                        stale.push(key.clone());
+                    }
+                });
+                checked_buckets += 1;
+                if self.expire_cursor == 0 {
+                    break;
+                }
+            }
+
+            let expired = stale.len();
+            for key in stale {
+                self.delete_expired(&key);
+            }
+            report.sampled += sampled;
+            report.expired += expired;
+
+            if start.elapsed() > time_limit {
+                report.timed_out = true;
+                self.expire_stats.expired_time_cap_reached_count += 1;
+                break;
+            }
+            if sampled == 0 || expired * 100 / sampled <= ACTIVE_EXPIRE_ACCEPTABLE_STALE {
+                break;
+            }
+        }
+
+        if !report.timed_out && self.active_expire_hash_fields(start, time_limit) {
+            report.timed_out = true;
+            self.expire_stats.expired_time_cap_reached_count += 1;
+        }
+
+        // Keep a running estimate of the share of logically expired keys still in memory
+        let current_perc = if report.sampled > 0 {
+            report.expired as f64 / report.sampled as f64
+        } else {
+            0.0
+        };
+        self.expire_stats.expired_stale_perc = current_perc * 0.05 + self.expire_stats.expired_stale_perc * 0.95;
+        report
+    }
+
+    /// The hash field part of the active expire cycle: walks the index of hashes with
+    /// expiring fields the same way the keys are walked, and reclaims the fields of every
+    /// hash whose earliest deadline has passed. Returns true if it ran out of time.
+    fn active_expire_hash_fields(&mut self, start: Instant, time_limit: Duration) -> bool {
+        loop {
+            if self.hash_field_expires.is_empty() {
+                self.hash_field_expire_cursor = 0;
+                return false;
+            }
+
+            let now = now_ms();
+            let mut sampled = 0;
+            let mut due: Vec<Bytes> = Vec::new();
+            let max_buckets = ACTIVE_EXPIRE_KEYS_PER_LOOP * 20;
+            let mut checked_buckets = 0;
+            while sampled < ACTIVE_EXPIRE_KEYS_PER_LOOP && checked_buckets < max_buckets {
+                self.hash_field_expire_cursor =
+                    self.hash_field_expires
+                        .scan(self.hash_field_expire_cursor, |key, &expires_at| {
+                            sampled += 1;
+                            if expires_at <= now {
+                                due.push(key.clone());
+                            }
+                        });
+                checked_buckets += 1;
+                if self.hash_field_expire_cursor == 0 {
+                    break;
+                }
+            }
+
+            for key in &due {
+                self.delete_expired_hash_fields(key);
+                // The indexed deadline may be stale, e.g. after HPERSIST, so take it from
+                // the hash again
+                let next_expiry = match self.data.get(key).map(Arc::as_ref) {
+                    Some(CacheValue::Hash(hash)) => hash.next_expiry(),
+                    _ => None,
+                };
+                self.index_hash_field_expiry(key, next_expiry);
+            }
+
+            if start.elapsed() > time_limit {
+                return true;
+            }
+            if sampled == 0 || due.len() * 100 / sampled <= ACTIVE_EXPIRE_ACCEPTABLE_STALE {
+                return false;
+            }
+        }
+    }
+
+    /// Gives in-progress table resizes some time from the server cron, so memory released by
+    /// shrinking is not only reclaimed as a side effect of later writes.
+    pub fn incremental_rehash(&mut self, budget: Duration) {
+        let start = Instant::now();
+        while (self.data.rehash(100) | self.expires.rehash(100) | self.hash_field_expires.rehash(100))
+            && start.elapsed() < budget
+        {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_with_expired_key() -> CacheStore {
+        let mut store = CacheStore::new();
+        store.set(Bytes::from_static(b"k"), Bytes::from_static(b"v"), Some(now_ms() - 1));
+        store
+    }
+
+    #[test]
+    fn lazy_expire_deletes_and_queues_a_del() {
+        let mut store = store_with_expired_key();
+        assert_eq!(store.get(b"k").unwrap(), None);
+        assert!(store.data.is_empty());
+        assert_eq!(
+            store.take_pending_propagation(),
+            vec![vec![Bytes::from_static(b"DEL"), Bytes::from_static(b"k")]]
+        );
+    }
+
+    #[test]
+    fn hidden_keys_are_missing_but_wait_for_the_del() {
+        let mut store = store_with_expired_key();
+        store.set_lazy_expire(LazyExpire::Hide);
+        assert_eq!(store.get(b"k").unwrap(), None);
+        assert_eq!(store.expires_at(b"k"), None);
+        assert!(!store.set_expires_at(b"k", None));
+        assert!(store.data.contains_key(b"k".as_slice()));
+        assert!(store.take_pending_propagation().is_empty());
+
+        // Writing over a hidden key does not inherit its stale deadline
+        store.set_keep_ttl(Bytes::from_static(b"k"), Bytes::from_static(b"w"));
+        assert_eq!(store.get(b"k").unwrap(), Some(Bytes::from_static(b"w")));
+
+        let mut store = store_with_expired_key();
+        store.set_lazy_expire(LazyExpire::Hide);
+        store.list_or_create(&Bytes::from_static(b"k")).unwrap().push_back(Bytes::from_static(b"a"));
+        assert_eq!(store.list(b"k").unwrap().map(VecDeque::len), Some(1));
+    }
+
+    #[test]
+    fn keys_streamed_by_the_master_are_kept() {
+        let mut store = store_with_expired_key();
+        store.set_lazy_expire(LazyExpire::Keep);
+        assert_eq!(store.get(b"k").unwrap(), Some(Bytes::from_static(b"v")));
+        assert!(store.take_pending_propagation().is_empty());
+        assert!(store.remove(b"k"));
+    }
 }
@@ -1,5 +1,30 @@
 use super::model::RespValue;
-use std::io::{self, BufRead};
+use bytes::Bytes;
+use std::borrow::Cow;
+use std::io;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+// proto-max-bulk-len: the longest bulk string accepted
+const PROTO_MAX_BULK_LEN: u64 = 512 * 1024 * 1024;
+// The longest line accepted, for simple strings, errors, integers and length headers
+const PROTO_MAX_LINE_LEN: u64 = 64 * 1024;
+
+/// A malformed or oversized request. The connection cannot be resynchronized after one, so
+/// it is reported to the client and then closed, like Redis does.
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Protocol error: {}", message))
+}
+
+/// Simple strings and errors end at the first CRLF, so a CR or LF inside one, such as an
+/// argument echoed back in an error, would be read as the start of another reply. Like
+/// Redis they are replaced with spaces.
+fn single_line(s: &str) -> Cow<'_, str> {
+    if s.contains(['\r', '\n']) {
+        Cow::Owned(s.replace(['\r', '\n'], " "))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
 
 pub struct RespCodec;
 
@@ -23,13 +48,13 @@ impl RespCodec {
 
     pub fn encode(value: &RespValue) -> Vec<u8> {
         match value {
-            RespValue::SimpleString(s) => format!("+{}\r\n", s).into_bytes(),
-            RespValue::Error(e) => format!("-{}\r\n", e).into_bytes(),
+            RespValue::SimpleString(s) => format!("+{}\r\n", single_line(s)).into_bytes(),
+            RespValue::Error(e) => format!("-{}\r\n", single_line(e)).into_bytes(),
             RespValue::Integer(i) => format!(":{}\r\n", i).into_bytes(),
             RespValue::BulkString(s) => format!("${}\r\n{}\r\n", s.len(), s).into_bytes(),
             RespValue::BinaryBulkString(b) => {
                 let mut result = format!("${}\r\n", b.len()).into_bytes();
-                result.extend(b);
+                result.extend_from_slice(b);
                 result.extend(b"\r\n");
                 result
             }
@@ -45,76 +70,135 @@ impl RespCodec {
         }
     }
 
-    pub fn decode<R: BufRead>(reader: &mut R) -> io::Result<RespValue> {
-        let mut first_byte = [0u8; 1];
-        reader.read_exact(&mut first_byte)?;
+    pub async fn decode<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<RespValue> {
+        // Arrays are decoded with an explicit stack of partially filled frames instead of
+        // recursion, so the returned future stays a plain (non-boxed) state machine.
+        let mut stack: Vec<(usize, Vec<RespValue>)> = Vec::new();
+
+        loop {
+            let mut value = match Self::decode_frame(reader).await? {
+                Frame::Value(value) => value,
+                Frame::ArrayHeader(len) if len > 0 => {
+                    stack.push((len, Vec::with_capacity(len.min(1024))));
+                    continue;
+                }
+                Frame::ArrayHeader(_) => RespValue::Array(Vec::new()),
+            };
 
-        match first_byte[0] {
+            // Fold the completed value into its parent frames, closing every array it fills up.
+            loop {
+                match stack.last_mut() {
+                    None => return Ok(value),
+                    Some((remaining, items)) => {
+                        items.push(value);
+                        *remaining -= 1;
+                        if *remaining > 0 {
+                            break;
+                        }
+                    }
+                }
+                let (_, items) = stack.pop().unwrap();
+                value = RespValue::Array(items);
+            }
+        }
+    }
+
+    async fn decode_frame<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Frame> {
+        let first_byte = reader.read_u8().await?;
+
+        match first_byte {
             b'+' => {
-                let mut buf = String::new();
-                reader.read_line(&mut buf)?;
-                Ok(RespValue::SimpleString(buf.trim_end().to_string()))
+                let line = Self::read_line(reader).await?;
+                Ok(Frame::Value(RespValue::SimpleString(String::from_utf8_lossy(&line).to_string())))
             }
             b'-' => {
-                let mut buf = String::new();
-                reader.read_line(&mut buf)?;
-                Ok(RespValue::Error(buf.trim_end().to_string()))
+                let line = Self::read_line(reader).await?;
+                Ok(Frame::Value(RespValue::Error(String::from_utf8_lossy(&line).to_string())))
             }
             b':' => {
-                let mut buf = String::new();
-                reader.read_line(&mut buf)?;
-                let num = buf
-                    .trim_end()
-                    .parse()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                Ok(RespValue::Integer(num))
+                let num = Self::read_number(reader, "invalid integer").await?;
+                Ok(Frame::Value(RespValue::Integer(num)))
             }
             b'$' => {
-                let mut buf = String::new();
-                reader.read_line(&mut buf)?;
-                let len: i64 = buf
-                    .trim_end()
-                    .parse()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let len: i64 = Self::read_number(reader, "invalid bulk length").await?;
                 if len == -1 {
-                    return Ok(RespValue::Null);
+                    return Ok(Frame::Value(RespValue::Null));
                 }
-                let mut bulk = vec![0u8; len as usize];
-                let mut total_read = 0;
-                while total_read < len as usize {
-                    let read = reader.read(&mut bulk[total_read..])?;
-                    if read == 0 {
-                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Unexpected EOF while reading bulk string"));
-                    }
-                    total_read += read;
+                if !(0..=PROTO_MAX_BULK_LEN as i64).contains(&len) {
+                    return Err(protocol_error("invalid bulk length"));
+                }
+                // Grown as the data arrives, so that a large length alone allocates nothing
+                let mut bulk = Vec::new();
+                let read = (&mut *reader).take(len as u64).read_to_end(&mut bulk).await?;
+                if read as i64 != len {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Unexpected EOF while reading bulk string"));
                 }
-                reader.read_exact(&mut [0u8; 2])?; // Read and discard CRLF
-                Ok(RespValue::BinaryBulkString(bulk))
+                reader.read_exact(&mut [0u8; 2]).await?; // Read and discard CRLF
+                Ok(Frame::Value(RespValue::BinaryBulkString(Bytes::from(bulk))))
             }
             b'*' => {
-                let mut buf = String::new();
-                reader.read_line(&mut buf)?;
-                let len: i64 = buf.trim_end().parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                if buf.is_empty() {
-                    return Ok(RespValue::Null);
-                }
+                let len: i64 = Self::read_number(reader, "invalid multibulk length").await?;
                 if len == -1 {
-                    return Ok(RespValue::NullArray);
+                    return Ok(Frame::Value(RespValue::NullArray));
+                }
+                if !(0..=i32::MAX as i64).contains(&len) {
+                    return Err(protocol_error("invalid multibulk length"));
                 }
+                Ok(Frame::ArrayHeader(len as usize))
+            }
+            byte => Err(protocol_error(&format!("expected '*', got '{}'", byte as char))),
+        }
+    }
+
+    /// Reads one CRLF-terminated line and returns it without the line terminator. Lines
+    /// longer than `PROTO_MAX_LINE_LEN` are a protocol error rather than buffered.
+    async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        let read = (&mut *reader).take(PROTO_MAX_LINE_LEN).read_until(b'\n', &mut buf).await?;
+        if buf.last() != Some(&b'\n') && read as u64 == PROTO_MAX_LINE_LEN {
+            return Err(protocol_error("too big line"));
+        }
+        if read == 0 || buf.last() != Some(&b'\n') {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Unexpected EOF while reading line"));
+        }
+        buf.pop();
+        if buf.last() == Some(&b'\r') {
+            buf.pop();
+        }
+        Ok(buf)
+    }
 
+    async fn read_number<R, T>(reader: &mut R, invalid: &str) -> io::Result<T>
+    where
+        R: AsyncBufRead + Unpin,
+        T: std::str::FromStr,
+    {
+        let line = Self::read_line(reader).await?;
+        std::str::from_utf8(&line)
+            .ok()
+            .and_then(|line| line.parse().ok())
+            .ok_or_else(|| protocol_error(invalid))
+    }
+}
 
+enum Frame {
+    Value(RespValue),
+    ArrayHeader(usize),
+}
 
-                let mut array = Vec::with_capacity(len as usize);
-                for _ in 0..len {
-                    array.push(Self::decode(reader)?);
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                Ok(RespValue::Array(array))
-            }
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid RESP data",
-            )),
-        }
+    #[test]
+    fn line_breaks_cannot_split_a_reply() {
+        let error = RespValue::Error("ERR unknown command 'FOO', with args beginning with: 'a\r\n+OK' ".to_string());
+        assert_eq!(
+            RespCodec::encode(&error),
+            b"-ERR unknown command 'FOO', with args beginning with: 'a  +OK' \r\n"
+        );
+        assert_eq!(RespCodec::encode(&RespValue::SimpleString("a\nb".to_string())), b"+a b\r\n");
+        // Bulk strings carry their length, anything goes
+        assert_eq!(RespCodec::encode(&RespValue::BulkString("a\r\nb".to_string())), b"$4\r\na\r\nb\r\n");
     }
 }
@@ -0,0 +1,172 @@
+// referred source code: https://github.com/redis/redis/blob/unstable/src/expire.c
+
+use super::{arg_upper, parse_i64, CommandContext, CommandResult};
+use crate::client::cache_store::now_ms;
+use crate::client::error::CommandError;
+use crate::client::model::RespValue;
+use bytes::Bytes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TimeUnit {
+    Seconds,
+    Milliseconds,
+}
+
+#[derive(Debug, Default)]
+struct ExpireFlags {
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+}
+
+pub fn expire(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    expire_generic(ctx, argv, TimeUnit::Seconds, false, "expire")
+}
+
+pub fn pexpire(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    expire_generic(ctx, argv, TimeUnit::Milliseconds, false, "pexpire")
+}
+
+pub fn expireat(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    expire_generic(ctx, argv, TimeUnit::Seconds, true, "expireat")
+}
+
+pub fn pexpireat(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    expire_generic(ctx, argv, TimeUnit::Milliseconds, true, "pexpireat")
+}
+
+/// Shared implementation of EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT. Every variant is reduced
+/// to an absolute deadline in Unix milliseconds before the NX/XX/GT/LT conditions are checked.
+fn expire_generic(
+    ctx: &mut CommandContext<'_>,
+    argv: &[Bytes],
+    unit: TimeUnit,
+    absolute: bool,
+    name: &str,
+) -> CommandResult {
+    let key = &argv[1];
+    let flags = parse_expire_flags(&argv[3..])?;
+    let invalid = || CommandError::InvalidExpireTime(name.to_string());
+
+    let mut when = parse_i64(&argv[2])?;
+    if unit == TimeUnit::Seconds {
+        when = when.checked_mul(1000).ok_or_else(invalid)?;
+    }
+    if !absolute {
+        when = when.checked_add(now_ms() as i64).ok_or_else(invalid)?;
+    }
+
+    // Nothing changed, and replaying the conditions later could decide otherwise
+    let current = match ctx.store.expires_at(key) {
+        Some(current) => current,
+        None => {
+            ctx.prevent_propagation();
+            return Ok(RespValue::Integer(0).into());
+        }
+    };
+
+    // A key without a TTL counts as an infinite TTL for GT and LT
+    let allowed = match current {
+        None => !flags.xx && !flags.gt,
+        Some(current) => !flags.nx && (!flags.gt || when > current as i64) && (!flags.lt || when < current as i64),
+    };
+    if !allowed {
+        ctx.prevent_propagation();
+        return Ok(RespValue::Integer(0).into());
+    }
+
+    // Propagated with the absolute deadline, a relative TTL would restart when replicas or
+    // the AOF apply it
+    if when <= now_ms() as i64 {
+        // A deadline in the past deletes the key right away
+        ctx.store.remove(key);
+        ctx.rewrite_propagation(vec![Bytes::from_static(b"DEL"), key.clone()]);
+    } else {
+        ctx.store.set_expires_at(key, Some(when as u64));
+        ctx.rewrite_propagation(pexpireat_command(key, when as u64));
+    }
+    Ok(RespValue::Integer(1).into())
+}
+
+fn parse_expire_flags(args: &[Bytes]) -> Result<ExpireFlags, CommandError> {
+    let mut flags = ExpireFlags::default();
+    for arg in args {
+        match arg_upper(arg).as_str() {
+            "NX" => flags.nx = true,
+            "XX" => flags.xx = true,
+            "GT" => flags.gt = true,
+            "LT" => flags.lt = true,
+            _ => {
+                return Err(CommandError::Other(format!(
+                    "Unsupported option {}",
+                    String::from_utf8_lossy(arg)
+                )))
+            }
+        }
+    }
+
+    if flags.nx && (flags.xx || flags.gt || flags.lt) {
+        return Err(CommandError::Other(
+            "NX and XX, GT or LT options at the same time are not compatible".to_string(),
+        ));
+    }
+    if flags.gt && flags.lt {
+        return Err(CommandError::Other(
+            "GT and LT options at the same time are not compatible".to_string(),
+        ));
+    }
+    Ok(flags)
+}
+
+pub fn ttl(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    ttl_generic(ctx, argv, TimeUnit::Seconds, false)
+}
+
+pub fn pttl(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    ttl_generic(ctx, argv, TimeUnit::Milliseconds, false)
+}
+
+pub fn expiretime(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    ttl_generic(ctx, argv, TimeUnit::Seconds, true)
+}
+
+pub fn pexpiretime(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    ttl_generic(ctx, argv, TimeUnit::Milliseconds, true)
+}
+
+/// TTL, PTTL, EXPIRETIME and PEXPIRETIME: -2 for a missing key, -1 for a key without a TTL,
+/// otherwise the remaining time or the absolute deadline in the requested unit.
+fn ttl_generic(ctx: &mut CommandContext<'_>, argv: &[Bytes], unit: TimeUnit, absolute: bool) -> CommandResult {
+    let expires_at = match ctx.store.expires_at(&argv[1]) {
+        None => return Ok(RespValue::Integer(-2).into()),
+        Some(None) => return Ok(RespValue::Integer(-1).into()),
+        Some(Some(expires_at)) => expires_at,
+    };
+
+    let millis = if absolute {
+        expires_at
+    } else {
+        expires_at.saturating_sub(now_ms())
+    };
+    let value = match (unit, absolute) {
+        (TimeUnit::Milliseconds, _) => millis,
+        (TimeUnit::Seconds, true) => millis / 1000,
+        // Remaining seconds are rounded to the nearest second like Redis does
+        (TimeUnit::Seconds, false) => (millis + 500) / 1000,
+    };
+    Ok(RespValue::Integer(value as i64).into())
+}
+
+pub fn persist(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let removed = match ctx.store.expires_at(&argv[1]) {
+        Some(Some(_)) => ctx.store.set_expires_at(&argv[1], None),
+        _ => false,
+    };
+    Ok(RespValue::Integer(removed as i64).into())
+}
+
+/// PEXPIREAT, the form every change of a key deadline is propagated in.
+pub(super) fn pexpireat_command(key: &Bytes, expires_at: u64) -> Vec<Bytes> {
+    vec![Bytes::from_static(b"PEXPIREAT"), key.clone(), Bytes::from(expires_at.to_string())]
+}
@@ -0,0 +1,581 @@
+use super::expire::TimeUnit;
+use super::keyspace::{parse_scan_cursor, parse_scan_options, ScanTarget};
+use super::{arg_upper, format_f64_fixed, ok, parse_f64, parse_i64, CommandContext, CommandResult};
+use crate::client::cache_store::now_ms;
+use crate::client::error::CommandError;
+use crate::client::model::RespValue;
+use crate::client::random::random_below;
+use bytes::Bytes;
+
+fn bulk(value: &Bytes) -> RespValue {
+    RespValue::BinaryBulkString(value.clone())
+}
+
+/// HSET key field value [field value ...]. Replies with the number of fields added.
+pub fn hset(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let added = hset_generic(ctx, argv)?;
+    Ok(RespValue::Integer(added as i64).into())
+}
+
+/// HMSET key field value [field value ...], the HSET of old that replies OK.
+pub fn hmset(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    hset_generic(ctx, argv)?;
+    ok()
+}
+
+fn hset_generic(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> Result<usize, CommandError> {
+    if !argv.len().is_multiple_of(2) {
+        return Err(CommandError::WrongArity(String::from_utf8_lossy(&argv[0]).to_lowercase()));
+    }
+    let hash = ctx.store.hash_or_create(&argv[1])?;
+    Ok(argv[2..]
+        .chunks_exact(2)
+        .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()))
+        .count())
+}
+
+/// HSETNX key field value
+pub fn hsetnx(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    if ctx.store.hash(&argv[1])?.is_some_and(|hash| hash.contains(&argv[2])) {
+        return Ok(RespValue::Integer(0).into());
+    }
+    ctx.store.hash_or_create(&argv[1])?.insert(argv[2].clone(), argv[3].clone());
+    Ok(RespValue::Integer(1).into())
+}
+
+/// HGET key field
+pub fn hget(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let value = ctx.store.hash(&argv[1])?.and_then(|hash| hash.get(&argv[2]));
+    Ok(value.map_or(RespValue::Null, bulk).into())
+}
+
+/// HMGET key field [field ...]
+pub fn hmget(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let hash = ctx.store.hash(&argv[1])?;
+    let values = argv[2..]
+        .iter()
+        .map(|field| hash.and_then(|hash| hash.get(field)).map_or(RespValue::Null, bulk))
+        .collect();
+    Ok(RespValue::Array(values).into())
+}
+
+/// HGETALL key
+pub fn hgetall(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let Some(hash) = ctx.store.hash(&argv[1])? else {
+        return Ok(RespValue::Array(Vec::new()).into());
+    };
+    let reply = hash.iter().flat_map(|(field, value)| [bulk(field), bulk(value)]).collect();
+    Ok(RespValue::Array(reply).into())
+}
+
+/// HKEYS key
+pub fn hkeys(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let Some(hash) = ctx.store.hash(&argv[1])? else {
+        return Ok(RespValue::Array(Vec::new()).into());
+    };
+    Ok(RespValue::Array(hash.iter().map(|(field, _)| bulk(field)).collect()).into())
+}
+
+/// HVALS key
+pub fn hvals(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let Some(hash) = ctx.store.hash(&argv[1])? else {
+        return Ok(RespValue::Array(Vec::new()).into());
+    };
+    Ok(RespValue::Array(hash.iter().map(|(_, value)| bulk(value)).collect()).into())
+}
+
+/// HDEL key field [field ...]. Deleting the last field deletes the key.
+pub fn hdel(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let Some(hash) = ctx.store.hash_mut(key)? else {
+        return Ok(RespValue::Integer(0).into());
+    };
+    let removed = argv[2..].iter().filter(|field| hash.remove(field)).count();
+    ctx.store.remove_if_empty(key);
+    Ok(RespValue::Integer(removed as i64).into())
+}
+
+/// HEXISTS key field
+pub fn hexists(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let exists = ctx.store.hash(&argv[1])?.is_some_and(|hash| hash.contains(&argv[2]));
+    Ok(RespValue::Integer(exists as i64).into())
+}
+
+/// HLEN key
+pub fn hlen(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let length = ctx.store.hash(&argv[1])?.map_or(0, |hash| hash.len());
+    Ok(RespValue::Integer(length as i64).into())
+}
+
+/// HSTRLEN key field
+pub fn hstrlen(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let length = ctx
+        .store
+        .hash(&argv[1])?
+        .and_then(|hash| hash.get(&argv[2]))
+        .map_or(0, Bytes::len);
+    Ok(RespValue::Integer(length as i64).into())
+}
+
+/// HINCRBY key field increment
+pub fn hincrby(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let increment = parse_i64(&argv[3])?;
+    let hash = ctx.store.hash_or_create(&argv[1])?;
+    let current = match hash.get(&argv[2]) {
+        Some(value) => {
+            parse_i64(value).map_err(|_| CommandError::Other("hash value is not an integer".to_string()))?
+        }
+        None => 0,
+    };
+    let updated = current.checked_add(increment).ok_or(CommandError::Overflow)?;
+    // Unlike HSET, incrementing keeps the field's TTL
+    let expires_at = hash.expires_at(&argv[2]);
+    hash.insert(argv[2].clone(), Bytes::from(updated.to_string()));
+    hash.set_expires_at(&argv[2], expires_at);
+    Ok(RespValue::Integer(updated).into())
+}
+
+/// HINCRBYFLOAT key field increment. Propagated as the HSET of the result, so replicas
+/// never redo the floating point math.
+pub fn hincrbyfloat(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let increment = parse_f64(&argv[3])?;
+    let hash = ctx.store.hash_or_create(&argv[1])?;
+    let current = match hash.get(&argv[2]) {
+        Some(value) => parse_f64(value).map_err(|_| CommandError::Other("hash value is not a float".to_string()))?,
+        None => 0.0,
+    };
+    let updated = current + increment;
+    if !updated.is_finite() {
+        return Err(CommandError::Other("increment would produce NaN or Infinity".to_string()));
+    }
+    let formatted = Bytes::from(format_f64_fixed(updated));
+    let expires_at = hash.expires_at(&argv[2]);
+    hash.insert(argv[2].clone(), formatted.clone());
+    hash.set_expires_at(&argv[2], expires_at);
+    ctx.rewrite_propagation(vec![
+        Bytes::from_static(b"HSET"),
+        argv[1].clone(),
+        argv[2].clone(),
+        formatted.clone(),
+    ]);
+    // The HSET clears the TTL on the replica, so it is restored right after
+    if let Some(expires_at) = expires_at {
+        ctx.rewrite_propagation(hpexpireat_command(&argv[1], expires_at, vec![argv[2].clone()]));
+    }
+    Ok(RespValue::BinaryBulkString(formatted).into())
+}
+
+/// HRANDFIELD key [count [WITHVALUES]]. A positive count returns that many distinct fields
+/// at most, a negative one exactly that many, possibly repeating fields.
+pub fn hrandfield(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let with_values = match argv.len() {
+        2 | 3 => false,
+        4 if arg_upper(&argv[3]) == "WITHVALUES" => true,
+        _ => return Err(CommandError::Syntax),
+    };
+    let Some(count) = argv.get(2) else {
+        let field = ctx.store.hash(&argv[1])?.and_then(|hash| hash.random_entry());
+        return Ok(field.map_or(RespValue::Null, |(field, _)| bulk(field)).into());
+    };
+    let count = parse_i64(count)?;
+    // Every returned field takes two slots with WITHVALUES
+    if with_values && count.unsigned_abs() > i64::MAX as u64 / 2 {
+        return Err(CommandError::Other("value is out of range".to_string()));
+    }
+    let Some(hash) = ctx.store.hash(&argv[1])? else {
+        return Ok(RespValue::Array(Vec::new()).into());
+    };
+
+    let entries: Vec<(&Bytes, &Bytes)> = if count < 0 {
+        (0..count.unsigned_abs())
+            .filter_map(|_| hash.random_entry())
+            .collect()
+    } else if count as usize >= hash.len() {
+        hash.iter().collect()
+    } else {
+        // Partial Fisher-Yates shuffle: the first `count` slots end up a uniform sample
+        let mut entries: Vec<_> = hash.iter().collect();
+        let count = count as usize;
+        for i in 0..count {
+            let j = i + random_below(entries.len() - i);
+            entries.swap(i, j);
+        }
+        entries.truncate(count);
+        entries
+    };
+
+    let reply = entries
+        .into_iter()
+        .flat_map(|(field, value)| {
+            let value = with_values.then(|| bulk(value));
+            std::iter::once(bulk(field)).chain(value)
+        })
+        .collect();
+    Ok(RespValue::Array(reply).into())
+}
+
+/// HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]
+pub fn hscan(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let mut cursor = parse_scan_cursor(&argv[2])?;
+    let options = parse_scan_options(&argv[3..], ScanTarget::Hash)?;
+    let Some(hash) = ctx.store.hash(&argv[1])? else {
+        return Ok(RespValue::Array(vec![
+            RespValue::BulkString("0".to_string()),
+            RespValue::Array(Vec::new()),
+        ])
+        .into());
+    };
+
+    let mut elements = Vec::new();
+    let mut visited = 0;
+    let mut max_iterations = options.max_iterations();
+    loop {
+        cursor = hash.scan(cursor, |field, value| {
+            visited += 1;
+            if options.matches(field) {
+                elements.push(bulk(field));
+                if !options.no_values {
+                    elements.push(bulk(value));
+                }
+            }
+        });
+        max_iterations -= 1;
+        if cursor == 0 || max_iterations == 0 || visited >= options.count {
+            break;
+        }
+    }
+
+    Ok(RespValue::Array(vec![
+        RespValue::BulkString(cursor.to_string()),
+        RespValue::Array(elements),
+    ])
+    .into())
+}
+
+// Largest field deadline in Unix milliseconds, Redis' EB_EXPIRE_TIME_MAX
+const MAX_FIELD_EXPIRE_TIME: i64 = (1 << 48) - 1;
+
+// Per-field replies of the field TTL commands
+const NO_SUCH_FIELD: i64 = -2;
+const NO_FIELD_TTL: i64 = -1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldExpireCondition {
+    Always,
+    Nx,
+    Xx,
+    Gt,
+    Lt,
+}
+
+/// Parses `FIELDS numfields field [field ...]`, which must run to the end of the arguments.
+fn parse_fields(args: &[Bytes]) -> Result<&[Bytes], CommandError> {
+    if args.len() < 2 || arg_upper(&args[0]) != "FIELDS" {
+        return Err(CommandError::Other(
+            "Mandatory argument FIELDS is missing or not at the right position".to_string(),
+        ));
+    }
+    let numfields = parse_i64(&args[1])
+        .ok()
+        .filter(|&numfields| numfields > 0)
+        .ok_or_else(|| CommandError::Other("Parameter `numFields` should be greater than 0".to_string()))?;
+    if numfields as usize != args.len() - 2 {
+        return Err(CommandError::Other(
+            "The `numfields` parameter must match the number of arguments".to_string(),
+        ));
+    }
+    Ok(&args[2..])
+}
+
+fn hpexpireat_command(key: &Bytes, expires_at: u64, fields: Vec<Bytes>) -> Vec<Bytes> {
+    let header = [
+        Bytes::from_static(b"HPEXPIREAT"),
+        key.clone(),
+        Bytes::from(expires_at.to_string()),
+        Bytes::from_static(b"FIELDS"),
+        Bytes::from(fields.len().to_string()),
+    ];
+    header.into_iter().chain(fields).collect()
+}
+
+fn hpersist_command(key: &Bytes, fields: Vec<Bytes>) -> Vec<Bytes> {
+    let header = [
+        Bytes::from_static(b"HPERSIST"),
+        key.clone(),
+        Bytes::from_static(b"FIELDS"),
+        Bytes::from(fields.len().to_string()),
+    ];
+    header.into_iter().chain(fields).collect()
+}
+
+fn hdel_command(key: &Bytes, fields: Vec<Bytes>) -> Vec<Bytes> {
+    [Bytes::from_static(b"HDEL"), key.clone()].into_iter().chain(fields).collect()
+}
+
+fn integer_array(values: Vec<i64>) -> RespValue {
+    RespValue::Array(values.into_iter().map(RespValue::Integer).collect())
+}
+
+pub fn hexpire(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    hexpire_generic(ctx, argv, TimeUnit::Seconds, false, "hexpire")
+}
+
+pub fn hpexpire(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    hexpire_generic(ctx, argv, TimeUnit::Milliseconds, false, "hpexpire")
+}
+
+pub fn hexpireat(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    hexpire_generic(ctx, argv, TimeUnit::Seconds, true, "hexpireat")
+}
+
+pub fn hpexpireat(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    hexpire_generic(ctx, argv, TimeUnit::Milliseconds, true, "hpexpireat")
+}
+
+/// Shared implementation of HEXPIRE, HPEXPIRE, HEXPIREAT and HPEXPIREAT:
+/// `key time [NX | XX | GT | LT] FIELDS numfields field [field ...]`. Replies per field with
+/// -2 (no such field), 0 (condition not met), 1 (deadline set) or 2 (deleted right away as
+/// the deadline already passed). Propagated as HPEXPIREAT and HDEL of the fields that changed.
+fn hexpire_generic(
+    ctx: &mut CommandContext<'_>,
+    argv: &[Bytes],
+    unit: TimeUnit,
+    absolute: bool,
+    name: &str,
+) -> CommandResult {
+    let key = &argv[1];
+    let invalid = || CommandError::InvalidExpireTime(name.to_string());
+
+    let mut when = parse_i64(&argv[2])?;
+    if when < 0 {
+        return Err(CommandError::Other("invalid expire time, must be >= 0".to_string()));
+    }
+    if unit == TimeUnit::Seconds {
+        if when > MAX_FIELD_EXPIRE_TIME / 1000 {
+            return Err(invalid());
+        }
+        when *= 1000;
+    }
+    if !absolute {
+        when += now_ms() as i64;
+    }
+    if when > MAX_FIELD_EXPIRE_TIME {
+        return Err(invalid());
+    }
+    let when = when as u64;
+
+    let (condition, rest) = match arg_upper(&argv[3]).as_str() {
+        "NX" => (FieldExpireCondition::Nx, &argv[4..]),
+        "XX" => (FieldExpireCondition::Xx, &argv[4..]),
+        "GT" => (FieldExpireCondition::Gt, &argv[4..]),
+        "LT" => (FieldExpireCondition::Lt, &argv[4..]),
+        _ => (FieldExpireCondition::Always, &argv[3..]),
+    };
+    let fields = parse_fields(rest)?;
+
+    let now = now_ms();
+    let mut replies = Vec::with_capacity(fields.len());
+    let mut updated = Vec::new();
+    let mut deleted = Vec::new();
+    for field in fields {
+        let Some(hash) = ctx.store.hash(key)? else {
+            replies.push(NO_SUCH_FIELD);
+            continue;
+        };
+        if !hash.contains(field) {
+            replies.push(NO_SUCH_FIELD);
+            continue;
+        }
+        // A field without a TTL counts as an infinite TTL for GT and LT
+        let current = hash.expires_at(field);
+        let allowed = match condition {
+            FieldExpireCondition::Always => true,
+            FieldExpireCondition::Nx => current.is_none(),
+            FieldExpireCondition::Xx => current.is_some(),
+            FieldExpireCondition::Gt => current.is_some_and(|current| when > current),
+            FieldExpireCondition::Lt => current.is_none_or(|current| when < current),
+        };
+        if !allowed {
+            replies.push(0);
+        } else if when <= now {
+            if let Some(hash) = ctx.store.hash_mut(key)? {
+                hash.remove(field);
+            }
+            deleted.push(field.clone());
+            replies.push(2);
+        } else {
+            ctx.store.set_hash_field_expires_at(key, field, Some(when))?;
+            updated.push(field.clone());
+            replies.push(1);
+        }
+    }
+    ctx.store.remove_if_empty(key);
+
+    if updated.is_empty() && deleted.is_empty() {
+        ctx.prevent_propagation();
+    }
+    if !updated.is_empty() {
+        ctx.rewrite_propagation(hpexpireat_command(key, when, updated));
+    }
+    if !deleted.is_empty() {
+        ctx.rewrite_propagation(hdel_command(key, deleted));
+    }
+    Ok(integer_array(replies).into())
+}
+
+pub fn httl(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    httl_generic(ctx, argv, TimeUnit::Seconds, false)
+}
+
+pub fn hpttl(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    httl_generic(ctx, argv, TimeUnit::Milliseconds, false)
+}
+
+pub fn hexpiretime(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    httl_generic(ctx, argv, TimeUnit::Seconds, true)
+}
+
+pub fn hpexpiretime(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    httl_generic(ctx, argv, TimeUnit::Milliseconds, true)
+}
+
+/// Shared implementation of HTTL, HPTTL, HEXPIRETIME and HPEXPIRETIME:
+/// `key FIELDS numfields field [field ...]`. Replies per field with -2 (no such field), -1
+/// (no TTL), or the remaining time or deadline.
+fn httl_generic(ctx: &mut CommandContext<'_>, argv: &[Bytes], unit: TimeUnit, absolute: bool) -> CommandResult {
+    let fields = parse_fields(&argv[2..])?;
+    let hash = ctx.store.hash(&argv[1])?;
+    let base = if absolute { 0 } else { now_ms() };
+
+    let replies = fields
+        .iter()
+        .map(|field| match hash {
+            Some(hash) if hash.contains(field) => match hash.expires_at(field) {
+                None => NO_FIELD_TTL,
+                Some(expires_at) => {
+                    let millis = expires_at.saturating_sub(base);
+                    match unit {
+                        TimeUnit::Milliseconds => millis as i64,
+                        // Rounded up, a field never reports 0 seconds while it still exists
+                        TimeUnit::Seconds => millis.div_ceil(1000) as i64,
+                    }
+                }
+            },
+            _ => NO_SUCH_FIELD,
+        })
+        .collect();
+    Ok(integer_array(replies).into())
+}
+
+/// HPERSIST key FIELDS numfields field [field ...]. Replies per field with -2 (no such
+/// field), -1 (no TTL to remove) or 1 (TTL removed).
+pub fn hpersist(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let fields = parse_fields(&argv[2..])?;
+
+    let mut replies = Vec::with_capacity(fields.len());
+    let mut persisted = Vec::new();
+    for field in fields {
+        let ttl = ctx
+            .store
+            .hash(key)?
+            .and_then(|hash| hash.contains(field).then(|| hash.expires_at(field)));
+        match ttl {
+            None => replies.push(NO_SUCH_FIELD),
+            Some(None) => replies.push(NO_FIELD_TTL),
+            Some(Some(_)) => {
+                ctx.store.set_hash_field_expires_at(key, field, None)?;
+                persisted.push(field.clone());
+                replies.push(1);
+            }
+        }
+    }
+
+    if persisted.is_empty() {
+        ctx.prevent_propagation();
+    } else {
+        ctx.rewrite_propagation(hpersist_command(key, persisted));
+    }
+    Ok(integer_array(replies).into())
+}
+
+/// HGETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | PERSIST] FIELDS numfields field [field ...]
+///
+/// Replies with the values like HMGET, then updates the TTL of the fields that exist. A
+/// deadline in the past deletes them.
+pub fn hgetex(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let invalid = || CommandError::InvalidExpireTime("hgetex".to_string());
+
+    // None leaves the TTLs alone, Some(None) is PERSIST
+    let option = arg_upper(&argv[2]);
+    let (expiry, rest) = match option.as_str() {
+        "EX" | "PX" | "EXAT" | "PXAT" => {
+            let value = parse_i64(argv.get(3).ok_or(CommandError::Syntax)?)?;
+            if value <= 0 {
+                return Err(invalid());
+            }
+            let mut when = value;
+            if matches!(option.as_str(), "EX" | "EXAT") {
+                when = when.checked_mul(1000).ok_or_else(invalid)?;
+            }
+            if matches!(option.as_str(), "EX" | "PX") {
+                when = when.checked_add(now_ms() as i64).ok_or_else(invalid)?;
+            }
+            if when > MAX_FIELD_EXPIRE_TIME {
+                return Err(invalid());
+            }
+            (Some(Some(when as u64)), &argv[4..])
+        }
+        "PERSIST" => (Some(None), &argv[3..]),
+        _ => (None, &argv[2..]),
+    };
+    let fields = parse_fields(rest)?;
+
+    let hash = ctx.store.hash(key)?;
+    let values = fields
+        .iter()
+        .map(|field| hash.and_then(|hash| hash.get(field)).map_or(RespValue::Null, bulk))
+        .collect();
+    let existing: Vec<Bytes> = fields
+        .iter()
+        .filter(|field| hash.is_some_and(|hash| hash.contains(field)))
+        .cloned()
+        .collect();
+
+    match expiry {
+        None => ctx.prevent_propagation(),
+        Some(None) => {
+            let mut persisted = Vec::new();
+            for field in existing {
+                if ctx.store.hash(key)?.and_then(|hash| hash.expires_at(&field)).is_some() {
+                    ctx.store.set_hash_field_expires_at(key, &field, None)?;
+                    persisted.push(field);
+                }
+            }
+            if persisted.is_empty() {
+                ctx.prevent_propagation();
+            } else {
+                ctx.rewrite_propagation(hpersist_command(key, persisted));
+            }
+        }
+        Some(Some(_)) if existing.is_empty() => ctx.prevent_propagation(),
+        Some(Some(when)) if when <= now_ms() => {
+            if let Some(hash) = ctx.store.hash_mut(key)? {
+                existing.iter().for_each(|field| {
+                    hash.remove(field);
+                });
+            }
+            ctx.store.remove_if_empty(key);
+            ctx.rewrite_propagation(hdel_command(key, existing));
+        }
+        Some(Some(when)) => {
+            for field in &existing {
+                ctx.store.set_hash_field_expires_at(key, field, Some(when))?;
+            }
+            ctx.rewrite_propagation(hpexpireat_command(key, when, existing));
+        }
+    }
+    Ok(RespValue::Array(values).into())
+}
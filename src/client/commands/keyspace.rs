@@ -0,0 +1,230 @@
+// Generic commands that work on keys regardless of their value type.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/db.c
+
+use super::{arg_upper, ok, parse_i64, CommandContext, CommandResult};
+use crate::client::error::CommandError;
+use crate::client::glob::glob_match;
+use crate::client::model::RespValue;
+use bytes::Bytes;
+
+/// DEL key [key ...]
+pub fn del(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let removed = argv[1..].iter().filter(|key| ctx.store.remove(key)).count();
+    Ok(RespValue::Integer(removed as i64).into())
+}
+
+/// UNLINK key [key ...]. Values are cheap to drop so far, so this is DEL under another name.
+pub fn unlink(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    del(ctx, argv)
+}
+
+/// EXISTS key [key ...]. A key mentioned several times is counted several times.
+pub fn exists(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let count = argv[1..].iter().filter(|key| ctx.store.contains_key(key)).count();
+    Ok(RespValue::Integer(count as i64).into())
+}
+
+/// TOUCH key [key ...]. We keep no access times, so this only counts the existing keys.
+pub fn touch(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    exists(ctx, argv)
+}
+
+pub fn type_command(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let type_name = ctx.store.value(&argv[1]).map_or("none", |value| value.type_name());
+    Ok(RespValue::SimpleString(type_name.to_string()).into())
+}
+
+/// RENAME key newkey. The value keeps its TTL and replaces whatever `newkey` held.
+pub fn rename(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let (source, destination) = (&argv[1], &argv[2]);
+    if !ctx.store.contains_key(source) {
+        return Err(CommandError::NoSuchKey);
+    }
+    if source != destination {
+        let (value, expires_at) = ctx.store.take_entry(source).ok_or(CommandError::NoSuchKey)?;
+        ctx.store.insert_entry(destination.clone(), value, expires_at);
+    }
+    ok()
+}
+
+/// RENAMENX key newkey
+pub fn renamenx(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let (source, destination) = (&argv[1], &argv[2]);
+    if !ctx.store.contains_key(source) {
+        return Err(CommandError::NoSuchKey);
+    }
+    if source == destination || ctx.store.contains_key(destination) {
+        return Ok(RespValue::Integer(0).into());
+    }
+    let (value, expires_at) = ctx.store.take_entry(source).ok_or(CommandError::NoSuchKey)?;
+    ctx.store.insert_entry(destination.clone(), value, expires_at);
+    Ok(RespValue::Integer(1).into())
+}
+
+/// COPY source destination [DB destination-db] [REPLACE]
+pub fn copy(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let (source, destination) = (&argv[1], &argv[2]);
+    let mut replace = false;
+
+    let mut i = 3;
+    while i < argv.len() {
+        match arg_upper(&argv[i]).as_str() {
+            "REPLACE" => replace = true,
+            "DB" if i + 1 < argv.len() => {
+                // Only database 0 exists
+                if parse_i64(&argv[i + 1])? != 0 {
+                    return Err(CommandError::Other("DB index is out of range".to_string()));
+                }
+                i += 1;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+
+    if source == destination {
+        return Err(CommandError::Other("source and destination objects are the same".to_string()));
+    }
+    let (value, expires_at) = match ctx.store.clone_entry(source) {
+        Some(entry) => entry,
+        None => return Ok(RespValue::Integer(0).into()),
+    };
+    if !replace && ctx.store.contains_key(destination) {
+        return Ok(RespValue::Integer(0).into());
+    }
+    ctx.store.insert_entry(destination.clone(), value, expires_at);
+    Ok(RespValue::Integer(1).into())
+}
+
+pub fn randomkey(ctx: &mut CommandContext<'_>, _argv: &[Bytes]) -> CommandResult {
+    Ok(ctx.store.random_key().map_or(RespValue::Null, RespValue::BinaryBulkString).into())
+}
+
+/// KEYS pattern
+pub fn keys(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let pattern = &argv[1];
+    let match_all = pattern.as_ref() == b"*";
+    let keys = ctx
+        .store
+        .keys()
+        .into_iter()
+        .filter(|key| match_all || glob_match(pattern, key))
+        .map(RespValue::BinaryBulkString)
+        .collect();
+    Ok(RespValue::Array(keys).into())
+}
+
+/// Options shared by SCAN, HSCAN, SSCAN and ZSCAN.
+pub(super) struct ScanOptions {
+    pub pattern: Option<Bytes>,
+    pub count: usize,
+    pub type_name: Option<String>,
+    pub no_values: bool,
+}
+
+/// What a SCAN family command iterates, which decides the options it accepts on top of
+/// MATCH and COUNT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ScanTarget {
+    Keyspace, // TYPE
+    Hash,     // NOVALUES
+    Set,
+}
+
+impl ScanOptions {
+    pub fn matches(&self, element: &[u8]) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.as_ref() == b"*" || glob_match(pattern, element))
+    }
+
+    /// How many table buckets one call may visit, which bounds the work done on sparse
+    /// tables where most buckets are empty. COUNT can be as large as `i64::MAX`.
+    pub fn max_iterations(&self) -> usize {
+        self.count.saturating_mul(10)
+    }
+}
+
+pub(super) fn parse_scan_cursor(arg: &[u8]) -> Result<u64, CommandError> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| CommandError::Other("invalid cursor".to_string()))
+}
+
+/// Parses `[MATCH pattern] [COUNT count]` and the options specific to `target`.
+pub(super) fn parse_scan_options(args: &[Bytes], target: ScanTarget) -> Result<ScanOptions, CommandError> {
+    let mut options = ScanOptions {
+        pattern: None,
+        count: 10,
+        type_name: None,
+        no_values: false,
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        // The only option without an argument
+        if target == ScanTarget::Hash && arg_upper(&args[i]) == "NOVALUES" {
+            options.no_values = true;
+            i += 1;
+            continue;
+        }
+        if i + 1 >= args.len() {
+            return Err(CommandError::Syntax);
+        }
+        match arg_upper(&args[i]).as_str() {
+            "MATCH" => options.pattern = Some(args[i + 1].clone()),
+            "COUNT" => {
+                let count = parse_i64(&args[i + 1])?;
+                if count < 1 {
+                    return Err(CommandError::Syntax);
+                }
+                options.count = count as usize;
+            }
+            "TYPE" if target == ScanTarget::Keyspace => {
+                let type_name = String::from_utf8_lossy(&args[i + 1]).to_lowercase();
+                if !VALUE_TYPES.contains(&type_name.as_str()) {
+                    return Err(CommandError::Other(format!("unknown type name '{}'", type_name)));
+                }
+                options.type_name = Some(type_name);
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 2;
+    }
+    Ok(options)
+}
+
+// Type names accepted by SCAN TYPE, as reported by TYPE
+const VALUE_TYPES: &[&str] = &["string", "list", "set", "zset", "hash", "stream"];
+
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+///
+/// The cursor walks the keyspace table in reverse-binary bucket order, so every key that
+/// exists from the first to the last call is returned even if the table is resized in
+/// between. Keys may be returned more than once; MATCH and TYPE are applied after the keys
+/// are collected, so a call can return fewer than COUNT keys or none at all.
+pub fn scan(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let mut cursor = parse_scan_cursor(&argv[1])?;
+    let options = parse_scan_options(&argv[2..], ScanTarget::Keyspace)?;
+
+    let mut keys = Vec::new();
+    let mut max_iterations = options.max_iterations();
+    loop {
+        cursor = ctx.store.scan(cursor, |key, value| {
+            if options.type_name.as_deref().is_none_or(|t| t == value.type_name()) && options.matches(key) {
+                keys.push(RespValue::BinaryBulkString(key.clone()));
+            }
+        });
+        max_iterations -= 1;
+        if cursor == 0 || max_iterations == 0 || keys.len() >= options.count {
+            break;
+        }
+    }
+
+    Ok(RespValue::Array(vec![
+        RespValue::BulkString(cursor.to_string()),
+        RespValue::Array(keys),
+    ])
+    .into())
+}
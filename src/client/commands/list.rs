@@ -0,0 +1,510 @@
+use super::{arg_upper, ok, parse_i64, parse_timeout, BlockOn, CommandContext, CommandResponse, CommandResult};
+use crate::client::error::CommandError;
+use crate::client::model::RespValue;
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// The side of a list an element is pushed to or popped from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum End {
+    Left,
+    Right,
+}
+
+impl End {
+    fn parse(arg: &[u8]) -> Result<End, CommandError> {
+        match arg_upper(arg).as_str() {
+            "LEFT" => Ok(End::Left),
+            "RIGHT" => Ok(End::Right),
+            _ => Err(CommandError::Syntax),
+        }
+    }
+
+    fn keyword(self) -> Bytes {
+        Bytes::from_static(match self {
+            End::Left => b"LEFT",
+            End::Right => b"RIGHT",
+        })
+    }
+
+    /// The plain pop command popping from this end.
+    fn pop_command(self) -> Bytes {
+        Bytes::from_static(match self {
+            End::Left => b"LPOP",
+            End::Right => b"RPOP",
+        })
+    }
+}
+
+fn push(list: &mut VecDeque<Bytes>, end: End, element: Bytes) {
+    match end {
+        End::Left => list.push_front(element),
+        End::Right => list.push_back(element),
+    }
+}
+
+fn pop(list: &mut VecDeque<Bytes>, end: End) -> Option<Bytes> {
+    match end {
+        End::Left => list.pop_front(),
+        End::Right => list.pop_back(),
+    }
+}
+
+fn pop_many(list: &mut VecDeque<Bytes>, end: End, count: usize) -> Vec<Bytes> {
+    let count = count.min(list.len());
+    match end {
+        End::Left => list.drain(..count).collect(),
+        // Elements come back in pop order, so the tail is reversed
+        End::Right => list.drain(list.len() - count..).rev().collect(),
+    }
+}
+
+fn bulk_array(elements: Vec<Bytes>) -> RespValue {
+    RespValue::Array(elements.into_iter().map(RespValue::BinaryBulkString).collect())
+}
+
+/// Resolves LRANGE/LTRIM style indexes (negative ones count from the tail) to an inclusive
+/// range, or None if the range is empty.
+fn normalize_range(start: i64, end: i64, length: usize) -> Option<(usize, usize)> {
+    let length = length as i64;
+    let start = if start < 0 { (start + length).max(0) } else { start };
+    let end = if end < 0 { end + length } else { end };
+    if start > end || start >= length {
+        return None;
+    }
+    Some((start as usize, end.min(length - 1) as usize))
+}
+
+/// LPUSH key element [element ...]
+pub fn lpush(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    push_generic(ctx, argv, End::Left, false)
+}
+
+/// RPUSH key element [element ...]
+pub fn rpush(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    push_generic(ctx, argv, End::Right, false)
+}
+
+/// LPUSHX key element [element ...]. Only pushes onto an existing list.
+pub fn lpushx(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    push_generic(ctx, argv, End::Left, true)
+}
+
+/// RPUSHX key element [element ...]. Only pushes onto an existing list.
+pub fn rpushx(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    push_generic(ctx, argv, End::Right, true)
+}
+
+fn push_generic(ctx: &mut CommandContext<'_>, argv: &[Bytes], end: End, only_existing: bool) -> CommandResult {
+    let key = &argv[1];
+    let list = if only_existing {
+        match ctx.store.list_mut(key)? {
+            Some(list) => list,
+            None => return Ok(RespValue::Integer(0).into()),
+        }
+    } else {
+        ctx.store.list_or_create(key)?
+    };
+    for element in &argv[2..] {
+        push(list, end, element.clone());
+    }
+    Ok(RespValue::Integer(list.len() as i64).into())
+}
+
+/// LPOP key [count]
+pub fn lpop(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    pop_generic(ctx, argv, End::Left)
+}
+
+/// RPOP key [count]
+pub fn rpop(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    pop_generic(ctx, argv, End::Right)
+}
+
+fn pop_generic(ctx: &mut CommandContext<'_>, argv: &[Bytes], end: End) -> CommandResult {
+    if argv.len() > 3 {
+        return Err(CommandError::WrongArity(String::from_utf8_lossy(&argv[0]).to_lowercase()));
+    }
+    let count = match argv.get(2) {
+        Some(arg) => {
+            let count = parse_i64(arg)?;
+            if count < 0 {
+                return Err(CommandError::Other("value is out of range, must be positive".to_string()));
+            }
+            Some(count as usize)
+        }
+        None => None,
+    };
+
+    let key = &argv[1];
+    let Some(list) = ctx.store.list_mut(key)? else {
+        // With a count the reply is an array, so a missing key is a null array
+        return Ok(count.map_or(RespValue::Null, |_| RespValue::NullArray).into());
+    };
+    let reply = match count {
+        Some(count) => bulk_array(pop_many(list, end, count)),
+        None => pop(list, end).map_or(RespValue::Null, RespValue::BinaryBulkString),
+    };
+    ctx.store.remove_if_empty(key);
+    Ok(reply.into())
+}
+
+/// LMPOP numkeys key [key ...] LEFT | RIGHT [COUNT count]. Pops from the first non-empty
+/// list and replies with its name and the popped elements.
+pub fn lmpop(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let (keys, end, count) = parse_mpop_args(&argv[1..])?;
+    Ok(mpop(ctx, keys, end, count)?.unwrap_or(RespValue::NullArray).into())
+}
+
+/// BLMPOP timeout numkeys key [key ...] LEFT | RIGHT [COUNT count], the blocking LMPOP.
+pub fn blmpop(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let deadline = parse_timeout(&argv[1])?;
+    let (keys, end, count) = parse_mpop_args(&argv[2..])?;
+    match mpop(ctx, keys, end, count)? {
+        Some(reply) => Ok(reply.into()),
+        None => Ok(block_on(keys.to_vec(), deadline, RespValue::NullArray)),
+    }
+}
+
+/// Pops up to `count` elements from the first non-empty list of `keys`, replying with its
+/// name and the popped elements. Propagates as the equivalent LPOP or RPOP with a count.
+fn mpop(ctx: &mut CommandContext<'_>, keys: &[Bytes], end: End, count: usize) -> Result<Option<RespValue>, CommandError> {
+    for key in keys {
+        let Some(list) = ctx.store.list_mut(key)? else {
+            continue;
+        };
+        let elements = pop_many(list, end, count);
+        ctx.store.remove_if_empty(key);
+        ctx.rewrite_propagation(vec![end.pop_command(), key.clone(), Bytes::from(elements.len().to_string())]);
+        return Ok(Some(RespValue::Array(vec![
+            RespValue::BinaryBulkString(key.clone()),
+            bulk_array(elements),
+        ])));
+    }
+    Ok(None)
+}
+
+fn block_on(keys: Vec<Bytes>, deadline: Option<Instant>, timeout_reply: RespValue) -> CommandResponse {
+    CommandResponse::Block(BlockOn {
+        keys,
+        value_type: "list",
+        deadline,
+        timeout_reply,
+        retry_argv: None,
+    })
+}
+
+/// Parses `numkeys key [key ...] LEFT | RIGHT [COUNT count]`.
+fn parse_mpop_args(args: &[Bytes]) -> Result<(&[Bytes], End, usize), CommandError> {
+    let numkeys = parse_i64(&args[0])
+        .ok()
+        .filter(|&numkeys| numkeys > 0)
+        .ok_or_else(|| CommandError::Other("numkeys should be greater than 0".to_string()))?;
+    let numkeys = numkeys as usize;
+    // The keys must be followed by at least the direction
+    if numkeys >= args.len() - 1 {
+        return Err(CommandError::Syntax);
+    }
+    let keys = &args[1..=numkeys];
+    let end = End::parse(&args[numkeys + 1])?;
+
+    let mut count = None;
+    let mut i = numkeys + 2;
+    while i < args.len() {
+        match arg_upper(&args[i]).as_str() {
+            "COUNT" if count.is_none() && i + 1 < args.len() => {
+                let value = parse_i64(&args[i + 1])
+                    .ok()
+                    .filter(|&count| count > 0)
+                    .ok_or_else(|| CommandError::Other("count should be greater than 0".to_string()))?;
+                count = Some(value as usize);
+                i += 2;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+    Ok((keys, end, count.unwrap_or(1)))
+}
+
+/// BLPOP key [key ...] timeout. Pops the head of the first non-empty list, or waits for
+/// one of them to receive an element.
+pub fn blpop(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    blocking_pop_generic(ctx, argv, End::Left)
+}
+
+/// BRPOP key [key ...] timeout
+pub fn brpop(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    blocking_pop_generic(ctx, argv, End::Right)
+}
+
+fn blocking_pop_generic(ctx: &mut CommandContext<'_>, argv: &[Bytes], end: End) -> CommandResult {
+    let deadline = parse_timeout(&argv[argv.len() - 1])?;
+    let keys = &argv[1..argv.len() - 1];
+    for key in keys {
+        let Some(element) = ctx.store.list_mut(key)?.and_then(|list| pop(list, end)) else {
+            continue;
+        };
+        ctx.store.remove_if_empty(key);
+        ctx.rewrite_propagation(vec![end.pop_command(), key.clone()]);
+        return Ok(RespValue::Array(vec![
+            RespValue::BinaryBulkString(key.clone()),
+            RespValue::BinaryBulkString(element),
+        ])
+        .into());
+    }
+    Ok(block_on(keys.to_vec(), deadline, RespValue::NullArray))
+}
+
+/// LMOVE source destination LEFT | RIGHT LEFT | RIGHT
+pub fn lmove(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let from = End::parse(&argv[3])?;
+    let to = End::parse(&argv[4])?;
+    lmove_generic(ctx, &argv[1], &argv[2], from, to)
+}
+
+/// RPOPLPUSH source destination, the LMOVE source destination RIGHT LEFT of old.
+pub fn rpoplpush(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    lmove_generic(ctx, &argv[1], &argv[2], End::Right, End::Left)
+}
+
+/// BLMOVE source destination LEFT | RIGHT LEFT | RIGHT timeout
+pub fn blmove(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let from = End::parse(&argv[3])?;
+    let to = End::parse(&argv[4])?;
+    let deadline = parse_timeout(&argv[5])?;
+    blocking_lmove_generic(ctx, &argv[1], &argv[2], from, to, deadline)
+}
+
+/// BRPOPLPUSH source destination timeout
+pub fn brpoplpush(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let deadline = parse_timeout(&argv[3])?;
+    blocking_lmove_generic(ctx, &argv[1], &argv[2], End::Right, End::Left, deadline)
+}
+
+fn blocking_lmove_generic(
+    ctx: &mut CommandContext<'_>,
+    source: &Bytes,
+    destination: &Bytes,
+    from: End,
+    to: End,
+    deadline: Option<Instant>,
+) -> CommandResult {
+    if ctx.store.list(source)?.is_none() {
+        return Ok(block_on(vec![source.clone()], deadline, RespValue::Null));
+    }
+    let response = lmove_generic(ctx, source, destination, from, to)?;
+    ctx.rewrite_propagation(vec![
+        Bytes::from_static(b"LMOVE"),
+        source.clone(),
+        destination.clone(),
+        from.keyword(),
+        to.keyword(),
+    ]);
+    Ok(response)
+}
+
+fn lmove_generic(ctx: &mut CommandContext<'_>, source: &Bytes, destination: &Bytes, from: End, to: End) -> CommandResult {
+    if ctx.store.list(source)?.is_none() {
+        return Ok(RespValue::Null.into());
+    }
+    // Nothing may move if the destination turns out to be of the wrong type
+    ctx.store.list(destination)?;
+
+    let Some(element) = ctx.store.list_mut(source)?.and_then(|list| pop(list, from)) else {
+        return Ok(RespValue::Null.into());
+    };
+    push(ctx.store.list_or_create(destination)?, to, element.clone());
+    // Checked only after the push so rotating a single element list keeps the key and its TTL
+    ctx.store.remove_if_empty(source);
+    Ok(RespValue::BinaryBulkString(element).into())
+}
+
+/// LLEN key
+pub fn llen(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let length = ctx.store.list(&argv[1])?.map_or(0, VecDeque::len);
+    Ok(RespValue::Integer(length as i64).into())
+}
+
+/// LRANGE key start stop
+pub fn lrange(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let start = parse_i64(&argv[2])?;
+    let end = parse_i64(&argv[3])?;
+    let Some(list) = ctx.store.list(&argv[1])? else {
+        return Ok(RespValue::Array(Vec::new()).into());
+    };
+    let elements = match normalize_range(start, end, list.len()) {
+        Some((start, end)) => list.range(start..=end).cloned().collect(),
+        None => Vec::new(),
+    };
+    Ok(bulk_array(elements).into())
+}
+
+/// LINDEX key index
+pub fn lindex(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let index = parse_i64(&argv[2])?;
+    let Some(list) = ctx.store.list(&argv[1])? else {
+        return Ok(RespValue::Null.into());
+    };
+    let element = resolve_index(index, list.len()).and_then(|index| list.get(index));
+    Ok(element
+        .cloned()
+        .map_or(RespValue::Null, RespValue::BinaryBulkString)
+        .into())
+}
+
+/// Resolves a possibly negative index to a position inside a list of `length` elements.
+fn resolve_index(index: i64, length: usize) -> Option<usize> {
+    let index = if index < 0 { index + length as i64 } else { index };
+    (0..length as i64).contains(&index).then_some(index as usize)
+}
+
+/// LSET key index element
+pub fn lset(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let index = parse_i64(&argv[2])?;
+    let list = ctx.store.list_mut(&argv[1])?.ok_or(CommandError::NoSuchKey)?;
+    let index = resolve_index(index, list.len())
+        .ok_or_else(|| CommandError::Other("index out of range".to_string()))?;
+    list[index] = argv[3].clone();
+    ok()
+}
+
+/// LREM key count element. A positive count removes matches from the head, a negative one
+/// from the tail, and 0 removes all of them.
+pub fn lrem(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let count = parse_i64(&argv[2])?;
+    let element = &argv[3];
+    let Some(list) = ctx.store.list_mut(key)? else {
+        return Ok(RespValue::Integer(0).into());
+    };
+
+    let limit = if count == 0 { usize::MAX } else { count.unsigned_abs() as usize };
+    let mut removed = 0;
+    if count >= 0 {
+        list.retain(|e| {
+            let remove = removed < limit && e == element;
+            removed += remove as usize;
+            !remove
+        });
+    } else {
+        let mut kept = VecDeque::with_capacity(list.len());
+        for e in list.drain(..).rev() {
+            if removed < limit && e == element {
+                removed += 1;
+            } else {
+                kept.push_front(e);
+            }
+        }
+        *list = kept;
+    }
+    ctx.store.remove_if_empty(key);
+    Ok(RespValue::Integer(removed as i64).into())
+}
+
+/// LTRIM key start stop
+pub fn ltrim(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let start = parse_i64(&argv[2])?;
+    let end = parse_i64(&argv[3])?;
+    let Some(list) = ctx.store.list_mut(key)? else {
+        return ok();
+    };
+    match normalize_range(start, end, list.len()) {
+        Some((start, end)) => {
+            list.truncate(end + 1);
+            list.drain(..start);
+        }
+        None => list.clear(),
+    }
+    ctx.store.remove_if_empty(key);
+    ok()
+}
+
+/// LINSERT key BEFORE | AFTER pivot element. Replies -1 when the pivot is not found.
+pub fn linsert(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let after = match arg_upper(&argv[2]).as_str() {
+        "BEFORE" => false,
+        "AFTER" => true,
+        _ => return Err(CommandError::Syntax),
+    };
+    let Some(list) = ctx.store.list_mut(&argv[1])? else {
+        return Ok(RespValue::Integer(0).into());
+    };
+    let Some(position) = list.iter().position(|e| *e == argv[3]) else {
+        return Ok(RespValue::Integer(-1).into());
+    };
+    list.insert(position + after as usize, argv[4].clone());
+    Ok(RespValue::Integer(list.len() as i64).into())
+}
+
+/// LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]
+pub fn lpos(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let element = &argv[2];
+    let mut rank: i64 = 1;
+    let mut count = None;
+    let mut max_len = 0;
+
+    let mut i = 3;
+    while i < argv.len() {
+        let value = argv.get(i + 1).ok_or(CommandError::Syntax)?;
+        match arg_upper(&argv[i]).as_str() {
+            "RANK" => {
+                rank = parse_i64(value)?;
+                if rank == 0 {
+                    return Err(CommandError::Other(
+                        "RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list".to_string(),
+                    ));
+                }
+                if rank == i64::MIN {
+                    return Err(CommandError::Other("value is out of range".to_string()));
+                }
+            }
+            "COUNT" => {
+                let value = parse_i64(value)?;
+                if value < 0 {
+                    return Err(CommandError::Other("COUNT can't be negative".to_string()));
+                }
+                count = Some(value as usize);
+            }
+            "MAXLEN" => {
+                let value = parse_i64(value)?;
+                if value < 0 {
+                    return Err(CommandError::Other("MAXLEN can't be negative".to_string()));
+                }
+                max_len = value as usize;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 2;
+    }
+
+    let Some(list) = ctx.store.list(&argv[1])? else {
+        return Ok(count.map_or(RespValue::Null, |_| RespValue::Array(Vec::new())).into());
+    };
+
+    // A negative rank searches from the tail; indexes are always reported from the head
+    let scanned = if max_len == 0 { list.len() } else { max_len.min(list.len()) };
+    let positions: Box<dyn Iterator<Item = usize>> = if rank > 0 {
+        Box::new(0..scanned)
+    } else {
+        Box::new((list.len() - scanned..list.len()).rev())
+    };
+    // COUNT 0 means all matches
+    let wanted = match count {
+        Some(0) => usize::MAX,
+        Some(count) => count,
+        None => 1,
+    };
+    let matches: Vec<RespValue> = positions
+        .filter(|&index| list[index] == *element)
+        .skip(rank.unsigned_abs() as usize - 1)
+        .take(wanted)
+        .map(|index| RespValue::Integer(index as i64))
+        .collect();
+
+    match count {
+        Some(_) => Ok(RespValue::Array(matches).into()),
+        None => Ok(matches.into_iter().next().unwrap_or(RespValue::Null).into()),
+    }
+}
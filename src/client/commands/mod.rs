@@ -0,0 +1,854 @@
+// Command registry shared by client connections and the replication stream.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/commands.def
+
+mod expire;
+mod hash;
+mod keyspace;
+mod list;
+mod server;
+mod set;
+mod stream;
+mod string;
+mod zset;
+
+use super::cache_store::{CacheStore, CacheValue, LazyExpire};
+use super::connection::{FullResync, ServerState};
+use super::error::CommandError;
+use super::model::RespValue;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+#[derive(Debug)]
+pub enum CommandResponse {
+    Normal(RespValue),
+    PsyncWithRdb(RespValue, FullResync), // FULLRESYNC response followed by RDB file
+    Block(BlockOn),          // returned by handlers, dispatch turns it into Blocked
+    Blocked(Blocked),
+}
+
+/// What a blocking command that found nothing to serve waits for.
+#[derive(Debug)]
+pub struct BlockOn {
+    pub keys: Vec<Bytes>,
+    // Only a value of this type can serve the command
+    pub value_type: &'static str,
+    pub deadline: Option<Instant>,
+    pub timeout_reply: RespValue,
+    // The command to execute again once a key is ready, when it differs from the one
+    // received, such as XREAD with `$` pinned to the ID it stood for when blocking
+    pub retry_argv: Option<Vec<Bytes>>,
+}
+
+/// A client parked by a blocking command. Its connection task waits on `receiver` for the
+/// reply, or until `deadline` passes and it replies with `timeout_reply` itself.
+#[derive(Debug)]
+pub struct Blocked {
+    pub id: u64,
+    pub receiver: oneshot::Receiver<RespValue>,
+    pub deadline: Option<Instant>,
+    pub timeout_reply: RespValue,
+}
+
+impl From<RespValue> for CommandResponse {
+    fn from(value: RespValue) -> Self {
+        CommandResponse::Normal(value)
+    }
+}
+
+pub type CommandResult = Result<CommandResponse, CommandError>;
+pub type CommandHandler = fn(&mut CommandContext<'_>, &[Bytes]) -> CommandResult;
+/// Locates the keys of commands whose key positions depend on their arguments, such as
+/// the `numkeys` of LMPOP.
+pub type KeyFinder = fn(&[Bytes]) -> Vec<usize>;
+
+/// Where a command came from. On a replica this decides how keys past their deadline look:
+/// its own clients no longer see them, while the commands streamed by the master still do
+/// until the master sends the DEL. Commands replayed from the AOF see keys the same way,
+/// and neither they nor the master's ever block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandOrigin {
+    Client,
+    Master,
+    AppendOnlyFile,
+}
+
+/// Everything a handler may touch while it runs. The store is already locked for the whole
+/// call, so a handler observes and mutates the keyspace atomically.
+pub struct CommandContext<'a> {
+    pub store: &'a mut CacheStore,
+    pub state: &'a ServerState,
+    // What replicas receive instead of the command itself, see `rewrite_propagation`
+    propagation: Option<Vec<Vec<Bytes>>>,
+}
+
+impl<'a> CommandContext<'a> {
+    fn new(store: &'a mut CacheStore, state: &'a ServerState) -> Self {
+        CommandContext {
+            store,
+            state,
+            propagation: None,
+        }
+    }
+
+    /// Propagates `argv` to replicas in place of the command being executed. Used when
+    /// replaying the command would not have the same effect, such as a SET whose relative
+    /// TTL would be counted again from when the replica applies it, or a blocking pop that is
+    /// only meaningful as the pop it turned into. May be called more than once.
+    pub fn rewrite_propagation(&mut self, argv: Vec<Bytes>) {
+        self.propagation.get_or_insert_with(Vec::new).push(argv);
+    }
+
+    /// Keeps a write command that turned out to change nothing from being propagated.
+    pub fn prevent_propagation(&mut self) {
+        self.propagation.get_or_insert_with(Vec::new);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandFlag {
+    Write,
+    ReadOnly,
+    DenyOom,
+    Admin,
+    Fast,
+    Loading,
+    Stale,
+    Blocking,
+    MovableKeys,
+}
+
+impl CommandFlag {
+    pub fn name(self) -> &'static str {
+        match self {
+            CommandFlag::Write => "write",
+            CommandFlag::ReadOnly => "readonly",
+            CommandFlag::DenyOom => "denyoom",
+            CommandFlag::Admin => "admin",
+            CommandFlag::Fast => "fast",
+            CommandFlag::Loading => "loading",
+            CommandFlag::Stale => "stale",
+            CommandFlag::Blocking => "blocking",
+            CommandFlag::MovableKeys => "movablekeys",
+        }
+    }
+}
+
+/// One row of the command table. `arity` follows the Redis convention: a positive value is
+/// the exact argument count including the command name, a negative one is the minimum.
+/// `first_key`, `last_key` and `step` locate the key arguments (`last_key` -1 means the last
+/// argument, a `first_key` of 0 means the command takes no keys). Commands with movable keys
+/// have a `key_finder` instead, and carry the `MovableKeys` flag.
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub arity: i32,
+    pub flags: &'static [CommandFlag],
+    pub first_key: i32,
+    pub last_key: i32,
+    pub step: i32,
+    pub key_finder: Option<KeyFinder>,
+    pub handler: CommandHandler,
+}
+
+impl CommandSpec {
+    pub fn has_flag(&self, flag: CommandFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.has_flag(CommandFlag::Write)
+    }
+
+    pub fn arity_matches(&self, argc: usize) -> bool {
+        let argc = argc as i32;
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    /// Positions of the key arguments in `argv`, derived from the key spec of the table.
+    pub fn key_positions(&self, argv: &[Bytes]) -> Vec<usize> {
+        if let Some(key_finder) = self.key_finder {
+            return key_finder(argv);
+        }
+        if self.first_key <= 0 {
+            return Vec::new();
+        }
+        let argc = argv.len();
+        let last = if self.last_key < 0 {
+            argc as i32 + self.last_key
+        } else {
+            self.last_key
+        };
+        (self.first_key..=last)
+            .step_by(self.step.max(1) as usize)
+            .filter(|&i| (i as usize) < argc)
+            .map(|i| i as usize)
+            .collect()
+    }
+}
+
+use CommandFlag::*;
+
+static COMMAND_TABLE: &[CommandSpec] = &[
+    // server
+    CommandSpec { name: "ping", arity: -1, flags: &[Fast, Stale], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: server::ping },
+    CommandSpec { name: "echo", arity: 2, flags: &[Fast], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: server::echo },
+    CommandSpec { name: "info", arity: -1, flags: &[Loading, Stale], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: server::info },
+    CommandSpec { name: "command", arity: -1, flags: &[Loading, Stale], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: server::command },
+    CommandSpec { name: "replconf", arity: -1, flags: &[Admin, Loading, Stale], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: server::replconf },
+    CommandSpec { name: "psync", arity: -3, flags: &[Admin], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: server::psync },
+    CommandSpec { name: "save", arity: 1, flags: &[Admin], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: server::save },
+    CommandSpec { name: "bgsave", arity: -1, flags: &[Admin], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: server::bgsave },
+    CommandSpec { name: "lastsave", arity: 1, flags: &[Fast, Loading, Stale], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: server::lastsave },
+    CommandSpec { name: "wait", arity: 3, flags: &[], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: server::wait },
+    // string
+    CommandSpec { name: "get", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::get },
+    CommandSpec { name: "set", arity: -3, flags: &[Write, DenyOom], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::set },
+    CommandSpec { name: "mget", arity: -2, flags: &[ReadOnly, Fast], first_key: 1, last_key: -1, step: 1, key_finder: None, handler: string::mget },
+    CommandSpec { name: "mset", arity: -3, flags: &[Write, DenyOom], first_key: 1, last_key: -1, step: 2, key_finder: None, handler: string::mset },
+    CommandSpec { name: "msetnx", arity: -3, flags: &[Write, DenyOom], first_key: 1, last_key: -1, step: 2, key_finder: None, handler: string::msetnx },
+    CommandSpec { name: "getdel", arity: 2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::getdel },
+    CommandSpec { name: "getex", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::getex },
+    CommandSpec { name: "getset", arity: 3, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::getset },
+    CommandSpec { name: "lcs", arity: -3, flags: &[ReadOnly], first_key: 1, last_key: 2, step: 1, key_finder: None, handler: string::lcs },
+    CommandSpec { name: "incr", arity: 2, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::incr },
+    CommandSpec { name: "decr", arity: 2, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::decr },
+    CommandSpec { name: "incrby", arity: 3, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::incrby },
+    CommandSpec { name: "decrby", arity: 3, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::decrby },
+    CommandSpec { name: "incrbyfloat", arity: 3, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::incrbyfloat },
+    CommandSpec { name: "append", arity: 3, flags: &[Write, DenyOom], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::append },
+    CommandSpec { name: "strlen", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::strlen },
+    CommandSpec { name: "getrange", arity: 4, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::getrange },
+    CommandSpec { name: "setrange", arity: 4, flags: &[Write, DenyOom], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: string::setrange },
+    // keyspace
+    CommandSpec { name: "del", arity: -2, flags: &[Write], first_key: 1, last_key: -1, step: 1, key_finder: None, handler: keyspace::del },
+    CommandSpec { name: "unlink", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: -1, step: 1, key_finder: None, handler: keyspace::unlink },
+    CommandSpec { name: "exists", arity: -2, flags: &[ReadOnly, Fast], first_key: 1, last_key: -1, step: 1, key_finder: None, handler: keyspace::exists },
+    CommandSpec { name: "touch", arity: -2, flags: &[ReadOnly, Fast], first_key: 1, last_key: -1, step: 1, key_finder: None, handler: keyspace::touch },
+    CommandSpec { name: "type", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: keyspace::type_command },
+    CommandSpec { name: "rename", arity: 3, flags: &[Write], first_key: 1, last_key: 2, step: 1, key_finder: None, handler: keyspace::rename },
+    CommandSpec { name: "renamenx", arity: 3, flags: &[Write, Fast], first_key: 1, last_key: 2, step: 1, key_finder: None, handler: keyspace::renamenx },
+    CommandSpec { name: "copy", arity: -3, flags: &[Write, DenyOom], first_key: 1, last_key: 2, step: 1, key_finder: None, handler: keyspace::copy },
+    CommandSpec { name: "keys", arity: 2, flags: &[ReadOnly], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: keyspace::keys },
+    CommandSpec { name: "scan", arity: -2, flags: &[ReadOnly], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: keyspace::scan },
+    CommandSpec { name: "randomkey", arity: 1, flags: &[ReadOnly], first_key: 0, last_key: 0, step: 0, key_finder: None, handler: keyspace::randomkey },
+    // list
+    CommandSpec { name: "lpush", arity: -3, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::lpush },
+    CommandSpec { name: "rpush", arity: -3, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::rpush },
+    CommandSpec { name: "lpushx", arity: -3, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::lpushx },
+    CommandSpec { name: "rpushx", arity: -3, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::rpushx },
+    CommandSpec { name: "lpop", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::lpop },
+    CommandSpec { name: "rpop", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::rpop },
+    CommandSpec { name: "lmpop", arity: -4, flags: &[Write, MovableKeys], first_key: 0, last_key: 0, step: 0, key_finder: Some(numkeys_at_1), handler: list::lmpop },
+    CommandSpec { name: "blpop", arity: -3, flags: &[Write, Blocking], first_key: 1, last_key: -2, step: 1, key_finder: None, handler: list::blpop },
+    CommandSpec { name: "brpop", arity: -3, flags: &[Write, Blocking], first_key: 1, last_key: -2, step: 1, key_finder: None, handler: list::brpop },
+    CommandSpec { name: "blmpop", arity: -5, flags: &[Write, Blocking, MovableKeys], first_key: 0, last_key: 0, step: 0, key_finder: Some(numkeys_at_2), handler: list::blmpop },
+    CommandSpec { name: "lmove", arity: 5, flags: &[Write, DenyOom], first_key: 1, last_key: 2, step: 1, key_finder: None, handler: list::lmove },
+    CommandSpec { name: "rpoplpush", arity: 3, flags: &[Write, DenyOom], first_key: 1, last_key: 2, step: 1, key_finder: None, handler: list::rpoplpush },
+    CommandSpec { name: "blmove", arity: 6, flags: &[Write, DenyOom, Blocking], first_key: 1, last_key: 2, step: 1, key_finder: None, handler: list::blmove },
+    CommandSpec { name: "brpoplpush", arity: 4, flags: &[Write, DenyOom, Blocking], first_key: 1, last_key: 2, step: 1, key_finder: None, handler: list::brpoplpush },
+    CommandSpec { name: "llen", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::llen },
+    CommandSpec { name: "lrange", arity: 4, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::lrange },
+    CommandSpec { name: "lindex", arity: 3, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::lindex },
+    CommandSpec { name: "lset", arity: 4, flags: &[Write, DenyOom], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::lset },
+    CommandSpec { name: "lrem", arity: 4, flags: &[Write], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::lrem },
+    CommandSpec { name: "ltrim", arity: 4, flags: &[Write], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::ltrim },
+    CommandSpec { name: "linsert", arity: 5, flags: &[Write, DenyOom], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::linsert },
+    CommandSpec { name: "lpos", arity: -3, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: list::lpos },
+    // hash
+    CommandSpec { name: "hset", arity: -4, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hset },
+    CommandSpec { name: "hmset", arity: -4, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hmset },
+    CommandSpec { name: "hsetnx", arity: 4, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hsetnx },
+    CommandSpec { name: "hget", arity: 3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hget },
+    CommandSpec { name: "hmget", arity: -3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hmget },
+    CommandSpec { name: "hgetall", arity: 2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hgetall },
+    CommandSpec { name: "hkeys", arity: 2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hkeys },
+    CommandSpec { name: "hvals", arity: 2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hvals },
+    CommandSpec { name: "hdel", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hdel },
+    CommandSpec { name: "hexists", arity: 3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hexists },
+    CommandSpec { name: "hlen", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hlen },
+    CommandSpec { name: "hstrlen", arity: 3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hstrlen },
+    CommandSpec { name: "hincrby", arity: 4, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hincrby },
+    CommandSpec { name: "hincrbyfloat", arity: 4, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hincrbyfloat },
+    CommandSpec { name: "hrandfield", arity: -2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hrandfield },
+    CommandSpec { name: "hscan", arity: -3, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hscan },
+    CommandSpec { name: "hexpire", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hexpire },
+    CommandSpec { name: "hpexpire", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hpexpire },
+    CommandSpec { name: "hexpireat", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hexpireat },
+    CommandSpec { name: "hpexpireat", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hpexpireat },
+    CommandSpec { name: "httl", arity: -5, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::httl },
+    CommandSpec { name: "hpttl", arity: -5, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hpttl },
+    CommandSpec { name: "hexpiretime", arity: -5, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hexpiretime },
+    CommandSpec { name: "hpexpiretime", arity: -5, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hpexpiretime },
+    CommandSpec { name: "hpersist", arity: -5, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hpersist },
+    CommandSpec { name: "hgetex", arity: -5, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: hash::hgetex },
+    // set
+    CommandSpec { name: "sadd", arity: -3, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: set::sadd },
+    CommandSpec { name: "srem", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: set::srem },
+    CommandSpec { name: "smembers", arity: 2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: set::smembers },
+    CommandSpec { name: "sismember", arity: 3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: set::sismember },
+    CommandSpec { name: "smismember", arity: -3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: set::smismember },
+    CommandSpec { name: "scard", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: set::scard },
+    CommandSpec { name: "spop", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: set::spop },
+    CommandSpec { name: "srandmember", arity: -2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: set::srandmember },
+    CommandSpec { name: "smove", arity: 4, flags: &[Write, Fast], first_key: 1, last_key: 2, step: 1, key_finder: None, handler: set::smove },
+    CommandSpec { name: "sinter", arity: -2, flags: &[ReadOnly], first_key: 1, last_key: -1, step: 1, key_finder: None, handler: set::sinter },
+    CommandSpec { name: "sintercard", arity: -3, flags: &[ReadOnly, MovableKeys], first_key: 0, last_key: 0, step: 0, key_finder: Some(numkeys_at_1), handler: set::sintercard },
+    CommandSpec { name: "sinterstore", arity: -3, flags: &[Write, DenyOom], first_key: 1, last_key: -1, step: 1, key_finder: None, handler: set::sinterstore },
+    CommandSpec { name: "sunion", arity: -2, flags: &[ReadOnly], first_key: 1, last_key: -1, step: 1, key_finder: None, handler: set::sunion },
+    CommandSpec { name: "sunionstore", arity: -3, flags: &[Write, DenyOom], first_key: 1, last_key: -1, step: 1, key_finder: None, handler: set::sunionstore },
+    CommandSpec { name: "sdiff", arity: -2, flags: &[ReadOnly], first_key: 1, last_key: -1, step: 1, key_finder: None, handler: set::sdiff },
+    CommandSpec { name: "sdiffstore", arity: -3, flags: &[Write, DenyOom], first_key: 1, last_key: -1, step: 1, key_finder: None, handler: set::sdiffstore },
+    CommandSpec { name: "sscan", arity: -3, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: set::sscan },
+    // sorted set
+    CommandSpec { name: "zadd", arity: -4, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zadd },
+    CommandSpec { name: "zincrby", arity: 4, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zincrby },
+    CommandSpec { name: "zrem", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zrem },
+    CommandSpec { name: "zscore", arity: 3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zscore },
+    CommandSpec { name: "zmscore", arity: -3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zmscore },
+    CommandSpec { name: "zrank", arity: -3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zrank },
+    CommandSpec { name: "zrevrank", arity: -3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zrevrank },
+    CommandSpec { name: "zcard", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zcard },
+    CommandSpec { name: "zcount", arity: 4, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zcount },
+    CommandSpec { name: "zrange", arity: -4, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zrange },
+    CommandSpec { name: "zrangestore", arity: -5, flags: &[Write, DenyOom], first_key: 1, last_key: 2, step: 1, key_finder: None, handler: zset::zrangestore },
+    CommandSpec { name: "zunionstore", arity: -4, flags: &[Write, DenyOom, MovableKeys], first_key: 0, last_key: 0, step: 0, key_finder: Some(destination_and_numkeys_at_2), handler: zset::zunionstore },
+    CommandSpec { name: "zinterstore", arity: -4, flags: &[Write, DenyOom, MovableKeys], first_key: 0, last_key: 0, step: 0, key_finder: Some(destination_and_numkeys_at_2), handler: zset::zinterstore },
+    CommandSpec { name: "zdiffstore", arity: -4, flags: &[Write, DenyOom, MovableKeys], first_key: 0, last_key: 0, step: 0, key_finder: Some(destination_and_numkeys_at_2), handler: zset::zdiffstore },
+    CommandSpec { name: "zpopmin", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zpopmin },
+    CommandSpec { name: "zpopmax", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: zset::zpopmax },
+    CommandSpec { name: "bzpopmin", arity: -3, flags: &[Write, Fast, Blocking], first_key: 1, last_key: -2, step: 1, key_finder: None, handler: zset::bzpopmin },
+    CommandSpec { name: "bzpopmax", arity: -3, flags: &[Write, Fast, Blocking], first_key: 1, last_key: -2, step: 1, key_finder: None, handler: zset::bzpopmax },
+    CommandSpec { name: "zmpop", arity: -4, flags: &[Write, MovableKeys], first_key: 0, last_key: 0, step: 0, key_finder: Some(numkeys_at_1), handler: zset::zmpop },
+    CommandSpec { name: "bzmpop", arity: -5, flags: &[Write, Blocking, MovableKeys], first_key: 0, last_key: 0, step: 0, key_finder: Some(numkeys_at_2), handler: zset::bzmpop },
+    // stream
+    CommandSpec { name: "xadd", arity: -5, flags: &[Write, DenyOom, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: stream::xadd },
+    CommandSpec { name: "xrange", arity: -4, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: stream::xrange },
+    CommandSpec { name: "xrevrange", arity: -4, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: stream::xrevrange },
+    CommandSpec { name: "xread", arity: -4, flags: &[ReadOnly, Blocking, MovableKeys], first_key: 0, last_key: 0, step: 0, key_finder: Some(streams_keys), handler: stream::xread },
+    CommandSpec { name: "xgroup", arity: -2, flags: &[Write, DenyOom], first_key: 2, last_key: 2, step: 1, key_finder: None, handler: stream::xgroup },
+    CommandSpec { name: "xreadgroup", arity: -7, flags: &[Write, Blocking, MovableKeys], first_key: 0, last_key: 0, step: 0, key_finder: Some(streams_keys), handler: stream::xreadgroup },
+    CommandSpec { name: "xack", arity: -4, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: stream::xack },
+    CommandSpec { name: "xpending", arity: -3, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: stream::xpending },
+    CommandSpec { name: "xclaim", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: stream::xclaim },
+    CommandSpec { name: "xautoclaim", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: stream::xautoclaim },
+    CommandSpec { name: "xinfo", arity: -2, flags: &[ReadOnly], first_key: 2, last_key: 2, step: 1, key_finder: None, handler: stream::xinfo },
+    CommandSpec { name: "xlen", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: stream::xlen },
+    CommandSpec { name: "xdel", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: stream::xdel },
+    CommandSpec { name: "xtrim", arity: -4, flags: &[Write], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: stream::xtrim },
+    // expire
+    CommandSpec { name: "expire", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: expire::expire },
+    CommandSpec { name: "pexpire", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: expire::pexpire },
+    CommandSpec { name: "expireat", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: expire::expireat },
+    CommandSpec { name: "pexpireat", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: expire::pexpireat },
+    CommandSpec { name: "ttl", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: expire::ttl },
+    CommandSpec { name: "pttl", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: expire::pttl },
+    CommandSpec { name: "expiretime", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: expire::expiretime },
+    CommandSpec { name: "pexpiretime", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: expire::pexpiretime },
+    CommandSpec { name: "persist", arity: 2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1, key_finder: None, handler: expire::persist },
+];
+
+/// Key finder for `<command> numkeys key [key ...] ...`.
+fn numkeys_at_1(argv: &[Bytes]) -> Vec<usize> {
+    numkeys_keys(argv, 1)
+}
+
+/// Key finder for `<command> <arg> numkeys key [key ...] ...`, such as BLMPOP.
+fn numkeys_at_2(argv: &[Bytes]) -> Vec<usize> {
+    numkeys_keys(argv, 2)
+}
+
+/// Key finder for `<command> destination numkeys key [key ...] ...`, such as ZUNIONSTORE.
+fn destination_and_numkeys_at_2(argv: &[Bytes]) -> Vec<usize> {
+    std::iter::once(1).chain(numkeys_keys(argv, 2)).collect()
+}
+
+/// Key finder for XREAD and XREADGROUP: the first half of the arguments after STREAMS.
+fn streams_keys(argv: &[Bytes]) -> Vec<usize> {
+    let Some(streams) = argv.iter().position(|arg| arg.eq_ignore_ascii_case(b"STREAMS")) else {
+        return Vec::new();
+    };
+    let count = (argv.len() - streams - 1) / 2;
+    (streams + 1..streams + 1 + count).collect()
+}
+
+/// Keys announced by the `numkeys` argument at `index`, which directly precede them.
+fn numkeys_keys(argv: &[Bytes], index: usize) -> Vec<usize> {
+    let numkeys = argv.get(index).and_then(|arg| parse_i64(arg).ok()).unwrap_or(0);
+    (index + 1..index + 1 + numkeys.max(0) as usize)
+        .filter(|&i| i < argv.len())
+        .collect()
+}
+
+fn command_index() -> &'static HashMap<&'static str, &'static CommandSpec> {
+    static INDEX: OnceLock<HashMap<&'static str, &'static CommandSpec>> = OnceLock::new();
+    INDEX.get_or_init(|| COMMAND_TABLE.iter().map(|spec| (spec.name, spec)).collect())
+}
+
+pub fn lookup_command(name: &[u8]) -> Option<&'static CommandSpec> {
+    let name = std::str::from_utf8(name).ok()?.to_ascii_lowercase();
+    command_index().get(name.as_str()).copied()
+}
+
+pub fn all_commands() -> &'static [CommandSpec] {
+    COMMAND_TABLE
+}
+
+/// Converts a decoded RESP request into its argument vector.
+pub fn to_argv(commands: &[RespValue]) -> Result<Vec<Bytes>, CommandError> {
+    commands
+        .iter()
+        .map(|value| {
+            value
+                .to_bytes()
+                .ok_or_else(|| CommandError::Other("Protocol error: expected bulk string".to_string()))
+        })
+        .collect()
+}
+
+/// Looks the command up in the table, validates its arity and runs the handler with the
+/// store locked. Failures are turned into RESP error replies.
+pub fn dispatch(state: &ServerState, argv: &[Bytes], origin: CommandOrigin) -> CommandResponse {
+    match try_dispatch(state, argv, origin) {
+        Ok(response) => response,
+        Err(e) => CommandResponse::Normal(RespValue::Error(e.to_string())),
+    }
+}
+
+// How much of an unknown command its error echoes back, as in Redis
+const UNKNOWN_COMMAND_ECHO_LEN: usize = 128;
+
+/// The error for a command missing from the table. It echoes the name and the leading
+/// arguments, each cut to what is left of 128 bytes, so a huge request is not sent back.
+fn unknown_command(argv: &[Bytes]) -> CommandError {
+    let truncated = |arg: &[u8], len: usize| String::from_utf8_lossy(&arg[..arg.len().min(len)]).to_string();
+    let mut args = String::new();
+    for arg in &argv[1..] {
+        if args.len() >= UNKNOWN_COMMAND_ECHO_LEN {
+            break;
+        }
+        args.push_str(&format!("'{}' ", truncated(arg, UNKNOWN_COMMAND_ECHO_LEN - args.len())));
+    }
+    CommandError::UnknownCommand {
+        name: truncated(&argv[0], UNKNOWN_COMMAND_ECHO_LEN),
+        args,
+    }
+}
+
+fn try_dispatch(state: &ServerState, argv: &[Bytes], origin: CommandOrigin) -> CommandResult {
+    let name = match argv.first() {
+        Some(name) => name,
+        None => return Err(CommandError::Other("no command specified".to_string())),
+    };
+    let spec = lookup_command(name).ok_or_else(|| unknown_command(argv))?;
+    if !spec.arity_matches(argv.len()) {
+        return Err(CommandError::WrongArity(spec.name.to_string()));
+    }
+
+    let mut store = state.data_store.lock().unwrap();
+    store.set_lazy_expire(match origin {
+        CommandOrigin::Master | CommandOrigin::AppendOnlyFile => LazyExpire::Keep,
+        CommandOrigin::Client if state.replica_config.is_some() => LazyExpire::Hide,
+        CommandOrigin::Client => LazyExpire::Delete,
+    });
+    let mut ctx = CommandContext::new(&mut store, state);
+    let result = (spec.handler)(&mut ctx, argv);
+    let propagation = ctx.propagation;
+    // Whatever the keyspace deleted on its own while the command ran goes out first, even
+    // if the command then failed
+    propagate_pending(state, &mut store);
+    let response = result?;
+
+    let response = match response {
+        // Blocking commands are never streamed by a master nor logged, there is nobody to
+        // park anyway
+        CommandResponse::Block(block) if origin != CommandOrigin::Client => CommandResponse::Normal(block.timeout_reply),
+        CommandResponse::Block(block) => {
+            let retry_argv = block.retry_argv.unwrap_or_else(|| argv.to_vec());
+            let (id, receiver) = store.blocked_clients().block(retry_argv, block.keys, block.value_type);
+            CommandResponse::Blocked(Blocked {
+                id,
+                receiver,
+                deadline: block.deadline,
+                timeout_reply: block.timeout_reply,
+            })
+        }
+        response => {
+            if spec.is_write() {
+                propagate(state, argv, propagation);
+            }
+            response
+        }
+    };
+    handle_clients_blocked_on_keys(state, &mut store);
+    Ok(response)
+}
+
+/// Sends the effect of a write to the replicas: the command itself, or whatever the handler
+/// rewrote it to.
+fn propagate(state: &ServerState, argv: &[Bytes], rewritten: Option<Vec<Vec<Bytes>>>) {
+    match rewritten {
+        Some(commands) => commands.iter().for_each(|command| state.propagate(command)),
+        None => state.propagate(argv),
+    }
+}
+
+/// Sends the writes the keyspace queued by itself, see [`CacheStore::take_pending_propagation`].
+pub fn propagate_pending(state: &ServerState, store: &mut CacheStore) {
+    for argv in store.take_pending_propagation() {
+        state.propagate(&argv);
+    }
+}
+
+/// Serves the clients blocked on keys that received data, longest waiting first, by
+/// executing their command again like Redis 7.2 does. This runs under the store lock right
+/// after the command that added the data, so no other client can take an element before the
+/// blocked clients had their turn.
+fn handle_clients_blocked_on_keys(state: &ServerState, store: &mut CacheStore) {
+    // Serving a client can make more keys ready, e.g. BLMOVE pushing onto its destination
+    loop {
+        let ready_keys = store.blocked_clients().take_ready_keys();
+        if ready_keys.is_empty() {
+            break;
+        }
+        for key in ready_keys {
+            for id in store.blocked_clients().waiting_on(&key) {
+                let Some(value_type) = store.value(&key).map(CacheValue::type_name) else {
+                    break;
+                };
+                let argv = match store.blocked_clients().get(id) {
+                    Some(client) if client.value_type == value_type => client.argv.clone(),
+                    _ => continue,
+                };
+                let Some(spec) = lookup_command(&argv[0]) else {
+                    continue;
+                };
+                let mut ctx = CommandContext::new(store, state);
+                let result = (spec.handler)(&mut ctx, &argv);
+                let propagation = ctx.propagation;
+                propagate_pending(state, store);
+                let reply = match result {
+                    Ok(CommandResponse::Normal(value)) => {
+                        // A served XREAD changed nothing, unlike a blocking pop
+                        if spec.is_write() {
+                            propagate(state, &argv, propagation);
+                        }
+                        value
+                    }
+                    Err(e) => RespValue::Error(e.to_string()),
+                    // Someone else got there first, keep waiting
+                    Ok(_) => continue,
+                };
+                if let Some(client) = store.blocked_clients().unblock(id) {
+                    client.reply(reply);
+                }
+            }
+        }
+    }
+}
+
+/// Uppercased copy of an argument, for matching keywords and options.
+pub fn arg_upper(arg: &[u8]) -> String {
+    String::from_utf8_lossy(arg).to_ascii_uppercase()
+}
+
+/// Parses a signed 64 bit integer with the same strictness as Redis' `string2ll`: no sign
+/// other than a leading `-`, no leading zeros and no surrounding whitespace.
+pub fn parse_i64(arg: &[u8]) -> Result<i64, CommandError> {
+    let digits = arg.strip_prefix(b"-").unwrap_or(arg);
+    let canonical = match digits {
+        [] => false,
+        [b'0'] => arg.len() == 1,
+        [first, rest @ ..] => (b'1'..=b'9').contains(first) && rest.iter().all(u8::is_ascii_digit),
+    };
+    if !canonical {
+        return Err(CommandError::NotInteger);
+    }
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or(CommandError::NotInteger)
+}
+
+/// Parses a double the way Redis' `string2d` does: no surrounding whitespace and no NaN.
+pub fn parse_f64(arg: &[u8]) -> Result<f64, CommandError> {
+    let text = std::str::from_utf8(arg).map_err(|_| CommandError::NotFloat)?;
+    if text.is_empty() || text.trim() != text {
+        return Err(CommandError::NotFloat);
+    }
+    match text.parse::<f64>() {
+        Ok(value) if !value.is_nan() => Ok(value),
+        _ => Err(CommandError::NotFloat),
+    }
+}
+
+/// Formats a double the way Redis replies with one, like `%.17g` but with the shortest
+/// digits that parse back to the same value: fixed-point for exponents from -4 to 16, and
+/// `1.5e+300` style otherwise. Infinities are `inf` and `-inf`.
+pub fn format_f64(value: f64) -> String {
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let scientific = format!("{:e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    if (-4..17).contains(&exponent) {
+        return format!("{}", value);
+    }
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+}
+
+/// Formats the result of INCRBYFLOAT and HINCRBYFLOAT, which Redis writes in fixed-point
+/// (`%.17Lf` with the trailing zeros removed) whatever the magnitude, since it becomes the
+/// stored value.
+///
+/// Redis computes in `long double`, 64 bits of mantissa on x86, and prints 17 significant
+/// digits, so the extra precision hides the rounding error of decimal fractions: 0.1 + 0.2
+/// is `0.3` there. Here the sum is an `f64` printed with the shortest digits that parse back
+/// to it, `0.30000000000000004`. Values that an `f64` holds exactly print the same in both.
+pub fn format_f64_fixed(value: f64) -> String {
+    format!("{}", value)
+}
+
+/// Parses the timeout of a blocking command in seconds, fractions allowed. 0 blocks forever,
+/// which is a deadline of None.
+pub fn parse_timeout(arg: &[u8]) -> Result<Option<Instant>, CommandError> {
+    let seconds = parse_f64(arg)
+        .ok()
+        .filter(|seconds| seconds.is_finite())
+        .ok_or_else(|| CommandError::Other("timeout is not a float or out of range".to_string()))?;
+    if seconds < 0.0 {
+        return Err(CommandError::Other("timeout is negative".to_string()));
+    }
+    let millis = (seconds * 1000.0).ceil();
+    if millis > i64::MAX as f64 {
+        return Err(CommandError::Other("timeout is out of range".to_string()));
+    }
+    Ok((millis > 0.0).then(|| Instant::now() + Duration::from_millis(millis as u64)))
+}
+
+pub fn ok() -> CommandResult {
+    Ok(RespValue::SimpleString("OK".to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::config::ServerConfig;
+
+    fn argv(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())).collect()
+    }
+
+    fn server_state() -> ServerState {
+        ServerState::new(ServerConfig::default(), None)
+    }
+
+    fn run(state: &ServerState, args: &[&str]) -> RespValue {
+        match dispatch(state, &argv(args), CommandOrigin::Client) {
+            CommandResponse::Normal(value) => value,
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    fn bulk(value: &str) -> RespValue {
+        RespValue::BinaryBulkString(Bytes::copy_from_slice(value.as_bytes()))
+    }
+
+    #[test]
+    fn unknown_command_echo_is_truncated() {
+        assert_eq!(
+            unknown_command(&argv(&["foo", "a", "b"])).to_string(),
+            "ERR unknown command 'foo', with args beginning with: 'a' 'b' "
+        );
+        let long = "x".repeat(1000);
+        let error = unknown_command(&argv(&[&long, &long, &long]));
+        let CommandError::UnknownCommand { name, args } = error else {
+            panic!("unexpected error {:?}", error);
+        };
+        assert_eq!(name.len(), 128);
+        assert_eq!(args, format!("'{}' ", "x".repeat(128)));
+        let error = unknown_command(&argv(&["foo", &"y".repeat(100), &long]));
+        let CommandError::UnknownCommand { args, .. } = error else {
+            panic!("unexpected error {:?}", error);
+        };
+        assert_eq!(args, format!("'{}' '{}' ", "y".repeat(100), "x".repeat(25)));
+    }
+
+    #[test]
+    fn scan_with_a_huge_count() {
+        let state = server_state();
+        run(&state, &["SET", "k", "v"]);
+        assert_eq!(
+            run(&state, &["SCAN", "0", "COUNT", "9223372036854775807"]),
+            RespValue::Array(vec![RespValue::BulkString("0".to_string()), RespValue::Array(vec![bulk("k")])])
+        );
+    }
+
+    #[test]
+    fn incrbyfloat_results_are_fixed_point() {
+        assert_eq!(format_f64_fixed(10.5 + 0.1), "10.6");
+        assert_eq!(format_f64_fixed(3.0), "3");
+        assert_eq!(format_f64_fixed(-0.5), "-0.5");
+        assert_eq!(format_f64_fixed(1e20), "100000000000000000000");
+        assert_eq!(format_f64_fixed(1.5e-7), "0.00000015");
+        // Where long double arithmetic rounds back to the decimal value and f64 does not
+        assert_eq!(format_f64_fixed(0.1 + 0.2), "0.30000000000000004");
+
+        // The examples of the INCRBYFLOAT documentation
+        let state = server_state();
+        run(&state, &["SET", "mykey", "10.50"]);
+        assert_eq!(run(&state, &["INCRBYFLOAT", "mykey", "0.1"]), bulk("10.6"));
+        assert_eq!(run(&state, &["INCRBYFLOAT", "mykey", "-5"]), bulk("5.6"));
+        run(&state, &["SET", "mykey", "5.0e3"]);
+        assert_eq!(run(&state, &["INCRBYFLOAT", "mykey", "2.0e2"]), bulk("5200"));
+    }
+
+    fn lcs_match(a: (i64, i64), b: (i64, i64), len: Option<i64>) -> RespValue {
+        let range = |(start, end)| RespValue::Array(vec![RespValue::Integer(start), RespValue::Integer(end)]);
+        let mut entry = vec![range(a), range(b)];
+        entry.extend(len.map(RespValue::Integer));
+        RespValue::Array(entry)
+    }
+
+    fn lcs_idx(matches: Vec<RespValue>, len: i64) -> RespValue {
+        RespValue::Array(vec![
+            RespValue::BulkString("matches".to_string()),
+            RespValue::Array(matches),
+            RespValue::BulkString("len".to_string()),
+            RespValue::Integer(len),
+        ])
+    }
+
+    #[test]
+    fn lcs_options() {
+        let state = server_state();
+        run(&state, &["MSET", "key1", "ohmytext", "key2", "mynewtext"]);
+        assert_eq!(run(&state, &["LCS", "key1", "key2"]), bulk("mytext"));
+        assert_eq!(run(&state, &["LCS", "key1", "key2", "LEN"]), RespValue::Integer(6));
+        assert_eq!(
+            run(&state, &["LCS", "key1", "key2", "IDX"]),
+            lcs_idx(vec![lcs_match((4, 7), (5, 8), None), lcs_match((2, 3), (0, 1), None)], 6)
+        );
+        assert_eq!(
+            run(&state, &["LCS", "key1", "key2", "IDX", "WITHMATCHLEN"]),
+            lcs_idx(vec![lcs_match((4, 7), (5, 8), Some(4)), lcs_match((2, 3), (0, 1), Some(2))], 6)
+        );
+        assert_eq!(
+            run(&state, &["LCS", "key1", "key2", "IDX", "MINMATCHLEN", "4"]),
+            lcs_idx(vec![lcs_match((4, 7), (5, 8), None)], 6)
+        );
+        assert_eq!(
+            run(&state, &["LCS", "key1", "key2", "MINMATCHLEN", "4", "WITHMATCHLEN", "IDX"]),
+            lcs_idx(vec![lcs_match((4, 7), (5, 8), Some(4))], 6)
+        );
+        // Without IDX the match filters change nothing
+        assert_eq!(run(&state, &["LCS", "key1", "key2", "MINMATCHLEN", "4"]), bulk("mytext"));
+        assert_eq!(run(&state, &["LCS", "key1", "key2", "LEN", "MINMATCHLEN", "4"]), RespValue::Integer(6));
+        assert!(matches!(run(&state, &["LCS", "key1", "key2", "LEN", "IDX"]), RespValue::Error(_)));
+        assert!(matches!(run(&state, &["LCS", "key1", "key2", "MINMATCHLEN"]), RespValue::Error(_)));
+    }
+
+    #[test]
+    fn lcs_of_empty_strings() {
+        let state = server_state();
+        run(&state, &["MSET", "empty", "", "text", "abc"]);
+        for (a, b) in [("empty", "text"), ("text", "empty"), ("empty", "missing"), ("missing", "missing")] {
+            assert_eq!(run(&state, &["LCS", a, b]), bulk(""));
+            assert_eq!(run(&state, &["LCS", a, b, "LEN"]), RespValue::Integer(0));
+            assert_eq!(run(&state, &["LCS", a, b, "IDX", "WITHMATCHLEN"]), lcs_idx(Vec::new(), 0));
+        }
+        run(&state, &["SET", "other", "xyz"]);
+        assert_eq!(run(&state, &["LCS", "text", "other"]), bulk(""));
+        assert_eq!(run(&state, &["LCS", "text", "other", "IDX"]), lcs_idx(Vec::new(), 0));
+    }
+
+    fn block(state: &ServerState, args: &[&str]) -> Blocked {
+        match dispatch(state, &argv(args), CommandOrigin::Client) {
+            CommandResponse::Blocked(blocked) => blocked,
+            other => panic!("expected to block, got {:?}", other),
+        }
+    }
+
+    fn popped(key: &str, element: &str) -> RespValue {
+        RespValue::Array(vec![bulk(key), bulk(element)])
+    }
+
+    #[test]
+    fn blocked_clients_are_served_in_arrival_order() {
+        let state = server_state();
+        let mut first = block(&state, &["BLPOP", "list", "0"]);
+        let mut second = block(&state, &["BRPOP", "other", "list", "0"]);
+        let mut third = block(&state, &["BLPOP", "list", "0"]);
+
+        run(&state, &["RPUSH", "list", "a", "b"]);
+        assert_eq!(first.receiver.try_recv().unwrap(), popped("list", "a"));
+        assert_eq!(second.receiver.try_recv().unwrap(), popped("list", "b"));
+        assert!(third.receiver.try_recv().is_err());
+        assert_eq!(run(&state, &["EXISTS", "list"]), RespValue::Integer(0));
+
+        run(&state, &["RPUSH", "list", "c"]);
+        assert_eq!(third.receiver.try_recv().unwrap(), popped("list", "c"));
+    }
+
+    #[test]
+    fn blocked_clients_are_served_by_lmove_and_chained_blmove() {
+        let state = server_state();
+        let mut popper = block(&state, &["BLPOP", "destination", "0"]);
+        run(&state, &["RPUSH", "source", "a"]);
+        assert_eq!(run(&state, &["LMOVE", "source", "destination", "LEFT", "RIGHT"]), bulk("a"));
+        assert_eq!(popper.receiver.try_recv().unwrap(), popped("destination", "a"));
+        assert_eq!(run(&state, &["EXISTS", "source", "destination"]), RespValue::Integer(0));
+
+        // The element BLMOVE moves makes the destination ready for the next blocked client
+        let mut mover = block(&state, &["BLMOVE", "first", "second", "LEFT", "LEFT", "0"]);
+        let mut popper = block(&state, &["BLPOP", "second", "0"]);
+        run(&state, &["RPUSH", "first", "b"]);
+        assert_eq!(mover.receiver.try_recv().unwrap(), bulk("b"));
+        assert_eq!(popper.receiver.try_recv().unwrap(), popped("second", "b"));
+        assert_eq!(run(&state, &["EXISTS", "first", "second"]), RespValue::Integer(0));
+    }
+
+    #[test]
+    fn hscan_with_a_huge_count() {
+        let state = server_state();
+        run(&state, &["HSET", "hash", "field", "value"]);
+        assert_eq!(
+            run(&state, &["HSCAN", "hash", "0", "COUNT", "9223372036854775807"]),
+            RespValue::Array(vec![
+                RespValue::BulkString("0".to_string()),
+                RespValue::Array(vec![bulk("field"), bulk("value")])
+            ])
+        );
+    }
+
+    #[test]
+    fn sscan_with_a_huge_count() {
+        let state = server_state();
+        run(&state, &["SADD", "set", "member"]);
+        assert_eq!(
+            run(&state, &["SSCAN", "set", "0", "COUNT", "9223372036854775807"]),
+            RespValue::Array(vec![RespValue::BulkString("0".to_string()), RespValue::Array(vec![bulk("member")])])
+        );
+    }
+
+    #[test]
+    fn doubles_switch_to_exponent_form_like_printf_g() {
+        assert_eq!(format_f64(1.5), "1.5");
+        assert_eq!(format_f64(-3.0), "-3");
+        assert_eq!(format_f64(0.0001), "0.0001");
+        assert_eq!(format_f64(0.00001), "1e-05");
+        assert_eq!(format_f64(1e16), "10000000000000000");
+        assert_eq!(format_f64(1e17), "1e+17");
+        assert_eq!(format_f64(-2.5e-10), "-2.5e-10");
+        assert_eq!(format_f64(1e300), "1e+300");
+        assert_eq!(format_f64(f64::NEG_INFINITY), "-inf");
+        assert_eq!(format_f64(0.1 + 0.2), "0.30000000000000004");
+    }
+}
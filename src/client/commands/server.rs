@@ -0,0 +1,201 @@
+use super::{all_commands, arg_upper, lookup_command, ok, parse_i64, CommandContext, CommandResponse, CommandResult, CommandSpec};
+use crate::client::error::CommandError;
+use crate::client::model::RespValue;
+use crate::client::persistence;
+use bytes::Bytes;
+
+pub fn ping(_ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    match argv.len() {
+        1 => Ok(RespValue::SimpleString("PONG".to_string()).into()),
+        2 => Ok(RespValue::BinaryBulkString(argv[1].clone()).into()),
+        _ => Err(CommandError::WrongArity("ping".to_string())),
+    }
+}
+
+pub fn echo(_ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    Ok(RespValue::BinaryBulkString(argv[1].clone()).into())
+}
+
+pub fn info(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let sections: Vec<String> = argv[1..].iter().map(|arg| arg_upper(arg).to_lowercase()).collect();
+    let wants = |section: &str| {
+        sections.is_empty() || sections.iter().any(|s| s == section || s == "all" || s == "everything")
+    };
+
+    let mut info_response = Vec::new();
+    if wants("replication") {
+        // TODO replica info 
+        let replica_config = &ctx.state.replica_config;
+        let role = if replica_config.is_some() {
+            "role:slave"
+        } else {
+            "role:master"
+        };
+
+        // Add master replication ID and offset for master instances
+        info_response.push(if replica_config.is_none() {
+            // This is a master instance
+            format!("# Replication\r\n{}\r\nmaster_replid:8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb\r\nmaster_repl_offset:0", role)
+        } else {
+            // This is a slave instance, only return role
+            format!("# Replication\r\n{}", role)
+        });
+    }
+    if wants("persistence") {
+        let persistence = ctx.state.persistence.lock().unwrap();
+        let aof = ctx.state.aof.lock().unwrap();
+        info_response.push(format!(
+            "# Persistence\r\nloading:0\r\nrdb_changes_since_last_save:{}\r\nrdb_bgsave_in_progress:{}\r\nrdb_last_save_time:{}\r\nrdb_last_bgsave_status:{}\r\naof_enabled:{}\r\naof_rewrite_in_progress:{}\r\naof_last_bgrewrite_status:{}\r\naof_last_write_status:{}",
+            persistence.dirty,
+            persistence.bgsave_in_progress as u8,
+            persistence.last_save,
+            if persistence.last_bgsave_ok { "ok" } else { "err" },
+            aof.is_some() as u8,
+            aof.as_ref().is_some_and(|aof| aof.rewrite_in_progress()) as u8,
+            if aof.as_ref().is_none_or(|aof| aof.last_rewrite_ok) { "ok" } else { "err" },
+            if aof.as_ref().is_none_or(|aof| aof.last_write_ok) { "ok" } else { "err" }
+        ));
+    }
+    if wants("stats") {
+        let stats = ctx.store.expire_stats();
+        info_response.push(format!(
+            "# Stats\r\nexpired_keys:{}\r\nexpired_subkeys:{}\r\nexpired_stale_perc:{:.2}\r\nexpired_time_cap_reached_count:{}",
+            stats.expired_keys,
+            stats.expired_subkeys,
+            stats.expired_stale_perc * 100.0,
+            stats.expired_time_cap_reached_count
+        ));
+    }
+
+    Ok(RespValue::BulkString(info_response.join("\r\n\r\n")).into())
+}
+
+pub fn save(ctx: &mut CommandContext<'_>, _argv: &[Bytes]) -> CommandResult {
+    if ctx.state.persistence.lock().unwrap().bgsave_in_progress {
+        return Err(CommandError::Other("Background save already in progress".to_string()));
+    }
+    persistence::save(ctx.state, ctx.store)
+        .map_err(|e| CommandError::Other(format!("Error saving DB on disk: {}", e)))?;
+    ok()
+}
+
+pub fn bgsave(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    // BGSAVE [SCHEDULE]
+    let schedule = match argv.len() {
+        1 => false,
+        2 if arg_upper(&argv[1]) == "SCHEDULE" => true,
+        _ => return Err(CommandError::Syntax),
+    };
+    if persistence::background_save(ctx.state, ctx.store) {
+        return Ok(RespValue::SimpleString("Background saving started".to_string()).into());
+    }
+    if !schedule {
+        return Err(CommandError::Other("Background save already in progress".to_string()));
+    }
+    // Started by the server cron once the running one is done
+    ctx.state.persistence.lock().unwrap().bgsave_scheduled = true;
+    Ok(RespValue::SimpleString("Background saving scheduled".to_string()).into())
+}
+
+pub fn lastsave(ctx: &mut CommandContext<'_>, _argv: &[Bytes]) -> CommandResult {
+    let last_save = ctx.state.persistence.lock().unwrap().last_save;
+    Ok(RespValue::Integer(last_save as i64).into())
+}
+
+pub fn replconf(_ctx: &mut CommandContext<'_>, _argv: &[Bytes]) -> CommandResult {
+    // For the purposes of this challenge, we can safely ignore the arguments
+    // and just respond with +OK\r\n ("OK" encoded as a RESP Simple String)
+    ok()
+}
+
+pub fn psync(ctx: &mut CommandContext<'_>, _argv: &[Bytes]) -> CommandResult {
+    // The master responds with +FULLRESYNC <REPL_ID> 0\r\n
+    // FULLRESYNC means full resynchronization (not incremental)
+    // <REPL_ID> is the replication ID of the master
+    // 0 is the replication offset of the master
+    let repl_id = "8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb";
+    let response = format!("FULLRESYNC {} 0", repl_id);
+    let resync = ctx.state.attach_replica(ctx.store);
+    Ok(CommandResponse::PsyncWithRdb(RespValue::SimpleString(response), resync))
+}
+
+pub fn wait(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    // WAIT numreplicas timeout
+    parse_i64(&argv[1])?;
+    parse_i64(&argv[2])?;
+    // Return the actual number of connected replicas
+    let replica_count = ctx.state.replica_connections.lock().unwrap().len() as i64;
+    Ok(RespValue::Integer(replica_count).into())
+}
+
+/// COMMAND, COMMAND COUNT, COMMAND LIST, COMMAND INFO, COMMAND GETKEYS and COMMAND DOCS,
+/// all answered straight from the command table.
+pub fn command(_ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    if argv.len() == 1 {
+        return Ok(RespValue::Array(all_commands().iter().map(command_info).collect()).into());
+    }
+
+    let subcommand = arg_upper(&argv[1]);
+    match subcommand.as_str() {
+        "COUNT" if argv.len() == 2 => Ok(RespValue::Integer(all_commands().len() as i64).into()),
+        "LIST" if argv.len() == 2 => Ok(RespValue::Array(
+            all_commands()
+                .iter()
+                .map(|spec| RespValue::BulkString(spec.name.to_string()))
+                .collect(),
+        )
+        .into()),
+        "INFO" => {
+            let specs: Vec<&CommandSpec> = if argv.len() == 2 {
+                all_commands().iter().collect()
+            } else {
+                Vec::new()
+            };
+            let mut reply: Vec<RespValue> = specs.into_iter().map(command_info).collect();
+            for name in &argv[2..] {
+                reply.push(lookup_command(name).map_or(RespValue::NullArray, command_info));
+            }
+            Ok(RespValue::Array(reply).into())
+        }
+        "GETKEYS" if argv.len() > 2 => {
+            let spec = lookup_command(&argv[2])
+                .ok_or_else(|| CommandError::Other("Invalid command specified".to_string()))?;
+            let command_argv = &argv[2..];
+            if !spec.arity_matches(command_argv.len()) {
+                return Err(CommandError::Other("Invalid number of arguments specified for command".to_string()));
+            }
+            let keys = spec.key_positions(command_argv);
+            if keys.is_empty() {
+                return Err(CommandError::Other("The command has no key arguments".to_string()));
+            }
+            Ok(RespValue::Array(
+                keys.into_iter()
+                    .map(|i| RespValue::BinaryBulkString(command_argv[i].clone()))
+                    .collect(),
+            )
+            .into())
+        }
+        // Clients such as redis-cli ask for docs on connect; an empty map keeps them happy
+        "DOCS" => Ok(RespValue::Array(Vec::new()).into()),
+        _ => Err(CommandError::Other(format!(
+            "unknown subcommand '{}'. Try COMMAND HELP.",
+            String::from_utf8_lossy(&argv[1])
+        ))),
+    }
+}
+
+fn command_info(spec: &CommandSpec) -> RespValue {
+    RespValue::Array(vec![
+        RespValue::BulkString(spec.name.to_string()),
+        RespValue::Integer(spec.arity as i64),
+        RespValue::Array(
+            spec.flags
+                .iter()
+                .map(|flag| RespValue::SimpleString(flag.name().to_string()))
+                .collect(),
+        ),
+        RespValue::Integer(spec.first_key as i64),
+        RespValue::Integer(spec.last_key as i64),
+        RespValue::Integer(spec.step as i64),
+    ])
+}
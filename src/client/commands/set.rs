@@ -0,0 +1,325 @@
+use super::keyspace::{parse_scan_cursor, parse_scan_options, ScanTarget};
+use super::{arg_upper, parse_i64, CommandContext, CommandResult};
+use crate::client::cache_store::CacheValue;
+use crate::client::error::CommandError;
+use crate::client::model::RespValue;
+use crate::client::random::random_below;
+use crate::client::types::set::Set;
+use bytes::Bytes;
+
+fn bulk_array(members: impl Iterator<Item = Bytes>) -> RespValue {
+    RespValue::Array(members.map(RespValue::BinaryBulkString).collect())
+}
+
+/// SADD key member [member ...]. Replies with the number of members added.
+pub fn sadd(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let set = ctx.store.set_value_or_create(&argv[1])?;
+    let added = argv[2..].iter().filter(|member| set.insert((*member).clone())).count();
+    Ok(RespValue::Integer(added as i64).into())
+}
+
+/// SREM key member [member ...]. Replies with the number of members removed.
+pub fn srem(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let Some(set) = ctx.store.set_value_mut(&argv[1])? else {
+        return Ok(RespValue::Integer(0).into());
+    };
+    let removed = argv[2..].iter().filter(|member| set.remove(member)).count();
+    ctx.store.remove_if_empty(&argv[1]);
+    Ok(RespValue::Integer(removed as i64).into())
+}
+
+/// SMEMBERS key
+pub fn smembers(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let Some(set) = ctx.store.set_value(&argv[1])? else {
+        return Ok(RespValue::Array(Vec::new()).into());
+    };
+    Ok(bulk_array(set.iter()).into())
+}
+
+/// SISMEMBER key member
+pub fn sismember(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let found = ctx.store.set_value(&argv[1])?.is_some_and(|set| set.contains(&argv[2]));
+    Ok(RespValue::Integer(found as i64).into())
+}
+
+/// SMISMEMBER key member [member ...]
+pub fn smismember(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let set = ctx.store.set_value(&argv[1])?;
+    let found = argv[2..]
+        .iter()
+        .map(|member| RespValue::Integer(set.is_some_and(|set| set.contains(member)) as i64))
+        .collect();
+    Ok(RespValue::Array(found).into())
+}
+
+/// SCARD key
+pub fn scard(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let len = ctx.store.set_value(&argv[1])?.map_or(0, Set::len);
+    Ok(RespValue::Integer(len as i64).into())
+}
+
+/// SPOP key [count]. Replicas are told which members went, as an SREM, or a DEL when the
+/// whole set was popped.
+pub fn spop(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    if argv.len() > 3 {
+        return Err(CommandError::Syntax);
+    }
+    let count = match argv.get(2) {
+        Some(arg) => {
+            let count = parse_i64(arg)?;
+            if count < 0 {
+                return Err(CommandError::Other("value is out of range, must be positive".to_string()));
+            }
+            Some(count as usize)
+        }
+        None => None,
+    };
+
+    let key = &argv[1];
+    let Some(set) = ctx.store.set_value_mut(key)? else {
+        return Ok(count.map_or(RespValue::Null, |_| RespValue::Array(Vec::new())).into());
+    };
+    let Some(count) = count else {
+        let member = set.pop_random();
+        ctx.store.remove_if_empty(key);
+        match &member {
+            Some(member) => ctx.rewrite_propagation(vec![Bytes::from_static(b"SREM"), key.clone(), member.clone()]),
+            None => ctx.prevent_propagation(),
+        }
+        return Ok(member.map_or(RespValue::Null, RespValue::BinaryBulkString).into());
+    };
+
+    if count == 0 {
+        ctx.prevent_propagation();
+        return Ok(RespValue::Array(Vec::new()).into());
+    }
+    let members: Vec<Bytes> = if count >= set.len() {
+        let members = set.iter().collect();
+        ctx.store.remove(key);
+        ctx.rewrite_propagation(vec![Bytes::from_static(b"DEL"), key.clone()]);
+        members
+    } else {
+        let members: Vec<Bytes> = (0..count).filter_map(|_| set.pop_random()).collect();
+        ctx.rewrite_propagation(
+            [Bytes::from_static(b"SREM"), key.clone()]
+                .into_iter()
+                .chain(members.iter().cloned())
+                .collect(),
+        );
+        members
+    };
+    Ok(bulk_array(members.into_iter()).into())
+}
+
+/// SRANDMEMBER key [count]. A negative count may return the same member more than once.
+pub fn srandmember(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    if argv.len() > 3 {
+        return Err(CommandError::Syntax);
+    }
+    let Some(count) = argv.get(2) else {
+        let member = ctx.store.set_value(&argv[1])?.and_then(Set::random_member);
+        return Ok(member.map_or(RespValue::Null, RespValue::BinaryBulkString).into());
+    };
+    let count = parse_i64(count)?;
+    if count == i64::MIN {
+        return Err(CommandError::Other("value is out of range".to_string()));
+    }
+    let Some(set) = ctx.store.set_value(&argv[1])? else {
+        return Ok(RespValue::Array(Vec::new()).into());
+    };
+
+    let members: Vec<Bytes> = if count < 0 {
+        (0..count.unsigned_abs()).filter_map(|_| set.random_member()).collect()
+    } else if count as usize >= set.len() {
+        set.iter().collect()
+    } else {
+        // Partial Fisher-Yates shuffle: the first `count` slots end up a uniform sample
+        let mut members: Vec<Bytes> = set.iter().collect();
+        let count = count as usize;
+        for i in 0..count {
+            let j = i + random_below(members.len() - i);
+            members.swap(i, j);
+        }
+        members.truncate(count);
+        members
+    };
+    Ok(bulk_array(members.into_iter()).into())
+}
+
+/// SMOVE source destination member
+pub fn smove(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let (source, destination, member) = (&argv[1], &argv[2], &argv[3]);
+    // Both types are checked before anything is moved
+    let Some(contains) = ctx.store.set_value(source)?.map(|set| set.contains(member)) else {
+        return Ok(RespValue::Integer(0).into());
+    };
+    ctx.store.set_value(destination)?;
+    if source == destination || !contains {
+        ctx.prevent_propagation();
+        return Ok(RespValue::Integer(contains as i64).into());
+    }
+
+    if let Some(set) = ctx.store.set_value_mut(source)? {
+        set.remove(member);
+    }
+    ctx.store.remove_if_empty(source);
+    ctx.store.set_value_or_create(destination)?.insert(member.clone());
+    Ok(RespValue::Integer(1).into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetOperation {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// The members of every set that are in all of them. Missing keys are empty sets, so a
+/// single one empties the result.
+fn intersection<'a>(sets: &[Option<&'a Set>]) -> Box<dyn Iterator<Item = Bytes> + 'a> {
+    let Some(mut sets) = sets.iter().copied().collect::<Option<Vec<&Set>>>() else {
+        return Box::new(std::iter::empty());
+    };
+    // Walking the smallest set bounds the number of lookups
+    sets.sort_by_key(|set| set.len());
+    let Some((smallest, rest)) = sets.split_first() else {
+        return Box::new(std::iter::empty());
+    };
+    let rest = rest.to_vec();
+    Box::new(smallest.iter().filter(move |member| rest.iter().all(|set| set.contains(member))))
+}
+
+fn combine(sets: &[Option<&Set>], operation: SetOperation) -> Set {
+    match operation {
+        SetOperation::Inter => intersection(sets).collect(),
+        SetOperation::Union => sets.iter().flatten().flat_map(|set| set.iter()).collect(),
+        SetOperation::Diff => {
+            let Some((Some(first), rest)) = sets.split_first() else {
+                return Set::default();
+            };
+            first
+                .iter()
+                .filter(|member| !rest.iter().flatten().any(|set| set.contains(member)))
+                .collect()
+        }
+    }
+}
+
+/// SINTER key [key ...]
+pub fn sinter(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    set_operation_generic(ctx, &argv[1..], SetOperation::Inter)
+}
+
+/// SUNION key [key ...]
+pub fn sunion(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    set_operation_generic(ctx, &argv[1..], SetOperation::Union)
+}
+
+/// SDIFF key [key ...]. The members of the first set that are in none of the others.
+pub fn sdiff(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    set_operation_generic(ctx, &argv[1..], SetOperation::Diff)
+}
+
+fn set_operation_generic(ctx: &mut CommandContext<'_>, keys: &[Bytes], operation: SetOperation) -> CommandResult {
+    let sets = ctx.store.set_values(keys)?;
+    Ok(bulk_array(combine(&sets, operation).iter()).into())
+}
+
+/// SINTERSTORE destination key [key ...]
+pub fn sinterstore(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    set_operation_store(ctx, argv, SetOperation::Inter)
+}
+
+/// SUNIONSTORE destination key [key ...]
+pub fn sunionstore(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    set_operation_store(ctx, argv, SetOperation::Union)
+}
+
+/// SDIFFSTORE destination key [key ...]
+pub fn sdiffstore(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    set_operation_store(ctx, argv, SetOperation::Diff)
+}
+
+/// Stores the result under the destination, replacing whatever was there along with its
+/// TTL. An empty result deletes the destination instead.
+fn set_operation_store(ctx: &mut CommandContext<'_>, argv: &[Bytes], operation: SetOperation) -> CommandResult {
+    let destination = &argv[1];
+    let sets = ctx.store.set_values(&argv[2..])?;
+    let result = combine(&sets, operation);
+    let len = result.len();
+    if result.is_empty() {
+        ctx.store.remove(destination);
+    } else {
+        ctx.store.insert_entry(destination.clone(), CacheValue::Set(result), None);
+    }
+    Ok(RespValue::Integer(len as i64).into())
+}
+
+/// SINTERCARD numkeys key [key ...] [LIMIT limit]. Counts the intersection without
+/// building it, stopping early once `limit` members are found (0 means no limit).
+pub fn sintercard(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let numkeys = parse_i64(&argv[1])
+        .ok()
+        .filter(|&numkeys| numkeys > 0)
+        .ok_or_else(|| CommandError::Other("numkeys should be greater than 0".to_string()))?;
+    if numkeys as usize > argv.len() - 2 {
+        return Err(CommandError::Other("Number of keys can't be greater than number of args".to_string()));
+    }
+    let numkeys = numkeys as usize;
+    let keys = &argv[2..2 + numkeys];
+
+    let mut limit = 0;
+    let mut i = 2 + numkeys;
+    while i < argv.len() {
+        match arg_upper(&argv[i]).as_str() {
+            "LIMIT" if i + 1 < argv.len() => {
+                limit = parse_i64(&argv[i + 1])
+                    .ok()
+                    .filter(|&limit| limit >= 0)
+                    .ok_or_else(|| CommandError::Other("LIMIT can't be negative".to_string()))?;
+                i += 2;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+
+    let sets = ctx.store.set_values(keys)?;
+    let limit = if limit == 0 { usize::MAX } else { limit as usize };
+    let count = intersection(&sets).take(limit).count();
+    Ok(RespValue::Integer(count as i64).into())
+}
+
+/// SSCAN key cursor [MATCH pattern] [COUNT count]
+pub fn sscan(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let mut cursor = parse_scan_cursor(&argv[2])?;
+    let options = parse_scan_options(&argv[3..], ScanTarget::Set)?;
+    let Some(set) = ctx.store.set_value(&argv[1])? else {
+        return Ok(RespValue::Array(vec![
+            RespValue::BulkString("0".to_string()),
+            RespValue::Array(Vec::new()),
+        ])
+        .into());
+    };
+
+    let mut members = Vec::new();
+    let mut visited = 0;
+    let mut max_iterations = options.max_iterations();
+    loop {
+        cursor = set.scan(cursor, |member| {
+            visited += 1;
+            if options.matches(&member) {
+                members.push(RespValue::BinaryBulkString(member));
+            }
+        });
+        max_iterations -= 1;
+        if cursor == 0 || max_iterations == 0 || visited >= options.count {
+            break;
+        }
+    }
+
+    Ok(RespValue::Array(vec![
+        RespValue::BulkString(cursor.to_string()),
+        RespValue::Array(members),
+    ])
+    .into())
+}
@@ -0,0 +1,1284 @@
+use super::{arg_upper, ok, parse_i64, BlockOn, CommandContext, CommandResponse, CommandResult};
+use crate::client::cache_store::now_ms;
+use crate::client::error::CommandError;
+use crate::client::model::RespValue;
+use crate::client::types::stream::{ConsumerGroup, Fields, PendingEntry, Stream, StreamId, TrimThreshold};
+use bytes::Bytes;
+use std::time::{Duration, Instant};
+
+fn invalid_id() -> CommandError {
+    CommandError::Other("Invalid stream ID specified as stream command argument".to_string())
+}
+
+/// Parses `<ms>-<seq>`, or a bare `<ms>` that takes `missing_seq` as its sequence.
+pub(super) fn parse_id(arg: &[u8], missing_seq: u64) -> Result<StreamId, CommandError> {
+    let parse_part = |part: &[u8]| {
+        std::str::from_utf8(part)
+            .ok()
+            .filter(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|part| part.parse::<u64>().ok())
+            .ok_or_else(invalid_id)
+    };
+    match arg.iter().position(|&b| b == b'-') {
+        Some(dash) => Ok(StreamId {
+            ms: parse_part(&arg[..dash])?,
+            seq: parse_part(&arg[dash + 1..])?,
+        }),
+        None => Ok(StreamId {
+            ms: parse_part(arg)?,
+            seq: missing_seq,
+        }),
+    }
+}
+
+/// Parses the start or end of an XRANGE interval: `-`, `+`, an ID whose sequence defaults to
+/// the first or last of its millisecond, or an ID prefixed by `(` to exclude it.
+fn parse_interval_id(arg: &[u8], start: bool) -> Result<StreamId, CommandError> {
+    match arg {
+        b"-" => return Ok(StreamId::MIN),
+        b"+" => return Ok(StreamId::MAX),
+        _ => {}
+    }
+    let missing_seq = if start { 0 } else { u64::MAX };
+    let Some(id) = arg.strip_prefix(b"(") else {
+        return parse_id(arg, missing_seq);
+    };
+    let id = parse_id(id, missing_seq)?;
+    let excluded = if start { id.next() } else { id.prev() };
+    excluded.ok_or_else(|| {
+        let end = if start { "start" } else { "end" };
+        CommandError::Other(format!("invalid {} ID for the interval", end))
+    })
+}
+
+/// The reply form of an entry: its ID followed by a flat array of its fields and values.
+pub(super) fn entry_reply(id: &StreamId, fields: &Fields) -> RespValue {
+    let fields = fields
+        .iter()
+        .flat_map(|(field, value)| {
+            [
+                RespValue::BinaryBulkString(field.clone()),
+                RespValue::BinaryBulkString(value.clone()),
+            ]
+        })
+        .collect();
+    RespValue::Array(vec![RespValue::BulkString(id.to_string()), RespValue::Array(fields)])
+}
+
+/// The trimming options of XADD and XTRIM.
+struct TrimArgs {
+    threshold: TrimThreshold,
+    approximate: bool,
+    limit: Option<usize>,
+}
+
+/// The ID argument of XADD.
+enum IdArg {
+    Auto,               // *
+    AutoSequence(u64),  // <ms>-*
+    Explicit(StreamId), // <ms>-<seq>
+}
+
+/// Parses `[NOMKSTREAM] [MAXLEN | MINID [= | ~] threshold [LIMIT count]]`, the options that
+/// precede the ID of XADD, or the trimming options of XTRIM (`xadd` false). Returns the
+/// options and, for XADD, the index of the ID argument.
+fn parse_add_or_trim_args(args: &[Bytes], xadd: bool) -> Result<(Option<TrimArgs>, bool, usize), CommandError> {
+    let mut threshold = None;
+    let mut approximate = false;
+    let mut limit = None;
+    let mut no_mkstream = false;
+
+    let mut i = 0;
+    while i < args.len() {
+        let more_args = args.len() - 1 - i;
+        let option = arg_upper(&args[i]);
+        match option.as_str() {
+            "*" if xadd => break,
+            "MAXLEN" | "MINID" if more_args > 0 => {
+                if threshold.is_some() {
+                    return Err(CommandError::Other(
+                        "syntax error, MAXLEN and MINID options at the same time are not compatible".to_string(),
+                    ));
+                }
+                if more_args >= 2 && matches!(args[i + 1].as_ref(), b"~" | b"=") {
+                    approximate = args[i + 1].as_ref() == b"~";
+                    i += 1;
+                }
+                let value = &args[i + 1];
+                threshold = Some(if option == "MAXLEN" {
+                    let max_len = parse_i64(value)?;
+                    if max_len < 0 {
+                        return Err(CommandError::Other("The MAXLEN argument must be >= 0.".to_string()));
+                    }
+                    TrimThreshold::MaxLen(max_len as usize)
+                } else {
+                    TrimThreshold::MinId(parse_id(value, 0)?)
+                });
+                i += 1;
+            }
+            "LIMIT" if more_args > 0 => {
+                let count = parse_i64(&args[i + 1])?;
+                if count < 0 {
+                    return Err(CommandError::Other("The LIMIT argument must be >= 0.".to_string()));
+                }
+                limit = Some(count as usize);
+                i += 1;
+            }
+            "NOMKSTREAM" if xadd => no_mkstream = true,
+            // Anything else is the ID
+            _ if xadd => break,
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+
+    let Some(threshold) = threshold else {
+        if limit.is_some() {
+            return Err(CommandError::Other(
+                "syntax error, LIMIT cannot be used without specifying a trimming strategy".to_string(),
+            ));
+        }
+        if !xadd {
+            return Err(CommandError::Syntax);
+        }
+        return Ok((None, no_mkstream, i));
+    };
+    if limit.is_some() && !approximate {
+        return Err(CommandError::Other(
+            "syntax error, LIMIT cannot be used without the special ~ option".to_string(),
+        ));
+    }
+    let trim = TrimArgs {
+        threshold,
+        approximate,
+        limit,
+    };
+    Ok((Some(trim), no_mkstream, i))
+}
+
+/// The trimming options to propagate. An approximate trim depends on how the entries happen
+/// to be laid out, so replicas are told the exact length it left instead.
+fn propagated_trim_args(trim: &TrimArgs, original: &[Bytes], len: usize) -> Vec<Bytes> {
+    if trim.approximate {
+        vec![Bytes::from_static(b"MAXLEN"), Bytes::from_static(b"="), Bytes::from(len.to_string())]
+    } else {
+        original.to_vec()
+    }
+}
+
+/// XADD key [NOMKSTREAM] [MAXLEN | MINID [= | ~] threshold [LIMIT count]] * | id field value
+/// [field value ...]
+///
+/// Replies with the ID of the new entry. Replicas receive the command with the generated ID
+/// in place of `*`, so they store the very same entry.
+pub fn xadd(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let (trim, no_mkstream, id_index) = parse_add_or_trim_args(&argv[2..], true)?;
+    let id_index = id_index + 2;
+    let fields = argv.get(id_index + 1..).unwrap_or_default();
+    if fields.is_empty() || !fields.len().is_multiple_of(2) {
+        return Err(CommandError::WrongArity("xadd".to_string()));
+    }
+    let id_arg = match argv[id_index].as_ref() {
+        b"*" => IdArg::Auto,
+        arg => match arg.strip_suffix(b"-*") {
+            Some(ms) => IdArg::AutoSequence(parse_id(ms, 0)?.ms),
+            None => IdArg::Explicit(parse_id(arg, 0)?),
+        },
+    };
+    if matches!(id_arg, IdArg::Explicit(StreamId::MIN)) {
+        return Err(CommandError::Other("The ID specified in XADD must be greater than 0-0".to_string()));
+    }
+
+    let stream = if no_mkstream {
+        match ctx.store.stream_mut(key)? {
+            Some(stream) => stream,
+            None => {
+                ctx.prevent_propagation();
+                return Ok(RespValue::Null.into());
+            }
+        }
+    } else {
+        ctx.store.stream_or_create(key)?
+    };
+    let last_id = stream.last_id();
+    if last_id == StreamId::MAX {
+        return Err(CommandError::Other(
+            "The stream has exhausted the last possible ID, unable to add more items".to_string(),
+        ));
+    }
+    let id = match id_arg {
+        IdArg::Auto => stream.next_id(now_ms()),
+        IdArg::AutoSequence(ms) if ms == last_id.ms => last_id.next().filter(|id| id.ms == ms),
+        IdArg::AutoSequence(ms) => Some(StreamId { ms, seq: 0 }).filter(|id| *id > last_id),
+        IdArg::Explicit(id) => Some(id).filter(|id| *id > last_id),
+    }
+    .ok_or_else(|| {
+        CommandError::Other("The ID specified in XADD is equal or smaller than the target stream top item".to_string())
+    })?;
+
+    let entry = fields
+        .chunks_exact(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect();
+    stream.append(id, entry);
+    let trim_args = trim.map(|trim| {
+        stream.trim(trim.threshold, trim.approximate, trim.limit);
+        propagated_trim_args(&trim, &argv[2..id_index], stream.len())
+    });
+    ctx.store.signal_key_as_ready(key);
+
+    let id_arg = Bytes::from(id.to_string());
+    let mut propagated = vec![Bytes::from_static(b"XADD"), key.clone()];
+    if no_mkstream {
+        propagated.push(Bytes::from_static(b"NOMKSTREAM"));
+    }
+    propagated.extend(trim_args.into_iter().flatten().filter(|arg| !arg.eq_ignore_ascii_case(b"NOMKSTREAM")));
+    propagated.push(id_arg.clone());
+    propagated.extend(fields.iter().cloned());
+    ctx.rewrite_propagation(propagated);
+    Ok(RespValue::BinaryBulkString(id_arg).into())
+}
+
+/// XTRIM key MAXLEN | MINID [= | ~] threshold [LIMIT count]. Replies with the number of
+/// entries evicted.
+pub fn xtrim(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let (Some(trim), ..) = parse_add_or_trim_args(&argv[2..], false)? else {
+        return Err(CommandError::Syntax);
+    };
+    let Some(stream) = ctx.store.stream_mut(key)? else {
+        return Ok(RespValue::Integer(0).into());
+    };
+    let evicted = stream.trim(trim.threshold, trim.approximate, trim.limit);
+    if trim.approximate {
+        let trim_args = propagated_trim_args(&trim, &argv[2..], stream.len());
+        ctx.rewrite_propagation([Bytes::from_static(b"XTRIM"), key.clone()].into_iter().chain(trim_args).collect());
+    }
+    Ok(RespValue::Integer(evicted as i64).into())
+}
+
+/// XDEL key id [id ...]. Replies with the number of entries deleted.
+pub fn xdel(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    // Every ID is validated before the first one is deleted
+    let ids = argv[2..]
+        .iter()
+        .map(|arg| parse_id(arg, 0))
+        .collect::<Result<Vec<StreamId>, CommandError>>()?;
+    let Some(stream) = ctx.store.stream_mut(&argv[1])? else {
+        return Ok(RespValue::Integer(0).into());
+    };
+    let deleted = ids.into_iter().filter(|&id| stream.remove(id)).count();
+    Ok(RespValue::Integer(deleted as i64).into())
+}
+
+/// XLEN key
+pub fn xlen(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let len = ctx.store.stream(&argv[1])?.map_or(0, |stream| stream.len());
+    Ok(RespValue::Integer(len as i64).into())
+}
+
+/// XRANGE key start end [COUNT count]
+pub fn xrange(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    xrange_generic(ctx, argv, false)
+}
+
+/// XREVRANGE key end start [COUNT count]
+pub fn xrevrange(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    xrange_generic(ctx, argv, true)
+}
+
+fn xrange_generic(ctx: &mut CommandContext<'_>, argv: &[Bytes], reverse: bool) -> CommandResult {
+    let (start, end) = if reverse { (&argv[3], &argv[2]) } else { (&argv[2], &argv[3]) };
+    let start = parse_interval_id(start, true)?;
+    let end = parse_interval_id(end, false)?;
+
+    let mut count = None;
+    let mut i = 4;
+    while i < argv.len() {
+        match arg_upper(&argv[i]).as_str() {
+            "COUNT" if i + 1 < argv.len() => {
+                count = Some(parse_i64(&argv[i + 1])?.max(0) as usize);
+                i += 2;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+
+    let Some(stream) = ctx.store.stream(&argv[1])? else {
+        return Ok(RespValue::Array(Vec::new()).into());
+    };
+    if count == Some(0) {
+        return Ok(RespValue::NullArray.into());
+    }
+    let entries = stream
+        .range(start, end, reverse)
+        .take(count.unwrap_or(usize::MAX))
+        .map(|(id, fields)| entry_reply(id, fields))
+        .collect();
+    Ok(RespValue::Array(entries).into())
+}
+
+/// Parses the BLOCK timeout of XREAD, in milliseconds. 0 blocks forever, which is a deadline
+/// of None.
+fn parse_block_timeout(arg: &[u8]) -> Result<Option<Instant>, CommandError> {
+    let millis = parse_i64(arg)
+        .map_err(|_| CommandError::Other("timeout is not an integer or out of range".to_string()))?;
+    if millis < 0 {
+        return Err(CommandError::Other("timeout is negative".to_string()));
+    }
+    Ok((millis > 0).then(|| Instant::now() + Duration::from_millis(millis as u64)))
+}
+
+/// The options of XREAD and XREADGROUP that precede their STREAMS.
+struct ReadArgs {
+    group: Option<(Bytes, Bytes)>, // group and consumer of XREADGROUP
+    count: usize,
+    deadline: Option<Option<Instant>>, // BLOCK
+    no_ack: bool,
+    streams_index: usize,
+}
+
+/// Parses the options of XREAD, or of XREADGROUP when `xreadgroup`, and splits the
+/// arguments after STREAMS into keys and IDs.
+fn parse_read_args(argv: &[Bytes], xreadgroup: bool) -> Result<(ReadArgs, &[Bytes], &[Bytes]), CommandError> {
+    let mut args = ReadArgs {
+        group: None,
+        count: usize::MAX,
+        deadline: None,
+        no_ack: false,
+        streams_index: 0,
+    };
+    let mut i = 1;
+    loop {
+        if i + 1 >= argv.len() {
+            return Err(CommandError::Syntax);
+        }
+        match arg_upper(&argv[i]).as_str() {
+            // COUNT 0 or below means no limit
+            "COUNT" => {
+                let count = parse_i64(&argv[i + 1])?;
+                args.count = if count > 0 { count as usize } else { usize::MAX };
+            }
+            "BLOCK" => args.deadline = Some(parse_block_timeout(&argv[i + 1])?),
+            "GROUP" if !xreadgroup => {
+                return Err(CommandError::Other(
+                    "The GROUP option is only supported by XREADGROUP. You called XREAD instead.".to_string(),
+                ))
+            }
+            "GROUP" if i + 2 < argv.len() => {
+                args.group = Some((argv[i + 1].clone(), argv[i + 2].clone()));
+                i += 1;
+            }
+            "NOACK" if xreadgroup => {
+                args.no_ack = true;
+                i += 1;
+                continue;
+            }
+            "STREAMS" => break,
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 2;
+    }
+    args.streams_index = i + 1;
+    if xreadgroup && args.group.is_none() {
+        return Err(CommandError::Other("Missing GROUP option for XREADGROUP".to_string()));
+    }
+
+    let streams = &argv[args.streams_index..];
+    if !streams.len().is_multiple_of(2) {
+        let (name, special_id) = if xreadgroup { ("xreadgroup", ">") } else { ("xread", "$") };
+        return Err(CommandError::Other(format!(
+            "Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be specified.",
+            name, special_id
+        )));
+    }
+    let (keys, ids) = streams.split_at(streams.len() / 2);
+    Ok((args, keys, ids))
+}
+
+/// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+///
+/// Replies with the entries newer than the given ID of every stream that has any, or with
+/// a null array if none has. `$` stands for the last ID of the stream, so only entries
+/// added from now on are read, and `+` for the ID right before it, which reads the last
+/// entry. With BLOCK the client waits for an XADD to any of the streams instead.
+pub fn xread(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let (args, keys, ids) = parse_read_args(argv, false)?;
+
+    let mut last_read = Vec::with_capacity(keys.len());
+    for (key, id) in keys.iter().zip(ids) {
+        let last_id = ctx.store.stream(key)?.map(|stream| stream.last_id());
+        last_read.push(match id.as_ref() {
+            b"$" => last_id.unwrap_or(StreamId::MIN),
+            b"+" => last_id.and_then(StreamId::prev).unwrap_or(StreamId::MIN),
+            id => parse_id(id, 0)?,
+        });
+    }
+
+    let mut reply = Vec::new();
+    for (key, last_read) in keys.iter().zip(&last_read) {
+        let Some(stream) = ctx.store.stream(key)? else {
+            continue;
+        };
+        let Some(start) = last_read.next() else {
+            continue;
+        };
+        let entries: Vec<RespValue> = stream
+            .range(start, StreamId::MAX, false)
+            .take(args.count)
+            .map(|(id, fields)| entry_reply(id, fields))
+            .collect();
+        if !entries.is_empty() {
+            reply.push(stream_reply(key, entries));
+        }
+    }
+    if !reply.is_empty() {
+        return Ok(RespValue::Array(reply).into());
+    }
+    let Some(deadline) = args.deadline else {
+        return Ok(RespValue::NullArray.into());
+    };
+
+    // Executed again once woken up, by then `$` would stand for the entry that woke it
+    let retry_argv = argv[..args.streams_index + keys.len()]
+        .iter()
+        .cloned()
+        .chain(last_read.iter().map(|id| Bytes::from(id.to_string())))
+        .collect();
+    Ok(CommandResponse::Block(BlockOn {
+        keys: keys.to_vec(),
+        value_type: "stream",
+        deadline,
+        timeout_reply: RespValue::NullArray,
+        retry_argv: Some(retry_argv),
+    }))
+}
+
+/// The reply of XREAD and XREADGROUP for one stream: its key and the entries read from it.
+fn stream_reply(key: &Bytes, entries: Vec<RespValue>) -> RespValue {
+    RespValue::Array(vec![RespValue::BinaryBulkString(key.clone()), RespValue::Array(entries)])
+}
+
+/// A reply in the form RESP2 gives maps: a flat array of names and values.
+fn map_reply(pairs: Vec<(&str, RespValue)>) -> RespValue {
+    RespValue::Array(
+        pairs
+            .into_iter()
+            .flat_map(|(name, value)| [RespValue::BulkString(name.to_string()), value])
+            .collect(),
+    )
+}
+
+fn id_reply(id: StreamId) -> RespValue {
+    RespValue::BulkString(id.to_string())
+}
+
+fn optional_integer_reply(value: Option<u64>) -> RespValue {
+    value.map_or(RespValue::Null, |value| RespValue::Integer(value as i64))
+}
+
+fn no_such_group(key: &[u8], group: &[u8]) -> CommandError {
+    CommandError::NoGroup(format!(
+        "No such key '{}' or consumer group '{}'",
+        String::from_utf8_lossy(key),
+        String::from_utf8_lossy(group)
+    ))
+}
+
+/// Looks up a stream that has the given group, for the commands that fail with NOGROUP
+/// otherwise.
+fn stream_with_group<'a>(
+    ctx: &'a mut CommandContext<'_>,
+    key: &Bytes,
+    group: &[u8],
+) -> Result<Option<&'a mut Stream>, CommandError> {
+    Ok(ctx.store.stream_mut(key)?.filter(|stream| stream.group(group).is_some()))
+}
+
+/// Parses the ID of XGROUP CREATE and SETID, where `$` stands for the last ID of the stream.
+fn parse_group_id(arg: &[u8], stream: Option<&Stream>) -> Result<StreamId, CommandError> {
+    match arg {
+        b"$" => Ok(stream.map_or(StreamId::MIN, Stream::last_id)),
+        arg => parse_id(arg, 0),
+    }
+}
+
+/// Parses the ENTRIESREAD option of XGROUP, -1 meaning the count is unknown.
+fn parse_entries_read(arg: &[u8]) -> Result<Option<u64>, CommandError> {
+    match parse_i64(arg)? {
+        -1 => Ok(None),
+        entries_read if entries_read >= 0 => Ok(Some(entries_read as u64)),
+        _ => Err(CommandError::Other("value for ENTRIESREAD must be positive or -1".to_string())),
+    }
+}
+
+fn entries_read_arg(entries_read: Option<u64>) -> Bytes {
+    Bytes::from(entries_read.map_or(-1, |entries_read| entries_read as i64).to_string())
+}
+
+/// The XCLAIM replicas receive to record a delivery exactly like it happened here: the same
+/// consumer, delivery time and count, and the same read position of the group. For an entry
+/// that no longer exists it removes the entry from their PEL instead.
+fn claim_propagation(key: &Bytes, group: &Bytes, id: StreamId, entry: &PendingEntry, last_id: StreamId) -> Vec<Bytes> {
+    vec![
+        Bytes::from_static(b"XCLAIM"),
+        key.clone(),
+        group.clone(),
+        entry.consumer.clone(),
+        Bytes::from_static(b"0"),
+        Bytes::from(id.to_string()),
+        Bytes::from_static(b"TIME"),
+        Bytes::from(entry.delivery_time.to_string()),
+        Bytes::from_static(b"RETRYCOUNT"),
+        Bytes::from(entry.delivery_count.to_string()),
+        Bytes::from_static(b"FORCE"),
+        Bytes::from_static(b"JUSTID"),
+        Bytes::from_static(b"LASTID"),
+        Bytes::from(last_id.to_string()),
+    ]
+}
+
+/// The XGROUP SETID replicas receive to move the read position of a group.
+fn set_id_propagation(key: &Bytes, group_name: &Bytes, group: &ConsumerGroup) -> Vec<Bytes> {
+    vec![
+        Bytes::from_static(b"XGROUP"),
+        Bytes::from_static(b"SETID"),
+        key.clone(),
+        group_name.clone(),
+        Bytes::from(group.last_id.to_string()),
+        Bytes::from_static(b"ENTRIESREAD"),
+        entries_read_arg(group.entries_read),
+    ]
+}
+
+fn create_consumer_propagation(key: &Bytes, group: &Bytes, consumer: &Bytes) -> Vec<Bytes> {
+    vec![
+        Bytes::from_static(b"XGROUP"),
+        Bytes::from_static(b"CREATECONSUMER"),
+        key.clone(),
+        group.clone(),
+        consumer.clone(),
+    ]
+}
+
+/// XGROUP CREATE key group id | $ [MKSTREAM] [ENTRIESREAD entries-read]
+/// XGROUP SETID key group id | $ [ENTRIESREAD entries-read]
+/// XGROUP DESTROY key group
+/// XGROUP CREATECONSUMER key group consumer
+/// XGROUP DELCONSUMER key group consumer
+pub fn xgroup(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let subcommand = arg_upper(&argv[1]);
+    let arity_matches = match subcommand.as_str() {
+        "CREATE" => (5..=8).contains(&argv.len()),
+        "SETID" => argv.len() == 5 || argv.len() == 7,
+        "DESTROY" => argv.len() == 4,
+        "CREATECONSUMER" | "DELCONSUMER" => argv.len() == 5,
+        _ => {
+            return Err(CommandError::Other(format!(
+                "unknown subcommand '{}'. Try XGROUP HELP.",
+                String::from_utf8_lossy(&argv[1])
+            )))
+        }
+    };
+    if !arity_matches {
+        return Err(CommandError::WrongArity(format!("xgroup|{}", subcommand.to_ascii_lowercase())));
+    }
+    let key = &argv[2];
+    let group_name = &argv[3];
+
+    let mut mkstream = false;
+    let mut entries_read = None;
+    let mut has_entries_read = false;
+    let mut i = 5;
+    while i < argv.len() {
+        match arg_upper(&argv[i]).as_str() {
+            "MKSTREAM" if subcommand == "CREATE" => mkstream = true,
+            "ENTRIESREAD" if i + 1 < argv.len() && matches!(subcommand.as_str(), "CREATE" | "SETID") => {
+                entries_read = parse_entries_read(&argv[i + 1])?;
+                has_entries_read = true;
+                i += 1;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+
+    let exists = ctx.store.stream(key)?.is_some();
+    if !exists && !mkstream {
+        return Err(CommandError::Other(
+            "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the \
+             MKSTREAM option to create an empty stream automatically."
+                .to_string(),
+        ));
+    }
+    if matches!(subcommand.as_str(), "SETID" | "CREATECONSUMER" | "DELCONSUMER")
+        && ctx.store.stream(key)?.and_then(|stream| stream.group(group_name)).is_none()
+    {
+        return Err(CommandError::NoGroup(format!(
+            "No such consumer group '{}' for key name '{}'",
+            String::from_utf8_lossy(group_name),
+            String::from_utf8_lossy(key)
+        )));
+    }
+
+    match subcommand.as_str() {
+        "CREATE" => {
+            let stream = ctx.store.stream(key)?;
+            let last_id = parse_group_id(&argv[4], stream)?;
+            // A group created at the end of the stream has read every entry added so far
+            if argv[4].as_ref() == b"$" && !has_entries_read {
+                entries_read = Some(stream.map_or(0, Stream::entries_added));
+            }
+            let stream = ctx.store.stream_or_create(key)?;
+            if !stream.create_group(group_name, ConsumerGroup::new(last_id, entries_read)) {
+                return Err(CommandError::BusyGroup);
+            }
+            ok()
+        }
+        "SETID" => {
+            let last_id = parse_group_id(&argv[4], ctx.store.stream(key)?)?;
+            if let Some(group) = ctx.store.stream_mut(key)?.and_then(|stream| stream.group_mut(group_name)) {
+                group.last_id = last_id;
+                group.entries_read = entries_read;
+            }
+            ok()
+        }
+        "DESTROY" => {
+            let destroyed = ctx.store.stream_mut(key)?.is_some_and(|stream| stream.destroy_group(group_name));
+            if destroyed {
+                // Clients blocked reading from the group get an error
+                ctx.store.signal_key_as_ready(key);
+            } else {
+                ctx.prevent_propagation();
+            }
+            Ok(RespValue::Integer(destroyed as i64).into())
+        }
+        "CREATECONSUMER" => {
+            let created = ctx
+                .store
+                .stream_mut(key)?
+                .and_then(|stream| stream.group_mut(group_name))
+                .is_some_and(|group| group.create_consumer(&argv[4], now_ms()));
+            if !created {
+                ctx.prevent_propagation();
+            }
+            Ok(RespValue::Integer(created as i64).into())
+        }
+        _ => {
+            let pending = ctx
+                .store
+                .stream_mut(key)?
+                .and_then(|stream| stream.group_mut(group_name))
+                .and_then(|group| group.delete_consumer(&argv[4]));
+            if pending.is_none() {
+                ctx.prevent_propagation();
+            }
+            Ok(RespValue::Integer(pending.unwrap_or(0) as i64).into())
+        }
+    }
+}
+
+/// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key
+/// [key ...] id [id ...]
+///
+/// With the ID `>` the consumer gets entries no consumer of the group was given yet, which
+/// go to its PEL until acknowledged unless NOACK. Any other ID reads back the pending
+/// entries of the consumer after it, with a null in place of the fields of entries deleted
+/// since. Replicas receive the deliveries as XCLAIMs and the new read position of the group.
+pub fn xreadgroup(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let (args, keys, ids) = parse_read_args(argv, true)?;
+    let Some((group_name, consumer)) = args.group.clone() else {
+        return Err(CommandError::Syntax);
+    };
+
+    // Every key and ID is checked before the first entry is delivered. None stands for `>`
+    let mut last_read = Vec::with_capacity(keys.len());
+    for (key, id) in keys.iter().zip(ids) {
+        if ctx.store.stream(key)?.and_then(|stream| stream.group(&group_name)).is_none() {
+            return Err(CommandError::NoGroup(format!(
+                "No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(&group_name)
+            )));
+        }
+        last_read.push(match id.as_ref() {
+            b">" => None,
+            b"$" => {
+                return Err(CommandError::Other(
+                    "The $ ID is meaningless in the context of XREADGROUP: you want to read the history of \
+                     this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID \
+                     would just return an empty result set."
+                        .to_string(),
+                ))
+            }
+            id => Some(parse_id(id, 0)?),
+        });
+    }
+
+    let now = now_ms();
+    let mut propagation = Vec::new();
+    let mut reply = Vec::new();
+    for (key, last_read) in keys.iter().zip(last_read) {
+        let Some(stream) = ctx.store.stream_mut(key)? else {
+            continue;
+        };
+        let Some(group) = stream.group_mut(&group_name) else {
+            continue;
+        };
+        if group.touch_consumer(&consumer, now) {
+            propagation.push(create_consumer_propagation(key, &group_name, &consumer));
+        }
+
+        let entries = match last_read {
+            None => {
+                let delivered = stream.read_group(&group_name, &consumer, args.count, args.no_ack, now);
+                if delivered.is_empty() {
+                    continue;
+                }
+                let Some(group) = stream.group(&group_name) else {
+                    continue;
+                };
+                if !args.no_ack {
+                    for (id, _) in &delivered {
+                        if let Some(entry) = group.pending.get(id) {
+                            propagation.push(claim_propagation(key, &group_name, *id, entry, group.last_id));
+                        }
+                    }
+                }
+                propagation.push(set_id_propagation(key, &group_name, group));
+                delivered.iter().map(|(id, fields)| entry_reply(id, fields)).collect()
+            }
+            Some(last_read) => {
+                let pending: Vec<StreamId> = match (last_read.next(), group.consumers.get(&consumer)) {
+                    (Some(start), Some(consumer)) => consumer.pending.range(start..).take(args.count).copied().collect(),
+                    _ => Vec::new(),
+                };
+                let found: Vec<(StreamId, Option<Fields>)> =
+                    pending.into_iter().map(|id| (id, stream.get(id).cloned())).collect();
+                let mut group = stream.group_mut(&group_name);
+                let mut entries = Vec::with_capacity(found.len());
+                for (id, fields) in found {
+                    let Some(fields) = fields else {
+                        // Deleted from the stream while pending
+                        entries.push(RespValue::Array(vec![id_reply(id), RespValue::NullArray]));
+                        continue;
+                    };
+                    // Reading it again counts as another delivery
+                    if let Some(entry) = group.as_mut().and_then(|group| group.pending.get_mut(&id)) {
+                        entry.delivery_time = now;
+                        entry.delivery_count += 1;
+                    }
+                    entries.push(entry_reply(&id, &fields));
+                }
+                entries
+            }
+        };
+        reply.push(stream_reply(key, entries));
+    }
+
+    ctx.prevent_propagation();
+    for argv in propagation {
+        ctx.rewrite_propagation(argv);
+    }
+    if !reply.is_empty() {
+        return Ok(RespValue::Array(reply).into());
+    }
+    let Some(deadline) = args.deadline else {
+        return Ok(RespValue::NullArray.into());
+    };
+    Ok(CommandResponse::Block(BlockOn {
+        keys: keys.to_vec(),
+        value_type: "stream",
+        deadline,
+        timeout_reply: RespValue::NullArray,
+        retry_argv: None,
+    }))
+}
+
+/// XACK key group id [id ...]. Replies with the number of entries removed from the PEL.
+pub fn xack(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let ids = argv[3..]
+        .iter()
+        .map(|arg| parse_id(arg, 0))
+        .collect::<Result<Vec<StreamId>, CommandError>>()?;
+    let acknowledged = match ctx.store.stream_mut(&argv[1])?.and_then(|stream| stream.group_mut(&argv[2])) {
+        Some(group) => ids.into_iter().filter(|&id| group.remove_pending(id).is_some()).count(),
+        None => 0,
+    };
+    if acknowledged == 0 {
+        ctx.prevent_propagation();
+    }
+    Ok(RespValue::Integer(acknowledged as i64).into())
+}
+
+/// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+///
+/// The short form summarizes the PEL of the group: its size, smallest and greatest ID, and
+/// how many entries each consumer has pending. The extended form lists the pending entries
+/// within the interval with their consumer, idle time and delivery count.
+pub fn xpending(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let (key, group_name) = (&argv[1], &argv[2]);
+    let mut min_idle = 0;
+    let mut i = 3;
+    if argv.len() >= 8 && arg_upper(&argv[3]) == "IDLE" {
+        min_idle = parse_i64(&argv[4])?.max(0) as u64;
+        i = 5;
+    }
+    let extended = argv.len() > 3;
+    if extended && !(3..=4).contains(&(argv.len() - i)) {
+        return Err(CommandError::Syntax);
+    }
+    let interval = if extended {
+        let start = parse_interval_id(&argv[i], true)?;
+        let end = parse_interval_id(&argv[i + 1], false)?;
+        let count = parse_i64(&argv[i + 2])?.max(0) as usize;
+        Some((start, end, count, argv.get(i + 3)))
+    } else {
+        None
+    };
+
+    let Some(group) = ctx.store.stream(key)?.and_then(|stream| stream.group(group_name)) else {
+        return Err(no_such_group(key, group_name));
+    };
+    let Some((start, end, count, consumer)) = interval else {
+        let (Some((first, _)), Some((last, _))) = (group.pending.first_key_value(), group.pending.last_key_value())
+        else {
+            return Ok(RespValue::Array(vec![
+                RespValue::Integer(0),
+                RespValue::Null,
+                RespValue::Null,
+                RespValue::NullArray,
+            ])
+            .into());
+        };
+        let consumers = group
+            .consumers
+            .iter()
+            .filter(|(_, consumer)| !consumer.pending.is_empty())
+            .map(|(name, consumer)| {
+                RespValue::Array(vec![
+                    RespValue::BinaryBulkString(name.clone()),
+                    RespValue::BulkString(consumer.pending.len().to_string()),
+                ])
+            })
+            .collect();
+        return Ok(RespValue::Array(vec![
+            RespValue::Integer(group.pending.len() as i64),
+            id_reply(*first),
+            id_reply(*last),
+            RespValue::Array(consumers),
+        ])
+        .into());
+    };
+
+    if start > end {
+        return Ok(RespValue::Array(Vec::new()).into());
+    }
+    let now = now_ms();
+    let entries = group
+        .pending
+        .range(start..=end)
+        .filter(|(_, entry)| consumer.is_none_or(|consumer| entry.consumer == *consumer))
+        .map(|(id, entry)| (id, entry, now.saturating_sub(entry.delivery_time)))
+        .filter(|&(_, _, idle)| idle >= min_idle)
+        .take(count)
+        .map(|(id, entry, idle)| {
+            RespValue::Array(vec![
+                id_reply(*id),
+                RespValue::BinaryBulkString(entry.consumer.clone()),
+                RespValue::Integer(idle as i64),
+                RespValue::Integer(entry.delivery_count as i64),
+            ])
+        })
+        .collect();
+    Ok(RespValue::Array(entries).into())
+}
+
+/// Parses the min-idle-time of XCLAIM and XAUTOCLAIM, negative values counting as 0.
+fn parse_min_idle(arg: &[u8], command: &str) -> Result<u64, CommandError> {
+    parse_i64(arg)
+        .map(|min_idle| min_idle.max(0) as u64)
+        .map_err(|_| CommandError::Other(format!("Invalid min-idle-time argument for {}", command)))
+}
+
+/// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds]
+/// [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID lastid]
+///
+/// Hands the pending entries idle for at least min-idle-time over to `consumer`, counting
+/// it as a new delivery unless JUSTID. FORCE also claims entries that are not pending yet.
+/// Entries deleted from the stream are dropped from the PEL instead.
+pub fn xclaim(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let (key, group_name, consumer) = (&argv[1], &argv[2], &argv[3]);
+    if stream_with_group(ctx, key, group_name)?.is_none() {
+        return Err(no_such_group(key, group_name));
+    }
+    let min_idle = parse_min_idle(&argv[4], "XCLAIM")?;
+    let mut i = 5;
+    let mut ids = Vec::new();
+    while let Some(id) = argv.get(i).and_then(|arg| parse_id(arg, 0).ok()) {
+        ids.push(id);
+        i += 1;
+    }
+
+    let now = now_ms();
+    let mut delivery_time = None;
+    let mut retry_count = None;
+    let mut force = false;
+    let mut just_id = false;
+    let mut last_id = None;
+    while i < argv.len() {
+        let option = arg_upper(&argv[i]);
+        let value = argv.get(i + 1);
+        let integer = |name: &str| {
+            value
+                .and_then(|value| parse_i64(value).ok())
+                .ok_or_else(|| CommandError::Other(format!("Invalid {} option argument for XCLAIM", name)))
+        };
+        match option.as_str() {
+            "IDLE" if value.is_some() => delivery_time = Some(now as i64 - integer("IDLE")?),
+            "TIME" if value.is_some() => delivery_time = Some(integer("TIME")?),
+            "RETRYCOUNT" if value.is_some() => retry_count = Some(integer("RETRYCOUNT")?.max(0) as u64),
+            "LASTID" if value.is_some() => last_id = value.map(|value| parse_id(value, 0)).transpose()?,
+            "FORCE" => force = true,
+            "JUSTID" => just_id = true,
+            _ => {
+                return Err(CommandError::Other(format!(
+                    "Unrecognized XCLAIM option '{}'",
+                    String::from_utf8_lossy(&argv[i])
+                )))
+            }
+        }
+        i += if matches!(option.as_str(), "FORCE" | "JUSTID") { 1 } else { 2 };
+    }
+    // A delivery time in the future, or before the epoch, is taken as now
+    let delivery_time = delivery_time.filter(|&time| (0..=now as i64).contains(&time)).map_or(now, |time| time as u64);
+
+    let Some(stream) = stream_with_group(ctx, key, group_name)? else {
+        return Err(no_such_group(key, group_name));
+    };
+    let mut propagation = Vec::new();
+    if let Some(group) = stream.group_mut(group_name) {
+        if let Some(last_id) = last_id.filter(|&last_id| last_id > group.last_id) {
+            group.last_id = last_id;
+            propagation.push(set_id_propagation(key, group_name, group));
+        }
+        if group.touch_consumer(consumer, now) {
+            propagation.push(create_consumer_propagation(key, group_name, consumer));
+        }
+    }
+    let mut reply = Vec::new();
+    for id in ids {
+        let fields = stream.get(id).cloned();
+        let Some(group) = stream.group_mut(group_name) else {
+            break;
+        };
+        match (group.pending.get(&id), &fields) {
+            (None, Some(_)) if force => {}
+            (None, _) => continue,
+            (Some(_), None) => {
+                if let Some(entry) = group.remove_pending(id) {
+                    propagation.push(claim_propagation(key, group_name, id, &entry, group.last_id));
+                }
+                continue;
+            }
+            (Some(entry), Some(_)) => {
+                if min_idle > 0 && now.saturating_sub(entry.delivery_time) < min_idle {
+                    continue;
+                }
+            }
+        }
+        let entry = group.assign(id, consumer, now);
+        entry.delivery_time = delivery_time;
+        match retry_count {
+            Some(retry_count) => entry.delivery_count = retry_count,
+            None if !just_id => entry.delivery_count += 1,
+            None => {}
+        }
+        let entry = entry.clone();
+        propagation.push(claim_propagation(key, group_name, id, &entry, group.last_id));
+        if let Some(consumer) = group.consumers.get_mut(consumer) {
+            consumer.active_time = Some(now);
+        }
+        reply.push(match fields {
+            Some(fields) if !just_id => entry_reply(&id, &fields),
+            _ => id_reply(id),
+        });
+    }
+
+    ctx.prevent_propagation();
+    for argv in propagation {
+        ctx.rewrite_propagation(argv);
+    }
+    Ok(RespValue::Array(reply).into())
+}
+
+/// XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]
+///
+/// Like XCLAIM for the first `count` pending entries from `start` idle for long enough,
+/// scanning at most ten times as many. Replies with the cursor to continue the scan from,
+/// 0-0 once it went through the whole PEL, the claimed entries and the IDs of the entries
+/// it dropped from the PEL because they no longer exist.
+pub fn xautoclaim(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    const ATTEMPTS_FACTOR: usize = 10;
+    let (key, group_name, consumer) = (&argv[1], &argv[2], &argv[3]);
+    if stream_with_group(ctx, key, group_name)?.is_none() {
+        return Err(no_such_group(key, group_name));
+    }
+    let min_idle = parse_min_idle(&argv[4], "XAUTOCLAIM")?;
+    let start = parse_interval_id(&argv[5], true)?;
+    let mut count = 100;
+    let mut just_id = false;
+    let mut i = 6;
+    while i < argv.len() {
+        match arg_upper(&argv[i]).as_str() {
+            "COUNT" if i + 1 < argv.len() => {
+                count = parse_i64(&argv[i + 1])
+                    .ok()
+                    .filter(|&count| count >= 1 && count as usize <= usize::MAX / ATTEMPTS_FACTOR)
+                    .ok_or_else(|| CommandError::Other("COUNT must be > 0".to_string()))?
+                    as usize;
+                i += 1;
+            }
+            "JUSTID" => just_id = true,
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+
+    let Some(stream) = stream_with_group(ctx, key, group_name)? else {
+        return Err(no_such_group(key, group_name));
+    };
+    let now = now_ms();
+    let mut attempts = count * ATTEMPTS_FACTOR;
+    let candidates: Vec<StreamId> = stream
+        .group(group_name)
+        .map(|group| group.pending.range(start..).take(attempts + 1).map(|(id, _)| *id).collect())
+        .unwrap_or_default();
+    let mut propagation = Vec::new();
+    if let Some(group) = stream.group_mut(group_name) {
+        if group.touch_consumer(consumer, now) {
+            propagation.push(create_consumer_propagation(key, group_name, consumer));
+        }
+    }
+    let mut claimed = Vec::new();
+    let mut deleted = Vec::new();
+    let mut cursor = StreamId::MIN;
+    for id in candidates {
+        if attempts == 0 || count == 0 {
+            cursor = id;
+            break;
+        }
+        attempts -= 1;
+        let fields = stream.get(id).cloned();
+        let Some(group) = stream.group_mut(group_name) else {
+            break;
+        };
+        let Some(fields) = fields else {
+            if let Some(entry) = group.remove_pending(id) {
+                propagation.push(claim_propagation(key, group_name, id, &entry, group.last_id));
+            }
+            deleted.push(id_reply(id));
+            continue;
+        };
+        let idle = group.pending.get(&id).map_or(0, |entry| now.saturating_sub(entry.delivery_time));
+        if min_idle > 0 && idle < min_idle {
+            continue;
+        }
+        let entry = group.assign(id, consumer, now);
+        entry.delivery_time = now;
+        if !just_id {
+            entry.delivery_count += 1;
+        }
+        let entry = entry.clone();
+        propagation.push(claim_propagation(key, group_name, id, &entry, group.last_id));
+        if let Some(consumer) = group.consumers.get_mut(consumer) {
+            consumer.active_time = Some(now);
+        }
+        claimed.push(if just_id { id_reply(id) } else { entry_reply(&id, &fields) });
+        count -= 1;
+    }
+
+    ctx.prevent_propagation();
+    for argv in propagation {
+        ctx.rewrite_propagation(argv);
+    }
+    Ok(RespValue::Array(vec![
+        id_reply(cursor),
+        RespValue::Array(claimed),
+        RespValue::Array(deleted),
+    ])
+    .into())
+}
+
+/// XINFO STREAM key [FULL [COUNT count]]
+/// XINFO GROUPS key
+/// XINFO CONSUMERS key group
+pub fn xinfo(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let subcommand = arg_upper(&argv[1]);
+    let arity_matches = match subcommand.as_str() {
+        "STREAM" => argv.len() >= 3,
+        "GROUPS" => argv.len() == 3,
+        "CONSUMERS" => argv.len() == 4,
+        _ => {
+            return Err(CommandError::Other(format!(
+                "unknown subcommand '{}'. Try XINFO HELP.",
+                String::from_utf8_lossy(&argv[1])
+            )))
+        }
+    };
+    if !arity_matches {
+        return Err(CommandError::WrongArity(format!("xinfo|{}", subcommand.to_ascii_lowercase())));
+    }
+
+    // FULL lists the first 10 entries and PEL entries by default, COUNT 0 lists them all
+    let full_count = match &argv[3.min(argv.len())..] {
+        _ if subcommand != "STREAM" => None,
+        [] => None,
+        [full] if full.eq_ignore_ascii_case(b"FULL") => Some(10),
+        [full, option, count] if full.eq_ignore_ascii_case(b"FULL") && option.eq_ignore_ascii_case(b"COUNT") => {
+            match parse_i64(count)? {
+                0 => Some(usize::MAX),
+                count if count > 0 => Some(count as usize),
+                _ => Some(10),
+            }
+        }
+        _ => return Err(CommandError::Syntax),
+    };
+
+    let Some(stream) = ctx.store.stream(&argv[2])? else {
+        return Err(CommandError::NoSuchKey);
+    };
+    let now = now_ms();
+    let reply = match subcommand.as_str() {
+        "STREAM" => stream_info(stream, full_count),
+        "GROUPS" => RespValue::Array(
+            stream
+                .groups()
+                .iter()
+                .map(|(name, group)| {
+                    map_reply(vec![
+                        ("name", RespValue::BinaryBulkString(name.clone())),
+                        ("consumers", RespValue::Integer(group.consumers.len() as i64)),
+                        ("pending", RespValue::Integer(group.pending.len() as i64)),
+                        ("last-delivered-id", id_reply(group.last_id)),
+                        ("entries-read", optional_integer_reply(group.entries_read)),
+                        ("lag", optional_integer_reply(stream.lag(group))),
+                    ])
+                })
+                .collect(),
+        ),
+        _ => {
+            let Some(group) = stream.group(&argv[3]) else {
+                return Err(CommandError::NoGroup(format!(
+                    "No such consumer group '{}' for key name '{}'",
+                    String::from_utf8_lossy(&argv[3]),
+                    String::from_utf8_lossy(&argv[2])
+                )));
+            };
+            RespValue::Array(
+                group
+                    .consumers
+                    .iter()
+                    .map(|(name, consumer)| {
+                        let inactive = consumer.active_time.map_or(-1, |time| now.saturating_sub(time) as i64);
+                        map_reply(vec![
+                            ("name", RespValue::BinaryBulkString(name.clone())),
+                            ("pending", RespValue::Integer(consumer.pending.len() as i64)),
+                            ("idle", RespValue::Integer(now.saturating_sub(consumer.seen_time) as i64)),
+                            ("inactive", RespValue::Integer(inactive)),
+                        ])
+                    })
+                    .collect(),
+            )
+        }
+    };
+    Ok(reply.into())
+}
+
+/// The reply of XINFO STREAM, or of XINFO STREAM FULL listing up to `full_count` entries and
+/// pending entries of every group and consumer.
+fn stream_info(stream: &Stream, full_count: Option<usize>) -> RespValue {
+    let mut info = vec![
+        ("length", RespValue::Integer(stream.len() as i64)),
+        ("radix-tree-keys", RespValue::Integer(stream.node_count() as i64)),
+        ("radix-tree-nodes", RespValue::Integer(stream.node_count() as i64)),
+        ("last-generated-id", id_reply(stream.last_id())),
+        ("max-deleted-entry-id", id_reply(stream.max_deleted_id())),
+        ("entries-added", RespValue::Integer(stream.entries_added() as i64)),
+        ("recorded-first-entry-id", id_reply(stream.first_id())),
+    ];
+    let Some(count) = full_count else {
+        let entry = |entry: Option<(&StreamId, &Fields)>| entry.map_or(RespValue::Null, |(id, fields)| entry_reply(id, fields));
+        info.extend([
+            ("groups", RespValue::Integer(stream.groups().len() as i64)),
+            ("first-entry", entry(stream.first_entry())),
+            ("last-entry", entry(stream.last_entry())),
+        ]);
+        return map_reply(info);
+    };
+
+    let entries = stream
+        .range(StreamId::MIN, StreamId::MAX, false)
+        .take(count)
+        .map(|(id, fields)| entry_reply(id, fields))
+        .collect();
+    let groups = stream
+        .groups()
+        .iter()
+        .map(|(name, group)| group_info(stream, name, group, count))
+        .collect();
+    info.extend([("entries", RespValue::Array(entries)), ("groups", RespValue::Array(groups))]);
+    map_reply(info)
+}
+
+fn group_info(stream: &Stream, name: &Bytes, group: &ConsumerGroup, count: usize) -> RespValue {
+    let pending = group
+        .pending
+        .iter()
+        .take(count)
+        .map(|(id, entry)| {
+            RespValue::Array(vec![
+                id_reply(*id),
+                RespValue::BinaryBulkString(entry.consumer.clone()),
+                RespValue::Integer(entry.delivery_time as i64),
+                RespValue::Integer(entry.delivery_count as i64),
+            ])
+        })
+        .collect();
+    let consumers = group
+        .consumers
+        .iter()
+        .map(|(name, consumer)| {
+            let pending = consumer
+                .pending
+                .iter()
+                .take(count)
+                .filter_map(|id| group.pending.get(id).map(|entry| (id, entry)))
+                .map(|(id, entry)| {
+                    RespValue::Array(vec![
+                        id_reply(*id),
+                        RespValue::Integer(entry.delivery_time as i64),
+                        RespValue::Integer(entry.delivery_count as i64),
+                    ])
+                })
+                .collect();
+            map_reply(vec![
+                ("name", RespValue::BinaryBulkString(name.clone())),
+                ("seen-time", RespValue::Integer(consumer.seen_time as i64)),
+                ("active-time", RespValue::Integer(consumer.active_time.map_or(-1, |time| time as i64))),
+                ("pel-count", RespValue::Integer(consumer.pending.len() as i64)),
+                ("pending", RespValue::Array(pending)),
+            ])
+        })
+        .collect();
+    map_reply(vec![
+        ("name", RespValue::BinaryBulkString(name.clone())),
+        ("last-delivered-id", id_reply(group.last_id)),
+        ("entries-read", optional_integer_reply(group.entries_read)),
+        ("lag", optional_integer_reply(stream.lag(group))),
+        ("pel-count", RespValue::Integer(group.pending.len() as i64)),
+        ("pending", RespValue::Array(pending)),
+        ("consumers", RespValue::Array(consumers)),
+    ])
+}
@@ -0,0 +1,544 @@
+use super::expire::pexpireat_command;
+use super::{arg_upper, format_f64_fixed, ok, parse_f64, parse_i64, CommandContext, CommandResult};
+use crate::client::cache_store::now_ms;
+use crate::client::error::CommandError;
+use crate::client::model::RespValue;
+use bytes::Bytes;
+
+pub fn get(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    match ctx.store.get(&argv[1])? {
+        // An empty string is a valid value and must not be confused with a missing key
+        Some(value) => Ok(RespValue::BinaryBulkString(value).into()),
+        None => Ok(RespValue::Null.into()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetCondition {
+    Always,
+    IfNotExists, // NX
+    IfExists,    // XX
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetExpiry {
+    Clear,
+    KeepTtl,
+    At(u64), // Unix time in milliseconds
+}
+
+#[derive(Debug)]
+struct SetOptions {
+    condition: SetCondition,
+    expiry: SetExpiry,
+    get: bool,
+}
+
+/// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | KEEPTTL]
+pub fn set(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let options = parse_set_options(&argv[3..])?;
+    // SET overwrites keys of any type, only GET needs the old value to be a string
+    let old_value = if options.get { ctx.store.get(key)? } else { None };
+    let exists = ctx.store.contains_key(key);
+
+    let allowed = match options.condition {
+        SetCondition::Always => true,
+        SetCondition::IfNotExists => !exists,
+        SetCondition::IfExists => exists,
+    };
+
+    if allowed {
+        match options.expiry {
+            SetExpiry::Clear => ctx.store.set(key.clone(), argv[2].clone(), None),
+            SetExpiry::At(expires_at) => ctx.store.set(key.clone(), argv[2].clone(), Some(expires_at)),
+            SetExpiry::KeepTtl => ctx.store.set_keep_ttl(key.clone(), argv[2].clone()),
+        }
+        ctx.rewrite_propagation(set_command(argv, options.expiry));
+    } else {
+        ctx.prevent_propagation();
+    }
+
+    // With GET the reply is always the previous value, whether or not the write happened
+    if options.get {
+        return Ok(old_value.map_or(RespValue::Null, RespValue::BinaryBulkString).into());
+    }
+    if allowed {
+        ok()
+    } else {
+        Ok(RespValue::Null.into())
+    }
+}
+
+/// SET as propagated: key, value and the TTL as an absolute PXAT deadline, so that replicas
+/// do not count a relative TTL from when they apply it. NX, XX and GET were settled here and
+/// are left out.
+fn set_command(argv: &[Bytes], expiry: SetExpiry) -> Vec<Bytes> {
+    let mut command = vec![Bytes::from_static(b"SET"), argv[1].clone(), argv[2].clone()];
+    match expiry {
+        SetExpiry::Clear => {}
+        SetExpiry::KeepTtl => command.push(Bytes::from_static(b"KEEPTTL")),
+        SetExpiry::At(expires_at) => {
+            command.extend([Bytes::from_static(b"PXAT"), Bytes::from(expires_at.to_string())]);
+        }
+    }
+    command
+}
+
+fn parse_set_options(args: &[Bytes]) -> Result<SetOptions, CommandError> {
+    let mut options = SetOptions {
+        condition: SetCondition::Always,
+        expiry: SetExpiry::Clear,
+        get: false,
+    };
+    let mut expiry_seen = false;
+
+    let mut i = 0;
+    while i < args.len() {
+        let option = arg_upper(&args[i]);
+        match option.as_str() {
+            "NX" if options.condition != SetCondition::IfExists => options.condition = SetCondition::IfNotExists,
+            "XX" if options.condition != SetCondition::IfNotExists => options.condition = SetCondition::IfExists,
+            "GET" if !options.get => options.get = true,
+            "KEEPTTL" if !expiry_seen => {
+                options.expiry = SetExpiry::KeepTtl;
+                expiry_seen = true;
+            }
+            "EX" | "PX" | "EXAT" | "PXAT" if !expiry_seen && i + 1 < args.len() => {
+                let expires_at = parse_expire_time("set", &option, &args[i + 1])?;
+                options.expiry = SetExpiry::At(expires_at);
+                expiry_seen = true;
+                i += 1;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+
+    Ok(options)
+}
+
+/// Turns the argument of EX, PX, EXAT or PXAT into an absolute deadline in Unix milliseconds.
+/// Absolute times in the past are accepted, so the key is written already expired.
+fn parse_expire_time(command: &str, unit: &str, arg: &[u8]) -> Result<u64, CommandError> {
+    let invalid = || CommandError::InvalidExpireTime(command.to_string());
+    let value = parse_i64(arg)?;
+    if value <= 0 {
+        return Err(invalid());
+    }
+    let millis = match unit {
+        "EX" | "EXAT" => value.checked_mul(1000).ok_or_else(invalid)?,
+        _ => value,
+    };
+
+    match unit {
+        "EX" | "PX" => millis.checked_add(now_ms() as i64).map(|at| at as u64).ok_or_else(invalid),
+        _ => Ok(millis as u64),
+    }
+}
+
+// Largest string SETRANGE and APPEND may produce, Redis' default proto-max-bulk-len
+const MAX_STRING_LENGTH: usize = 512 * 1024 * 1024;
+
+pub fn incr(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    incr_decr(ctx, &argv[1], 1)
+}
+
+pub fn decr(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    incr_decr(ctx, &argv[1], -1)
+}
+
+pub fn incrby(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let increment = parse_i64(&argv[2])?;
+    incr_decr(ctx, &argv[1], increment)
+}
+
+pub fn decrby(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let decrement = parse_i64(&argv[2])?;
+    let increment = decrement
+        .checked_neg()
+        .ok_or_else(|| CommandError::Other("decrement would overflow".to_string()))?;
+    incr_decr(ctx, &argv[1], increment)
+}
+
+/// Adds `increment` to the integer stored at `key`, treating a missing key as 0. The key
+/// keeps its TTL.
+fn incr_decr(ctx: &mut CommandContext<'_>, key: &Bytes, increment: i64) -> CommandResult {
+    let current = match ctx.store.get(key)? {
+        Some(value) => parse_i64(&value)?,
+        None => 0,
+    };
+    let updated = current.checked_add(increment).ok_or(CommandError::Overflow)?;
+    ctx.store.set_keep_ttl(key.clone(), Bytes::from(updated.to_string()));
+    Ok(RespValue::Integer(updated).into())
+}
+
+pub fn incrbyfloat(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let increment = parse_f64(&argv[2])?;
+    let current = match ctx.store.get(key)? {
+        Some(value) => parse_f64(&value)?,
+        None => 0.0,
+    };
+    let updated = current + increment;
+    if !updated.is_finite() {
+        return Err(CommandError::Other("increment would produce NaN or Infinity".to_string()));
+    }
+    let formatted = Bytes::from(format_f64_fixed(updated));
+    ctx.store.set_keep_ttl(key.clone(), formatted.clone());
+    Ok(RespValue::BinaryBulkString(formatted).into())
+}
+
+/// APPEND key value. Creates the key when missing and replies with the new length.
+pub fn append(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let updated = match ctx.store.get(key)? {
+        Some(current) => {
+            check_string_length(current.len() + argv[2].len())?;
+            let mut updated = Vec::with_capacity(current.len() + argv[2].len());
+            updated.extend_from_slice(&current);
+            updated.extend_from_slice(&argv[2]);
+            Bytes::from(updated)
+        }
+        None => argv[2].clone(),
+    };
+    let length = updated.len();
+    ctx.store.set_keep_ttl(key.clone(), updated);
+    Ok(RespValue::Integer(length as i64).into())
+}
+
+pub fn strlen(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let length = ctx.store.get(&argv[1])?.map_or(0, |value| value.len());
+    Ok(RespValue::Integer(length as i64).into())
+}
+
+/// GETRANGE key start end. Negative offsets count from the end, and the range is clamped
+/// to the string, so out of range requests yield an empty string instead of an error.
+pub fn getrange(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let mut start = parse_i64(&argv[2])?;
+    let mut end = parse_i64(&argv[3])?;
+    let value = ctx.store.get(&argv[1])?.unwrap_or_default();
+    let length = value.len() as i64;
+
+    if (start < 0 && end < 0 && start > end) || length == 0 {
+        return Ok(RespValue::BinaryBulkString(Bytes::new()).into());
+    }
+    if start < 0 {
+        start += length;
+    }
+    if end < 0 {
+        end += length;
+    }
+    start = start.max(0);
+    end = end.max(0).min(length - 1);
+    if start > end {
+        return Ok(RespValue::BinaryBulkString(Bytes::new()).into());
+    }
+    Ok(RespValue::BinaryBulkString(value.slice(start as usize..=end as usize)).into())
+}
+
+/// SETRANGE key offset value. Writing past the end pads the string with zero bytes.
+pub fn setrange(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let offset = parse_i64(&argv[2])?;
+    if offset < 0 {
+        return Err(CommandError::Other("offset is out of range".to_string()));
+    }
+    let offset = offset as usize;
+    let patch = &argv[3];
+
+    let current = ctx.store.get(key)?;
+    if patch.is_empty() {
+        // Nothing to write: report the current length without creating the key
+        return Ok(RespValue::Integer(current.map_or(0, |value| value.len()) as i64).into());
+    }
+    check_string_length(offset + patch.len())?;
+
+    let mut updated = current.map(|value| value.to_vec()).unwrap_or_default();
+    if updated.len() < offset + patch.len() {
+        updated.resize(offset + patch.len(), 0);
+    }
+    updated[offset..offset + patch.len()].copy_from_slice(patch);
+    let length = updated.len();
+    ctx.store.set_keep_ttl(key.clone(), Bytes::from(updated));
+    Ok(RespValue::Integer(length as i64).into())
+}
+
+fn check_string_length(length: usize) -> Result<(), CommandError> {
+    if length > MAX_STRING_LENGTH {
+        return Err(CommandError::Other(
+            "string exceeds maximum allowed size (proto-max-bulk-len)".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// MGET key [key ...]
+pub fn mget(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let values = argv[1..]
+        .iter()
+        // Keys holding other types read as missing rather than failing the whole MGET
+        .map(|key| ctx.store.get(key).ok().flatten().map_or(RespValue::Null, RespValue::BinaryBulkString))
+        .collect();
+    Ok(RespValue::Array(values).into())
+}
+
+/// MSET key value [key value ...]. All pairs are written under one store lock, so no client
+/// can observe a partially applied MSET.
+pub fn mset(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    if argv.len().is_multiple_of(2) {
+        return Err(CommandError::WrongArity("mset".to_string()));
+    }
+    for pair in argv[1..].chunks(2) {
+        ctx.store.set(pair[0].clone(), pair[1].clone(), None);
+    }
+    ok()
+}
+
+/// MSETNX key value [key value ...]. Writes nothing unless none of the keys exist.
+pub fn msetnx(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    if argv.len().is_multiple_of(2) {
+        return Err(CommandError::WrongArity("msetnx".to_string()));
+    }
+    if argv[1..].iter().step_by(2).any(|key| ctx.store.contains_key(key)) {
+        return Ok(RespValue::Integer(0).into());
+    }
+    for pair in argv[1..].chunks(2) {
+        ctx.store.set(pair[0].clone(), pair[1].clone(), None);
+    }
+    Ok(RespValue::Integer(1).into())
+}
+
+pub fn getdel(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let value = ctx.store.get(&argv[1])?;
+    if value.is_some() {
+        ctx.store.remove(&argv[1]);
+    }
+    Ok(value.map_or(RespValue::Null, RespValue::BinaryBulkString).into())
+}
+
+/// GETSET key value. Like SET, the new value replaces the old one without its TTL.
+pub fn getset(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let old_value = ctx.store.get(&argv[1])?;
+    ctx.store.set(argv[1].clone(), argv[2].clone(), None);
+    Ok(old_value.map_or(RespValue::Null, RespValue::BinaryBulkString).into())
+}
+
+/// GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | PERSIST]
+pub fn getex(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let key = &argv[1];
+    let mut expiry = None;
+
+    let mut i = 2;
+    while i < argv.len() {
+        let option = arg_upper(&argv[i]);
+        match option.as_str() {
+            "PERSIST" if expiry.is_none() => expiry = Some(SetExpiry::Clear),
+            "EX" | "PX" | "EXAT" | "PXAT" if expiry.is_none() && i + 1 < argv.len() => {
+                expiry = Some(SetExpiry::At(parse_expire_time("getex", &option, &argv[i + 1])?));
+                i += 1;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+
+    let value = match ctx.store.get(key)? {
+        Some(value) => value,
+        None => {
+            ctx.prevent_propagation();
+            return Ok(RespValue::Null.into());
+        }
+    };
+    match expiry {
+        Some(SetExpiry::At(expires_at)) if expires_at <= now_ms() => {
+            ctx.store.remove(key);
+            ctx.rewrite_propagation(vec![Bytes::from_static(b"DEL"), key.clone()]);
+        }
+        Some(SetExpiry::At(expires_at)) => {
+            ctx.store.set_expires_at(key, Some(expires_at));
+            ctx.rewrite_propagation(pexpireat_command(key, expires_at));
+        }
+        Some(SetExpiry::Clear) => {
+            ctx.store.set_expires_at(key, None);
+            ctx.rewrite_propagation(vec![Bytes::from_static(b"PERSIST"), key.clone()]);
+        }
+        Some(SetExpiry::KeepTtl) | None => ctx.prevent_propagation(),
+    }
+    Ok(RespValue::BinaryBulkString(value).into())
+}
+
+/// LCS key1 key2 [LEN] [IDX] [MINMATCHLEN min-match-len] [WITHMATCHLEN]
+///
+/// Classic dynamic programming longest common subsequence. The table is walked back from
+/// the end of both strings, so IDX reports the matching ranges from last to first, the same
+/// order Redis uses.
+pub fn lcs(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let mut get_len = false;
+    let mut get_idx = false;
+    let mut with_match_len = false;
+    let mut min_match_len = 0usize;
+
+    let mut i = 3;
+    while i < argv.len() {
+        match arg_upper(&argv[i]).as_str() {
+            "LEN" => get_len = true,
+            "IDX" => get_idx = true,
+            "WITHMATCHLEN" => with_match_len = true,
+            "MINMATCHLEN" if i + 1 < argv.len() => {
+                min_match_len = parse_i64(&argv[i + 1])?.max(0) as usize;
+                i += 1;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+    if get_len && get_idx {
+        return Err(CommandError::Other(
+            "If you want both the length and indexes, please just use IDX.".to_string(),
+        ));
+    }
+
+    let string_value = |result: Result<Option<Bytes>, CommandError>| {
+        result
+            .map(Option::unwrap_or_default)
+            .map_err(|_| CommandError::Other("The specified keys must contain string values".to_string()))
+    };
+    let a = string_value(ctx.store.get(&argv[1]))?;
+    let b = string_value(ctx.store.get(&argv[2]))?;
+    let (alen, blen) = (a.len(), b.len());
+    let cells = (alen + 1)
+        .checked_mul(blen + 1)
+        .filter(|&cells| cells < u32::MAX as usize && cells * 4 <= MAX_STRING_LENGTH)
+        .ok_or_else(|| {
+            CommandError::Other("Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len".to_string())
+        })?;
+
+    // dp[i][j] is the LCS length of a[..i] and b[..j]
+    let width = blen + 1;
+    let mut dp = vec![0u32; cells];
+    for i in 1..=alen {
+        for j in 1..=blen {
+            dp[i * width + j] = if a[i - 1] == b[j - 1] {
+                dp[(i - 1) * width + j - 1] + 1
+            } else {
+                dp[(i - 1) * width + j].max(dp[i * width + j - 1])
+            };
+        }
+    }
+    let lcs_len = dp[alen * width + blen] as usize;
+
+    if get_len {
+        return Ok(RespValue::Integer(lcs_len as i64).into());
+    }
+
+    let mut result = vec![0u8; lcs_len];
+    let mut matches = Vec::new();
+    // `alen` as the start of the A range means "no range being tracked"
+    let (mut arange_start, mut arange_end, mut brange_start, mut brange_end) = (alen, 0, 0, 0);
+    let (mut i, mut j, mut idx) = (alen, blen, lcs_len);
+    while i > 0 && j > 0 {
+        let mut emit_range = false;
+        if a[i - 1] == b[j - 1] {
+            result[idx - 1] = a[i - 1];
+            if arange_start == alen {
+                arange_start = i - 1;
+                arange_end = i - 1;
+                brange_start = j - 1;
+                brange_end = j - 1;
+            } else if arange_start == i && brange_start == j {
+                // Contiguous with the range being tracked: extend it backwards
+                arange_start -= 1;
+                brange_start -= 1;
+            } else {
+                emit_range = true;
+            }
+            if arange_start == 0 || brange_start == 0 {
+                emit_range = true;
+            }
+            idx -= 1;
+            i -= 1;
+            j -= 1;
+        } else {
+            if dp[(i - 1) * width + j] > dp[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+            if arange_start != alen {
+                emit_range = true;
+            }
+        }
+
+        if emit_range {
+            let match_len = arange_end - arange_start + 1;
+            if get_idx && (min_match_len == 0 || match_len >= min_match_len) {
+                let mut entry = vec![
+                    RespValue::Array(vec![
+                        RespValue::Integer(arange_start as i64),
+                        RespValue::Integer(arange_end as i64),
+                    ]),
+                    RespValue::Array(vec![
+                        RespValue::Integer(brange_start as i64),
+                        RespValue::Integer(brange_end as i64),
+                    ]),
+                ];
+                if with_match_len {
+                    entry.push(RespValue::Integer(match_len as i64));
+                }
+                matches.push(RespValue::Array(entry));
+            }
+            arange_start = alen;
+        }
+    }
+
+    if get_idx {
+        return Ok(RespValue::Array(vec![
+            RespValue::BulkString("matches".to_string()),
+            RespValue::Array(matches),
+            RespValue::BulkString("len".to_string()),
+            RespValue::Integer(lcs_len as i64),
+        ])
+        .into());
+    }
+    Ok(RespValue::BinaryBulkString(Bytes::from(result)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())).collect()
+    }
+
+    #[test]
+    fn set_options_reject_repeats_and_conflicts() {
+        let options = parse_set_options(&args(&["nx", "get", "pxat", "100"])).unwrap();
+        assert_eq!(options.condition, SetCondition::IfNotExists);
+        assert!(options.get);
+        assert_eq!(options.expiry, SetExpiry::At(100));
+        for invalid in [
+            &["GET", "GET"][..],
+            &["NX", "XX"],
+            &["EX", "1", "PX", "1"],
+            &["KEEPTTL", "EX", "1"],
+            &["EX"],
+        ] {
+            assert!(matches!(parse_set_options(&args(invalid)), Err(CommandError::Syntax)), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn set_is_propagated_without_conditions_and_with_an_absolute_deadline() {
+        let argv = args(&["set", "k", "v", "NX", "GET", "EX", "10"]);
+        assert_eq!(set_command(&argv, SetExpiry::Clear), args(&["SET", "k", "v"]));
+        assert_eq!(set_command(&argv, SetExpiry::KeepTtl), args(&["SET", "k", "v", "KEEPTTL"]));
+        assert_eq!(
+            set_command(&argv, SetExpiry::At(1_700_000_000_000)),
+            args(&["SET", "k", "v", "PXAT", "1700000000000"])
+        );
+    }
+}
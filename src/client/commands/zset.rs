@@ -0,0 +1,743 @@
+use super::{
+    arg_upper, format_f64, parse_f64, parse_i64, parse_timeout, BlockOn, CommandContext, CommandResponse, CommandResult,
+};
+use crate::client::cache_store::CacheValue;
+use crate::client::error::CommandError;
+use crate::client::model::RespValue;
+use crate::client::types::set::Set;
+use crate::client::types::zset::{LexBound, LexRange, ScoreRange, SortedSet};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::time::Instant;
+
+fn score_reply(score: f64) -> RespValue {
+    RespValue::BulkString(format_f64(score))
+}
+
+fn nan_error() -> CommandError {
+    CommandError::Other("resulting score is not a number (NaN)".to_string())
+}
+
+/// Parses the `min` and `max` of a score range, each a float optionally prefixed by `(` to
+/// exclude it.
+fn parse_score_range(min: &[u8], max: &[u8]) -> Result<ScoreRange, CommandError> {
+    let parse = |arg: &[u8]| match arg.strip_prefix(b"(") {
+        Some(value) => parse_f64(value).map(|value| (value, true)),
+        None => parse_f64(arg).map(|value| (value, false)),
+    };
+    let error = |_| CommandError::Other("min or max is not a float".to_string());
+    let (min, min_exclusive) = parse(min).map_err(error)?;
+    let (max, max_exclusive) = parse(max).map_err(error)?;
+    Ok(ScoreRange {
+        min,
+        max,
+        min_exclusive,
+        max_exclusive,
+    })
+}
+
+/// Parses the `min` and `max` of a lexicographical range: `-`, `+`, or a member prefixed
+/// by `[` (inclusive) or `(` (exclusive).
+fn parse_lex_range(min: &[u8], max: &[u8]) -> Result<LexRange, CommandError> {
+    let parse = |arg: &[u8]| match arg {
+        b"-" => Some(LexBound::Min),
+        b"+" => Some(LexBound::Max),
+        [b'[', member @ ..] => Some(LexBound::Inclusive(Bytes::copy_from_slice(member))),
+        [b'(', member @ ..] => Some(LexBound::Exclusive(Bytes::copy_from_slice(member))),
+        _ => None,
+    };
+    match (parse(min), parse(max)) {
+        (Some(min), Some(max)) => Ok(LexRange { min, max }),
+        _ => Err(CommandError::Other("min or max not valid string range item".to_string())),
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct ZaddFlags {
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+    ch: bool,
+    incr: bool,
+}
+
+/// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+///
+/// Replies with the number of members added, or added and updated with CH. With INCR it
+/// behaves like ZINCRBY and replies with the new score, or nil if a condition blocked it.
+pub fn zadd(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let mut flags = ZaddFlags::default();
+    let mut i = 2;
+    while i < argv.len() {
+        match arg_upper(&argv[i]).as_str() {
+            "NX" => flags.nx = true,
+            "XX" => flags.xx = true,
+            "GT" => flags.gt = true,
+            "LT" => flags.lt = true,
+            "CH" => flags.ch = true,
+            "INCR" => flags.incr = true,
+            _ => break,
+        }
+        i += 1;
+    }
+
+    let elements = &argv[i..];
+    if elements.is_empty() || !elements.len().is_multiple_of(2) {
+        return Err(CommandError::Syntax);
+    }
+    if flags.nx && flags.xx {
+        return Err(CommandError::Other("XX and NX options at the same time are not compatible".to_string()));
+    }
+    if [flags.nx, flags.gt, flags.lt].iter().filter(|&&set| set).count() > 1 {
+        return Err(CommandError::Other(
+            "GT, LT, and/or NX options at the same time are not compatible".to_string(),
+        ));
+    }
+    if flags.incr && elements.len() > 2 {
+        return Err(CommandError::Other("INCR option supports a single increment-element pair".to_string()));
+    }
+    zadd_generic(ctx, &argv[1], flags, elements)
+}
+
+/// ZINCRBY key increment member
+pub fn zincrby(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let flags = ZaddFlags {
+        incr: true,
+        ..ZaddFlags::default()
+    };
+    zadd_generic(ctx, &argv[1], flags, &argv[2..])
+}
+
+fn zadd_generic(ctx: &mut CommandContext<'_>, key: &Bytes, flags: ZaddFlags, elements: &[Bytes]) -> CommandResult {
+    // Every score is validated before the first one is applied
+    let scores = elements
+        .iter()
+        .step_by(2)
+        .map(|score| parse_f64(score))
+        .collect::<Result<Vec<f64>, CommandError>>()?;
+
+    let no_change = || if flags.incr { RespValue::Null } else { RespValue::Integer(0) };
+    let zset = if flags.xx {
+        match ctx.store.zset_mut(key)? {
+            Some(zset) => zset,
+            None => return Ok(no_change().into()),
+        }
+    } else {
+        ctx.store.zset_or_create(key)?
+    };
+
+    let (mut added, mut updated) = (0, 0);
+    let mut incr_result = None;
+    for (score, member) in scores.into_iter().zip(elements.iter().skip(1).step_by(2)) {
+        match zset.score(member) {
+            Some(current) => {
+                if flags.nx {
+                    continue;
+                }
+                let score = if flags.incr { current + score } else { score };
+                if score.is_nan() {
+                    return Err(nan_error());
+                }
+                if (flags.lt && score >= current) || (flags.gt && score <= current) {
+                    continue;
+                }
+                if score != current {
+                    zset.insert(member.clone(), score);
+                    updated += 1;
+                }
+                incr_result = Some(score);
+            }
+            None if flags.xx => continue,
+            None => {
+                zset.insert(member.clone(), score);
+                added += 1;
+                incr_result = Some(score);
+            }
+        }
+    }
+
+    if added + updated == 0 {
+        ctx.prevent_propagation();
+    }
+    if flags.incr {
+        return Ok(incr_result.map_or(RespValue::Null, score_reply).into());
+    }
+    let changed = if flags.ch { added + updated } else { added };
+    Ok(RespValue::Integer(changed).into())
+}
+
+/// ZREM key member [member ...]
+pub fn zrem(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let Some(zset) = ctx.store.zset_mut(&argv[1])? else {
+        return Ok(RespValue::Integer(0).into());
+    };
+    let removed = argv[2..].iter().filter(|member| zset.remove(member)).count();
+    ctx.store.remove_if_empty(&argv[1]);
+    Ok(RespValue::Integer(removed as i64).into())
+}
+
+/// ZSCORE key member
+pub fn zscore(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let score = ctx.store.zset(&argv[1])?.and_then(|zset| zset.score(&argv[2]));
+    Ok(score.map_or(RespValue::Null, score_reply).into())
+}
+
+/// ZMSCORE key member [member ...]
+pub fn zmscore(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let zset = ctx.store.zset(&argv[1])?;
+    let scores = argv[2..]
+        .iter()
+        .map(|member| {
+            zset.and_then(|zset| zset.score(member))
+                .map_or(RespValue::Null, score_reply)
+        })
+        .collect();
+    Ok(RespValue::Array(scores).into())
+}
+
+/// ZCARD key
+pub fn zcard(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let len = ctx.store.zset(&argv[1])?.map_or(0, SortedSet::len);
+    Ok(RespValue::Integer(len as i64).into())
+}
+
+/// ZCOUNT key min max
+pub fn zcount(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let range = parse_score_range(&argv[2], &argv[3])?;
+    let count = ctx.store.zset(&argv[1])?.map_or(0, |zset| zset.count(&range));
+    Ok(RespValue::Integer(count as i64).into())
+}
+
+/// ZRANK key member [WITHSCORE]
+pub fn zrank(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    zrank_generic(ctx, argv, false)
+}
+
+/// ZREVRANK key member [WITHSCORE]
+pub fn zrevrank(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    zrank_generic(ctx, argv, true)
+}
+
+fn zrank_generic(ctx: &mut CommandContext<'_>, argv: &[Bytes], reverse: bool) -> CommandResult {
+    let with_score = match argv.len() {
+        3 => false,
+        4 if arg_upper(&argv[3]) == "WITHSCORE" => true,
+        _ => return Err(CommandError::Syntax),
+    };
+    let Some(zset) = ctx.store.zset(&argv[1])? else {
+        return Ok(if with_score { RespValue::NullArray } else { RespValue::Null }.into());
+    };
+    let rank = zset
+        .rank(&argv[2], reverse)
+        .zip(zset.score(&argv[2]));
+    Ok(match rank {
+        Some((rank, score)) if with_score => RespValue::Array(vec![RespValue::Integer(rank as i64), score_reply(score)]),
+        Some((rank, _)) => RespValue::Integer(rank as i64),
+        None if with_score => RespValue::NullArray,
+        None => RespValue::Null,
+    }
+    .into())
+}
+
+/// How ZRANGE interprets its `start` and `stop` arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeType {
+    Rank,
+    Score,
+    Lex,
+}
+
+/// A parsed ZRANGE request, also shared with ZRANGESTORE.
+struct RangeSpec {
+    range_type: RangeType,
+    reverse: bool,
+    limit: Option<(i64, i64)>,
+    with_scores: bool,
+}
+
+/// Parses the options of `ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count]
+/// [WITHSCORES]`, `args` starting after `stop`. ZRANGESTORE (`store`) takes no WITHSCORES.
+fn parse_range_spec(args: &[Bytes], store: bool) -> Result<RangeSpec, CommandError> {
+    let mut spec = RangeSpec {
+        range_type: RangeType::Rank,
+        reverse: false,
+        limit: None,
+        with_scores: false,
+    };
+    let mut i = 0;
+    while i < args.len() {
+        match arg_upper(&args[i]).as_str() {
+            "WITHSCORES" if !store => spec.with_scores = true,
+            "LIMIT" if i + 2 < args.len() => {
+                spec.limit = Some((parse_i64(&args[i + 1])?, parse_i64(&args[i + 2])?));
+                i += 2;
+            }
+            "BYSCORE" => spec.range_type = RangeType::Score,
+            "BYLEX" => spec.range_type = RangeType::Lex,
+            "REV" => spec.reverse = true,
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+
+    if spec.limit.is_some() && spec.range_type == RangeType::Rank {
+        return Err(CommandError::Other(
+            "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX".to_string(),
+        ));
+    }
+    if spec.with_scores && spec.range_type == RangeType::Lex {
+        return Err(CommandError::Other(
+            "syntax error, WITHSCORES not supported in combination with BYLEX".to_string(),
+        ));
+    }
+    Ok(spec)
+}
+
+/// Runs a parsed ZRANGE against the sorted set at `key`. By score and by member the range
+/// is given highest first when reversed, as in `ZRANGE key max min BYSCORE REV`.
+fn zrange_generic(
+    ctx: &mut CommandContext<'_>,
+    key: &[u8],
+    start: &[u8],
+    stop: &[u8],
+    spec: &RangeSpec,
+) -> Result<Vec<(Bytes, f64)>, CommandError> {
+    let (min, max) = if spec.reverse { (stop, start) } else { (start, stop) };
+    enum Range {
+        Rank(i64, i64),
+        Score(ScoreRange),
+        Lex(LexRange),
+    }
+    // The range is validated before the key is looked up
+    let range = match spec.range_type {
+        RangeType::Rank => Range::Rank(parse_i64(start)?, parse_i64(stop)?),
+        RangeType::Score => Range::Score(parse_score_range(min, max)?),
+        RangeType::Lex => Range::Lex(parse_lex_range(min, max)?),
+    };
+    let Some(zset) = ctx.store.zset(key)? else {
+        return Ok(Vec::new());
+    };
+
+    let (offset, limit) = match spec.limit {
+        Some((offset, _)) if offset < 0 => return Ok(Vec::new()),
+        Some((offset, count)) => (offset as usize, (count >= 0).then_some(count as usize)),
+        None => (0, None),
+    };
+    Ok(match range {
+        Range::Rank(start, stop) => {
+            let len = zset.len() as i64;
+            let start = if start < 0 { (start + len).max(0) } else { start };
+            let stop = if stop < 0 { stop + len } else { stop.min(len - 1) };
+            if start > stop || start >= len {
+                return Ok(Vec::new());
+            }
+            zset.range_by_rank(start as usize, stop as usize, spec.reverse)
+        }
+        Range::Score(range) => zset.range(&range, spec.reverse, offset, limit),
+        Range::Lex(range) => zset.range(&range, spec.reverse, offset, limit),
+    })
+}
+
+/// ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES]
+pub fn zrange(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let spec = parse_range_spec(&argv[4..], false)?;
+    let elements = zrange_generic(ctx, &argv[1], &argv[2], &argv[3], &spec)?;
+    let reply = elements
+        .into_iter()
+        .flat_map(|(member, score)| {
+            let score = spec.with_scores.then(|| score_reply(score));
+            std::iter::once(RespValue::BinaryBulkString(member)).chain(score)
+        })
+        .collect();
+    Ok(RespValue::Array(reply).into())
+}
+
+/// ZRANGESTORE destination source start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count].
+/// An empty range deletes the destination.
+pub fn zrangestore(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let spec = parse_range_spec(&argv[5..], true)?;
+    let elements = zrange_generic(ctx, &argv[2], &argv[3], &argv[4], &spec)?;
+    Ok(RespValue::Integer(store_result(ctx, &argv[1], elements.into_iter().collect()) as i64).into())
+}
+
+/// Stores a computed sorted set under `destination`, replacing whatever was there along
+/// with its TTL, or deletes the destination if the result is empty. Returns its size.
+fn store_result(ctx: &mut CommandContext<'_>, destination: &Bytes, result: SortedSet) -> usize {
+    let len = result.len();
+    if result.is_empty() {
+        ctx.store.remove(destination);
+    } else {
+        ctx.store.insert_entry(destination.clone(), CacheValue::SortedSet(result), None);
+    }
+    len
+}
+
+/// How ZUNIONSTORE and ZINTERSTORE combine the weighted scores of a member found in more
+/// than one input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            // inf + -inf counts as 0, a score is never NaN
+            Aggregate::Sum => Some(a + b).filter(|sum| !sum.is_nan()).unwrap_or(0.0),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetOperation {
+    Union,
+    Inter,
+    Diff,
+}
+
+/// An input of ZUNIONSTORE and friends: a sorted set, or a plain set whose members all
+/// score 1.
+#[derive(Clone, Copy)]
+enum ScoredInput<'a> {
+    Set(&'a Set),
+    SortedSet(&'a SortedSet),
+}
+
+impl<'a> ScoredInput<'a> {
+    fn len(self) -> usize {
+        match self {
+            ScoredInput::Set(set) => set.len(),
+            ScoredInput::SortedSet(zset) => zset.len(),
+        }
+    }
+
+    fn score(self, member: &[u8]) -> Option<f64> {
+        match self {
+            ScoredInput::Set(set) => set.contains(member).then_some(1.0),
+            ScoredInput::SortedSet(zset) => zset.score(member),
+        }
+    }
+
+    fn iter(self) -> Box<dyn Iterator<Item = (Bytes, f64)> + 'a> {
+        match self {
+            ScoredInput::Set(set) => Box::new(set.iter().map(|member| (member, 1.0))),
+            ScoredInput::SortedSet(zset) => Box::new(zset.iter()),
+        }
+    }
+}
+
+/// ZUNIONSTORE destination numkeys key [key ...] [WEIGHTS weight [weight ...]]
+/// [AGGREGATE SUM | MIN | MAX]
+pub fn zunionstore(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    zset_operation_store(ctx, argv, SetOperation::Union)
+}
+
+/// ZINTERSTORE destination numkeys key [key ...] [WEIGHTS weight [weight ...]]
+/// [AGGREGATE SUM | MIN | MAX]
+pub fn zinterstore(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    zset_operation_store(ctx, argv, SetOperation::Inter)
+}
+
+/// ZDIFFSTORE destination numkeys key [key ...]. The members of the first input that are in
+/// none of the others, with their scores in the first.
+pub fn zdiffstore(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    zset_operation_store(ctx, argv, SetOperation::Diff)
+}
+
+fn zset_operation_store(ctx: &mut CommandContext<'_>, argv: &[Bytes], operation: SetOperation) -> CommandResult {
+    let numkeys = parse_i64(&argv[2])?;
+    if numkeys < 1 {
+        return Err(CommandError::Other(format!(
+            "at least 1 input key is needed for '{}' command",
+            String::from_utf8_lossy(&argv[0]).to_lowercase()
+        )));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys > argv.len() - 3 {
+        return Err(CommandError::Syntax);
+    }
+    let inputs = ctx
+        .store
+        .values(&argv[3..3 + numkeys])
+        .into_iter()
+        .map(|value| match value {
+            Some(CacheValue::Set(set)) => Ok(Some(ScoredInput::Set(set))),
+            Some(CacheValue::SortedSet(zset)) => Ok(Some(ScoredInput::SortedSet(zset))),
+            Some(_) => Err(CommandError::WrongType),
+            None => Ok(None),
+        })
+        .collect::<Result<Vec<_>, CommandError>>()?;
+
+    let mut weights = vec![1.0; numkeys];
+    let mut aggregate = Aggregate::Sum;
+    let options = &argv[3 + numkeys..];
+    let mut i = 0;
+    while i < options.len() {
+        let remaining = options.len() - i - 1;
+        match arg_upper(&options[i]).as_str() {
+            "WEIGHTS" if operation != SetOperation::Diff && remaining >= numkeys => {
+                for (weight, arg) in weights.iter_mut().zip(&options[i + 1..=i + numkeys]) {
+                    *weight = parse_f64(arg).map_err(|_| CommandError::Other("weight value is not a float".to_string()))?;
+                }
+                i += numkeys;
+            }
+            "AGGREGATE" if operation != SetOperation::Diff && remaining >= 1 => {
+                aggregate = match arg_upper(&options[i + 1]).as_str() {
+                    "SUM" => Aggregate::Sum,
+                    "MIN" => Aggregate::Min,
+                    "MAX" => Aggregate::Max,
+                    _ => return Err(CommandError::Syntax),
+                };
+                i += 1;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+
+    let result = combine(&inputs, &weights, aggregate, operation);
+    Ok(RespValue::Integer(store_result(ctx, &argv[1], result) as i64).into())
+}
+
+fn combine(inputs: &[Option<ScoredInput>], weights: &[f64], aggregate: Aggregate, operation: SetOperation) -> SortedSet {
+    // A weight of 0 times an infinite score counts as 0
+    let weighted = |score: f64, weight: f64| Some(score * weight).filter(|score| !score.is_nan()).unwrap_or(0.0);
+    match operation {
+        SetOperation::Union => {
+            let mut scores: HashMap<Bytes, f64> = HashMap::new();
+            for (input, &weight) in inputs.iter().zip(weights) {
+                for (member, score) in input.iter().flat_map(|input| input.iter()) {
+                    let score = weighted(score, weight);
+                    scores
+                        .entry(member)
+                        .and_modify(|total| *total = aggregate.apply(*total, score))
+                        .or_insert(score);
+                }
+            }
+            scores.into_iter().collect()
+        }
+        SetOperation::Inter => {
+            // A missing input is empty, and so is the intersection
+            let Some(mut inputs) = inputs
+                .iter()
+                .zip(weights)
+                .map(|(input, &weight)| input.map(|input| (input, weight)))
+                .collect::<Option<Vec<_>>>()
+            else {
+                return SortedSet::default();
+            };
+            // Walking the smallest input bounds the number of lookups
+            inputs.sort_by_key(|(input, _)| input.len());
+            let Some(((smallest, weight), rest)) = inputs.split_first() else {
+                return SortedSet::default();
+            };
+            smallest
+                .iter()
+                .filter_map(|(member, score)| {
+                    let mut total = weighted(score, *weight);
+                    for (input, weight) in rest {
+                        total = aggregate.apply(total, weighted(input.score(&member)?, *weight));
+                    }
+                    Some((member, total))
+                })
+                .collect()
+        }
+        SetOperation::Diff => {
+            let Some((Some(first), rest)) = inputs.split_first() else {
+                return SortedSet::default();
+            };
+            first
+                .iter()
+                .filter(|(member, _)| !rest.iter().flatten().any(|input| input.score(member).is_some()))
+                .collect()
+        }
+    }
+}
+
+/// The end of a sorted set a pop takes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PopEnd {
+    Min,
+    Max,
+}
+
+impl PopEnd {
+    fn parse(arg: &[u8]) -> Result<PopEnd, CommandError> {
+        match arg_upper(arg).as_str() {
+            "MIN" => Ok(PopEnd::Min),
+            "MAX" => Ok(PopEnd::Max),
+            _ => Err(CommandError::Syntax),
+        }
+    }
+
+    /// The plain pop command popping from this end.
+    fn pop_command(self) -> Bytes {
+        Bytes::from_static(match self {
+            PopEnd::Min => b"ZPOPMIN",
+            PopEnd::Max => b"ZPOPMAX",
+        })
+    }
+}
+
+fn pop_many(zset: &mut SortedSet, end: PopEnd, count: usize) -> Vec<(Bytes, f64)> {
+    (0..count).map_while(|_| zset.pop(end == PopEnd::Max)).collect()
+}
+
+/// ZPOPMIN key [count]
+pub fn zpopmin(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    zpop_generic(ctx, argv, PopEnd::Min)
+}
+
+/// ZPOPMAX key [count]
+pub fn zpopmax(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    zpop_generic(ctx, argv, PopEnd::Max)
+}
+
+/// Replies with a flat array of members and scores, whether or not a count was given.
+fn zpop_generic(ctx: &mut CommandContext<'_>, argv: &[Bytes], end: PopEnd) -> CommandResult {
+    if argv.len() > 3 {
+        return Err(CommandError::Syntax);
+    }
+    let count = match argv.get(2) {
+        Some(arg) => {
+            let count = parse_i64(arg)?;
+            if count < 0 {
+                return Err(CommandError::Other("value is out of range, must be positive".to_string()));
+            }
+            count as usize
+        }
+        None => 1,
+    };
+
+    let key = &argv[1];
+    let Some(zset) = ctx.store.zset_mut(key)? else {
+        return Ok(RespValue::Array(Vec::new()).into());
+    };
+    let elements = pop_many(zset, end, count);
+    ctx.store.remove_if_empty(key);
+    if elements.is_empty() {
+        ctx.prevent_propagation();
+    }
+    let reply = elements
+        .into_iter()
+        .flat_map(|(member, score)| [RespValue::BinaryBulkString(member), score_reply(score)])
+        .collect();
+    Ok(RespValue::Array(reply).into())
+}
+
+/// BZPOPMIN key [key ...] timeout. Pops the lowest scored element of the first non-empty
+/// sorted set, or waits for one of them to receive an element.
+pub fn bzpopmin(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    blocking_zpop_generic(ctx, argv, PopEnd::Min)
+}
+
+/// BZPOPMAX key [key ...] timeout
+pub fn bzpopmax(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    blocking_zpop_generic(ctx, argv, PopEnd::Max)
+}
+
+fn blocking_zpop_generic(ctx: &mut CommandContext<'_>, argv: &[Bytes], end: PopEnd) -> CommandResult {
+    let deadline = parse_timeout(&argv[argv.len() - 1])?;
+    let keys = &argv[1..argv.len() - 1];
+    for key in keys {
+        let Some((member, score)) = ctx.store.zset_mut(key)?.and_then(|zset| zset.pop(end == PopEnd::Max)) else {
+            continue;
+        };
+        ctx.store.remove_if_empty(key);
+        ctx.rewrite_propagation(vec![end.pop_command(), key.clone()]);
+        return Ok(RespValue::Array(vec![
+            RespValue::BinaryBulkString(key.clone()),
+            RespValue::BinaryBulkString(member),
+            score_reply(score),
+        ])
+        .into());
+    }
+    Ok(block_on(keys.to_vec(), deadline))
+}
+
+/// ZMPOP numkeys key [key ...] MIN | MAX [COUNT count]. Pops from the first non-empty sorted
+/// set and replies with its name and the popped elements.
+pub fn zmpop(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let (keys, end, count) = parse_mpop_args(&argv[1..])?;
+    Ok(mpop(ctx, keys, end, count)?.unwrap_or(RespValue::NullArray).into())
+}
+
+/// BZMPOP timeout numkeys key [key ...] MIN | MAX [COUNT count], the blocking ZMPOP.
+pub fn bzmpop(ctx: &mut CommandContext<'_>, argv: &[Bytes]) -> CommandResult {
+    let deadline = parse_timeout(&argv[1])?;
+    let (keys, end, count) = parse_mpop_args(&argv[2..])?;
+    match mpop(ctx, keys, end, count)? {
+        Some(reply) => Ok(reply.into()),
+        None => Ok(block_on(keys.to_vec(), deadline)),
+    }
+}
+
+/// Pops up to `count` elements from the first non-empty sorted set of `keys`, replying with
+/// its name and the popped member and score pairs. Propagates as the equivalent ZPOPMIN or
+/// ZPOPMAX with a count.
+fn mpop(ctx: &mut CommandContext<'_>, keys: &[Bytes], end: PopEnd, count: usize) -> Result<Option<RespValue>, CommandError> {
+    for key in keys {
+        let Some(zset) = ctx.store.zset_mut(key)? else {
+            continue;
+        };
+        let elements = pop_many(zset, end, count);
+        ctx.store.remove_if_empty(key);
+        ctx.rewrite_propagation(vec![end.pop_command(), key.clone(), Bytes::from(elements.len().to_string())]);
+        let elements = elements
+            .into_iter()
+            .map(|(member, score)| RespValue::Array(vec![RespValue::BinaryBulkString(member), score_reply(score)]))
+            .collect();
+        return Ok(Some(RespValue::Array(vec![
+            RespValue::BinaryBulkString(key.clone()),
+            RespValue::Array(elements),
+        ])));
+    }
+    Ok(None)
+}
+
+fn block_on(keys: Vec<Bytes>, deadline: Option<Instant>) -> CommandResponse {
+    CommandResponse::Block(BlockOn {
+        keys,
+        value_type: "zset",
+        deadline,
+        timeout_reply: RespValue::NullArray,
+        retry_argv: None,
+    })
+}
+
+/// Parses `numkeys key [key ...] MIN | MAX [COUNT count]`.
+fn parse_mpop_args(args: &[Bytes]) -> Result<(&[Bytes], PopEnd, usize), CommandError> {
+    let numkeys = parse_i64(&args[0])
+        .ok()
+        .filter(|&numkeys| numkeys > 0)
+        .ok_or_else(|| CommandError::Other("numkeys should be greater than 0".to_string()))?;
+    let numkeys = numkeys as usize;
+    // The keys must be followed by at least MIN or MAX
+    if numkeys >= args.len() - 1 {
+        return Err(CommandError::Syntax);
+    }
+    let keys = &args[1..=numkeys];
+    let end = PopEnd::parse(&args[numkeys + 1])?;
+
+    let mut count = None;
+    let mut i = numkeys + 2;
+    while i < args.len() {
+        match arg_upper(&args[i]).as_str() {
+            "COUNT" if count.is_none() && i + 1 < args.len() => {
+                let value = parse_i64(&args[i + 1])
+                    .ok()
+                    .filter(|&count| count > 0)
+                    .ok_or_else(|| CommandError::Other("count should be greater than 0".to_string()))?;
+                count = Some(value as usize);
+                i += 2;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+    Ok((keys, end, count.unwrap_or(1)))
+}
@@ -0,0 +1,101 @@
+// Server settings given on the command line.
+
+use std::path::PathBuf;
+
+/// The settings of the server that are not about replication, such as where the RDB file
+/// lives (`--dir /var/lib/redis --dbfilename dump.rdb`) and when it is written, or whether
+/// writes are logged to an append-only file (`--appendonly yes --appendfsync always`).
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub dir: String,
+    pub dbfilename: String,
+    pub save_points: Vec<SavePoint>,
+    pub appendonly: bool,
+    pub appendfilename: String,
+    pub appendfsync: AppendFsync,
+    // Load an AOF whose last command was cut short instead of refusing to start
+    pub aof_load_truncated: bool,
+}
+
+/// When the AOF is flushed to the disk, trading durability for write throughput.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppendFsync {
+    Always,   // after every write, nothing acknowledged is ever lost
+    EverySec, // once a second in the background, at most a second of writes is lost
+    No,       // whenever the operating system sees fit
+}
+
+impl AppendFsync {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "always" => Some(AppendFsync::Always),
+            "everysec" => Some(AppendFsync::EverySec),
+            "no" => Some(AppendFsync::No),
+            _ => None,
+        }
+    }
+}
+
+/// A `save <seconds> <changes>` rule: snapshot the dataset in the background once at least
+/// `changes` writes happened and `seconds` passed since the last successful save.
+#[derive(Debug, Clone, Copy)]
+pub struct SavePoint {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            dir: ".".to_string(),
+            dbfilename: "dump.rdb".to_string(),
+            // The rules redis-server starts with when no config file sets any
+            save_points: vec![
+                SavePoint { seconds: 3600, changes: 1 },
+                SavePoint { seconds: 300, changes: 100 },
+                SavePoint { seconds: 60, changes: 10000 },
+            ],
+            appendonly: false,
+            appendfilename: "appendonly.aof".to_string(),
+            appendfsync: AppendFsync::EverySec,
+            aof_load_truncated: true,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn rdb_path(&self) -> PathBuf {
+        PathBuf::from(&self.dir).join(&self.dbfilename)
+    }
+
+    pub fn aof_path(&self) -> PathBuf {
+        PathBuf::from(&self.dir).join(&self.appendfilename)
+    }
+}
+
+/// Parses a boolean setting, given as `yes` or `no`.
+pub fn parse_yes_no(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses the value of `--save`: pairs of seconds and changes, such as `"900 1 300 10"`. An
+/// empty value disables snapshotting.
+pub fn parse_save_points(value: &str) -> Option<Vec<SavePoint>> {
+    let numbers = value
+        .split_whitespace()
+        .map(|number| number.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if numbers.len() % 2 != 0 {
+        return None;
+    }
+    Some(
+        numbers
+            .chunks_exact(2)
+            .map(|pair| SavePoint { seconds: pair[0], changes: pair[1] })
+            .collect(),
+    )
+}
@@ -1,29 +1,27 @@
-use super::cache_store::CacheStore;
+use super::aof::{self, AppendOnlyFile};
+use super::cache_store::{CacheStore, ExpireCycle, ExpireCycleReport};
 use super::codec::RespCodec;
+use super::commands::{self, Blocked, CommandOrigin, CommandResponse};
+use super::config::ServerConfig;
 use super::model::RespValue;
-use std::io::{BufReader, BufWriter, Write, BufRead, Read};
-use std::net::{TcpListener, TcpStream};
+use super::persistence::{self, Persistence};
+use super::rdb;
+use bytes::Bytes;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
 use tokio::task;
 
-// Empty RDB file content (hex decoded)
-const EMPTY_RDB_FILE: &[u8] = &[
-    0x52, 0x45, 0x44, 0x49, 0x53, 0x30, 0x30, 0x31, 0x31, 0xfa, 0x09, 0x72, 0x65, 0x64, 0x69, 0x73,
-    0x2d, 0x76, 0x65, 0x72, 0x05, 0x37, 0x2e, 0x32, 0x2e, 0x30, 0xfa, 0x0a, 0x72, 0x65, 0x64, 0x69,
-    0x73, 0x2d, 0x62, 0x69, 0x74, 0x73, 0xc0, 0x40, 0xfa, 0x05, 0x63, 0x74, 0x69, 0x6d, 0x65, 0xc2,
-    0x6d, 0x08, 0xbc, 0x65, 0xfa, 0x08, 0x75, 0x73, 0x65, 0x64, 0x2d, 0x6d, 0x65, 0x6d, 0xc2, 0xb0,
-    0xc4, 0x10, 0x00, 0xfa, 0x08, 0x61, 0x6f, 0x66, 0x2d, 0x62, 0x61, 0x73, 0x65, 0xc0, 0x00, 0xff,
-    0xf0, 0x6e, 0x3b, 0xfe, 0xc0, 0xff, 0x5a, 0xa2
-];
-
-#[derive(Debug)]
-enum CommandResponse {
-    Normal(RespValue),
-    PsyncWithRdb(RespValue), // FULLRESYNC response followed by RDB file
-}
+// Frequency of the server cron that drives background tasks such as active expiration
+const SERVER_HZ: u64 = 10;
+// Minimum spacing between two fast expire cycles
+const FAST_EXPIRE_CYCLE_INTERVAL: Duration = Duration::from_millis(2);
 
 #[derive(Debug, Clone)]
+#[allow(dead_code)] // Not every replication field is reported by INFO yet
 pub struct ReplicaConfig {
     pub master_host: Option<String>,
     pub master_port: Option<u16>,
@@ -36,51 +34,144 @@ pub struct ReplicaConfig {
 }
 
 // Structure to hold replica connection information
+// Propagated commands are queued on a channel and written by a dedicated task that owns the
+// replica socket, so a slow replica never stalls the client that issued the write.
 #[derive(Debug)]
-struct ReplicaConnection {
-    writer: Arc<Mutex<BufWriter<TcpStream>>>,
+pub struct ReplicaConnection {
+    sender: mpsc::UnboundedSender<Vec<u8>>,
 }
 
 impl ReplicaConnection {
-    fn new(stream: TcpStream) -> Self {
-        let writer = BufWriter::new(stream);
-        ReplicaConnection {
-            writer: Arc::new(Mutex::new(writer)),
-        }
+    /// A replica whose propagated commands queue up on the returned receiver until
+    /// [`ReplicaConnection::stream`] starts writing them.
+    fn new() -> (Self, mpsc::UnboundedReceiver<Vec<u8>>) {
+        let (sender, receiver) = mpsc::unbounded_channel::<Vec<u8>>();
+        (ReplicaConnection { sender }, receiver)
+    }
+
+    /// Writes the queued commands to the replica socket, then every command queued after.
+    fn stream(mut writer: BufWriter<OwnedWriteHalf>, mut receiver: mpsc::UnboundedReceiver<Vec<u8>>) {
+        task::spawn(async move {
+            while let Some(encoded) = receiver.recv().await {
+                if let Err(e) = writer.write_all(&encoded).await {
+                    eprintln!("Failed to write to replica: {}", e);
+                    break;
+                }
+                // Coalesce whatever else is already queued into the same flush
+                while let Ok(encoded) = receiver.try_recv() {
+                    if let Err(e) = writer.write_all(&encoded).await {
+                        eprintln!("Failed to write to replica: {}", e);
+                        return;
+                    }
+                }
+                if let Err(e) = writer.flush().await {
+                    eprintln!("Failed to flush replica stream: {}", e);
+                    break;
+                }
+            }
+        });
     }
 
     fn propagate_command(&self, command: &RespValue) -> std::io::Result<()> {
-        let mut writer = self.writer.lock().unwrap();
         let encoded = RespCodec::encode(command);
-        writer.write_all(&encoded)?;
-        writer.flush()?;
+        self.sender
+            .send(encoded)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "replica connection closed"))?;
         println!("Propagated command to replica: {:?}", command);
         Ok(())
     }
 }
 
+/// What a replica is sent on a full resynchronization: a point-in-time snapshot of the
+/// keyspace, and the writes executed since it was taken, held back until it is sent.
+#[derive(Debug)]
+pub struct FullResync {
+    snapshot: rdb::Snapshot,
+    backlog: mpsc::UnboundedReceiver<Vec<u8>>,
+}
+
+/// State shared by every connection task of one server instance.
+pub struct ServerState {
+    pub data_store: Mutex<CacheStore>,
+    pub config: ServerConfig,
+    pub replica_config: Option<ReplicaConfig>,
+    pub replica_connections: Mutex<Vec<ReplicaConnection>>,
+    pub persistence: Arc<Mutex<Persistence>>,
+    // Open once the dataset is loaded, when appendonly is enabled
+    pub aof: Mutex<Option<AppendOnlyFile>>,
+}
+
+impl ServerState {
+    pub fn new(config: ServerConfig, replica_config: Option<ReplicaConfig>) -> Self {
+        ServerState {
+            data_store: Mutex::new(CacheStore::new()),
+            config,
+            replica_config,
+            replica_connections: Mutex::new(Vec::new()),
+            persistence: Arc::new(Mutex::new(Persistence::default())),
+            aof: Mutex::new(None),
+        }
+    }
+
+    /// Records a write command: counts it toward the save points, appends it to the AOF and
+    /// queues it for every connected replica. Called with the store locked, so the AOF and
+    /// replicas get writes in the order they were executed. Replicas never propagate
+    /// further.
+    pub fn propagate(&self, argv: &[Bytes]) {
+        self.persistence.lock().unwrap().dirty += 1;
+        let command = RespValue::Array(argv.iter().cloned().map(RespValue::BinaryBulkString).collect());
+        if let Some(aof) = self.aof.lock().unwrap().as_mut() {
+            aof.append(&RespCodec::encode(&command));
+        }
+        if self.replica_config.is_some() {
+            return;
+        }
+        let mut connections = self.replica_connections.lock().unwrap();
+        // Replicas whose writer task has exited are dropped from the list
+        connections.retain(|replica| match replica.propagate_command(&command) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Failed to propagate command to replica: {}", e);
+                false
+            }
+        });
+    }
+
+    /// Starts propagating writes to a new replica and snapshots the keyspace it gets first.
+    /// Called with the store locked, so every write is either in the snapshot or queued. The
+    /// snapshot shares the values of the keyspace, so the lock is held for a reference per
+    /// key rather than for a copy of the dataset, and is encoded once it is released.
+    pub fn attach_replica(&self, store: &CacheStore) -> FullResync {
+        let (replica, backlog) = ReplicaConnection::new();
+        self.replica_connections.lock().unwrap().push(replica);
+        println!("Added replica connection for command propagation");
+        FullResync {
+            snapshot: rdb::Snapshot::capture(store),
+            backlog,
+        }
+    }
+}
+
 pub struct RedisServer {
     host: String,
     port: u16,
-    data_store: Arc<Mutex<CacheStore>>,
-    replica_config: Option<ReplicaConfig>,
-    replica_connections: Arc<Mutex<Vec<ReplicaConnection>>>,
+    state: Arc<ServerState>,
 }
 
 impl RedisServer {
-    pub fn new(host: String, port: u16, replica_config: Option<ReplicaConfig>) -> Self {
+    pub fn new(host: String, port: u16, config: ServerConfig, replica_config: Option<ReplicaConfig>) -> Self {
         RedisServer {
             host,
             port,
-            data_store: Arc::new(Mutex::new(CacheStore::new())),
-            replica_config,
-            replica_connections: Arc::new(Mutex::new(Vec::new())),
+            state: Arc::new(ServerState::new(config, replica_config)),
         }
     }
 
     pub async fn run(&self) -> std::io::Result<()> {
+        self.load_data_from_disk().await?;
+
         // If this is a replica, initiate handshake with master
-        if let Some(ref config) = self.replica_config {
+        if let Some(ref config) = self.state.replica_config {
             if let (Some(master_host), Some(master_port)) = (&config.master_host, config.master_port) {
                 println!("Connecting to master at {}:{}", master_host, master_port);
                 if let Err(e) = self.initiate_replica_handshake(master_host, master_port).await {
@@ -90,28 +181,100 @@ impl RedisServer {
             }
         }
 
-        let listener = TcpListener::bind(format!("{}:{}", self.host, self.port))?;
+        task::spawn(server_cron(Arc::clone(&self.state)));
+
+        let listener = TcpListener::bind(format!("{}:{}", self.host, self.port)).await?;
         println!("Listening on {}:{}", self.host, self.port);
 
         loop {
-            let (stream, _) = listener.accept()?;
-            let data_store = Arc::clone(&self.data_store);
-            let replica_config = self.replica_config.clone();
-            let replica_connections = Arc::clone(&self.replica_connections);
+            let (stream, _) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Transient failures such as EMFILE must not take the whole server down
+                    eprintln!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = stream.set_nodelay(true) {
+                eprintln!("Failed to set TCP_NODELAY: {}", e);
+            }
+            let state = Arc::clone(&self.state);
             println!("Accepted connection");
             task::spawn(async move {
-                if let Err(e) = handle_client(stream, data_store, replica_config, replica_connections).await {
+                if let Err(e) = handle_client(stream, state).await {
                     eprintln!("Error handling client: {}", e);
                 }
             });
         }
     }
 
+    /// Loads the AOF when it is enabled and exists, the RDB file named by `--dir` and
+    /// `--dbfilename` otherwise, if there is one. Then opens the AOF for appending when it is
+    /// enabled. A file that cannot be loaded stops the server, rather than have it start
+    /// without the data.
+    async fn load_data_from_disk(&self) -> std::io::Result<()> {
+        let aof_path = self.state.config.aof_path();
+        if self.state.config.appendonly && aof_path.exists() {
+            let start = Instant::now();
+            match aof::load(&self.state, &aof_path).await {
+                Ok(commands) => {
+                    println!("DB loaded from append only file: {} commands in {:.3} seconds", commands, start.elapsed().as_secs_f64());
+                }
+                Err(e) => {
+                    eprintln!("Failed loading AOF file {}: {}", aof_path.display(), e);
+                    return Err(e);
+                }
+            }
+        } else {
+            self.load_rdb_file()?;
+        }
+        // Replaying the AOF made no change that is not on the disk already
+        self.state.persistence.lock().unwrap().dirty = 0;
+        if self.state.config.appendonly {
+            aof::start(&self.state).await?;
+        }
+        Ok(())
+    }
+
+    fn load_rdb_file(&self) -> std::io::Result<()> {
+        let path = self.state.config.rdb_path();
+        let start = Instant::now();
+        let mut store = self.state.data_store.lock().unwrap();
+        match rdb::load_file(&path, &mut store) {
+            Ok(keys) => {
+                println!("DB loaded from disk: {} keys in {:.3} seconds", keys, start.elapsed().as_secs_f64());
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("Failed loading RDB file {}: {}", path.display(), e);
+                Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            }
+        }
+    }
+
+    /// Replaces the dataset with the one the master sent on a full resync, before any of
+    /// the writes it propagates after it are applied.
+    fn load_master_rdb(&self, payload: &[u8]) -> std::io::Result<()> {
+        let start = Instant::now();
+        let mut store = self.state.data_store.lock().unwrap();
+        store.flush();
+        match rdb::load(payload, &mut store) {
+            Ok(keys) => {
+                println!("MASTER <-> REPLICA sync: loaded {} keys in {:.3} seconds", keys, start.elapsed().as_secs_f64());
+                aof::rewrite(&self.state, &store);
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("Failed loading the RDB received from master: {}", e);
+                Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            }
+        }
+    }
+
     async fn initiate_replica_handshake(&self, master_host: &str, master_port: u16) -> std::io::Result<()> {
         // Connect to master
-        let master_stream = TcpStream::connect(format!("{}:{}", master_host, master_port))?;
-        let reader_stream = master_stream.try_clone()?;
-        let writer_stream = master_stream.try_clone()?;
+        let master_stream = TcpStream::connect(format!("{}:{}", master_host, master_port)).await?;
+        let (reader_stream, writer_stream) = master_stream.into_split();
         let mut master_reader = BufReader::new(reader_stream);
         let mut master_writer = BufWriter::new(writer_stream);
 
@@ -121,13 +284,13 @@ impl RedisServer {
         ]);
         
         let encoded_ping = RespCodec::encode(&ping_command);
-        master_writer.write_all(&encoded_ping)?;
-        master_writer.flush()?;
+        master_writer.write_all(&encoded_ping).await?;
+        master_writer.flush().await?;
         
         println!("Sent PING to master: {:?}", String::from_utf8_lossy(&encoded_ping));
 
         // Read response from master
-        match RespCodec::decode(&mut master_reader) {
+        match RespCodec::decode(&mut master_reader).await {
             Ok(response) => {
                 println!("Received response from master: {:?}", response);
                 // Expected response should be +PONG\r\n
@@ -155,13 +318,13 @@ impl RedisServer {
         ]);
         
         let encoded_replconf_port = RespCodec::encode(&replconf_port_command);
-        master_writer.write_all(&encoded_replconf_port)?;
-        master_writer.flush()?;
+        master_writer.write_all(&encoded_replconf_port).await?;
+        master_writer.flush().await?;
         
         println!("Sent REPLCONF listening-port to master: {:?}", String::from_utf8_lossy(&encoded_replconf_port));
 
         // Read response from master
-        match RespCodec::decode(&mut master_reader) {
+        match RespCodec::decode(&mut master_reader).await {
             Ok(response) => {
                 println!("Received response from master: {:?}", response);
                 // Expected response should be +OK\r\n
@@ -189,13 +352,13 @@ impl RedisServer {
         ]);
         
         let encoded_replconf_capa = RespCodec::encode(&replconf_capa_command);
-        master_writer.write_all(&encoded_replconf_capa)?;
-        master_writer.flush()?;
+        master_writer.write_all(&encoded_replconf_capa).await?;
+        master_writer.flush().await?;
         
         println!("Sent REPLCONF capa psync2 to master: {:?}", String::from_utf8_lossy(&encoded_replconf_capa));
 
         // Read response from master
-        match RespCodec::decode(&mut master_reader) {
+        match RespCodec::decode(&mut master_reader).await {
             Ok(response) => {
                 println!("Received response from master: {:?}", response);
                 // Expected response should be +OK\r\n
@@ -223,13 +386,13 @@ impl RedisServer {
         ]);
         
         let encoded_psync = RespCodec::encode(&psync_command);
-        master_writer.write_all(&encoded_psync)?;
-        master_writer.flush()?;
+        master_writer.write_all(&encoded_psync).await?;
+        master_writer.flush().await?;
         
         println!("Sent PSYNC ? -1 to master: {:?}", String::from_utf8_lossy(&encoded_psync));
 
         // Read response from master
-        match RespCodec::decode(&mut master_reader) {
+        match RespCodec::decode(&mut master_reader).await {
             Ok(response) => {
                 println!("Received response from master: {:?}", response);
                 // Expected response should be +FULLRESYNC <REPL_ID> 0\r\n
@@ -239,24 +402,25 @@ impl RedisServer {
                         
                         // After FULLRESYNC, we need to read the RDB file
                         // Read the RDB file header: $<length>\r\n
-                        let mut rdb_header = String::new();
-                        master_reader.read_line(&mut rdb_header)?;
+                        let mut rdb_header = Vec::new();
+                        master_reader.read_until(b'\n', &mut rdb_header).await?;
                         
-                        if rdb_header.starts_with('$') {
-                            let rdb_length: usize = rdb_header[1..].trim().parse()
+                        if rdb_header.starts_with(b"$") {
+                            let rdb_length: usize = String::from_utf8_lossy(&rdb_header[1..]).trim().parse()
                                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
                             
                             // Read the RDB file content
                             let mut rdb_content = vec![0u8; rdb_length];
-                            master_reader.read_exact(&mut rdb_content)?;
+                            master_reader.read_exact(&mut rdb_content).await?;
                             
                             println!("Received RDB file from master ({} bytes)", rdb_length);
-                            
+                            self.load_master_rdb(&rdb_content)?;
+
                             // Now we're ready to receive commands from master
                             // Use the existing reader and writer for the listening task
-                            let data_store = Arc::clone(&self.data_store);
+                            let state = Arc::clone(&self.state);
                             task::spawn(async move {
-                                if let Err(e) = listen_for_propagated_commands_with_streams(master_reader, master_writer, data_store).await {
+                                if let Err(e) = listen_for_propagated_commands_with_streams(master_reader, master_writer, state).await {
                                     eprintln!("Error listening for propagated commands: {}", e);
                                 }
                             });
@@ -278,16 +442,59 @@ impl RedisServer {
     }
 }
 
+/// Background housekeeping. Every cron period runs a slow active expire cycle and some
+/// incremental rehashing, starts a background save when a save point is reached and fsyncs
+/// the AOF; when a cycle finds the keyspace still full of expired keys, fast
+/// cycles are scheduled in between until it is clean again.
+async fn server_cron(state: Arc<ServerState>) {
+    let cron_period = Duration::from_millis(1000 / SERVER_HZ);
+    let mut next_slow_cycle = Instant::now();
+
+    loop {
+        let report = {
+            let mut store = state.data_store.lock().unwrap();
+            let report = if Instant::now() >= next_slow_cycle {
+                next_slow_cycle += cron_period;
+                let report = active_expire_cycle(&state, &mut store, ExpireCycle::Slow { cron_period });
+                store.incremental_rehash(Duration::from_millis(1));
+                report
+            } else {
+                active_expire_cycle(&state, &mut store, ExpireCycle::Fast)
+            };
+            commands::propagate_pending(&state, &mut store);
+            persistence::check_save_points(&state, &store);
+            aof::cron(&state, &store);
+            report
+        };
+
+        let delay = if report.needs_more() {
+            FAST_EXPIRE_CYCLE_INTERVAL
+        } else {
+            next_slow_cycle.saturating_duration_since(Instant::now())
+        };
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Replicas do not reclaim expired keys on their own, they wait for the DEL their master
+/// propagates so both keyspaces stay the same.
+fn active_expire_cycle(state: &ServerState, store: &mut CacheStore, cycle: ExpireCycle) -> ExpireCycleReport {
+    if state.replica_config.is_some() {
+        return ExpireCycleReport::default();
+    }
+    store.active_expire_cycle(cycle)
+}
+
 // Function that takes existing reader and writer streams
 async fn listen_for_propagated_commands_with_streams(
-    mut master_reader: BufReader<TcpStream>, 
-    mut master_writer: BufWriter<TcpStream>, 
-    data_store: Arc<Mutex<CacheStore>>
+    mut master_reader: BufReader<OwnedReadHalf>, 
+    mut master_writer: BufWriter<OwnedWriteHalf>, 
+    state: Arc<ServerState>
 ) -> std::io::Result<()> {
     let mut offset: u64 = 0; // Track bytes processed
     
     loop {
-        match RespCodec::decode(&mut master_reader) {
+        match RespCodec::decode(&mut master_reader).await {
             Ok(RespValue::Array(commands)) => {
                 println!("Received propagated command from master: {:?}", commands);
                 
@@ -296,11 +503,11 @@ async fn listen_for_propagated_commands_with_streams(
                 let command_byte_length = command_bytes.len() as u64;
                 
                 // Check if this is a REPLCONF GETACK command
-                if let Some(first_command) = commands.get(0) {
+                if let Some(first_command) = commands.first() {
                     let command = match first_command {
                         RespValue::BulkString(s) | RespValue::SimpleString(s) => s.to_uppercase(),
                         RespValue::BinaryBulkString(b) => {
-                            match String::from_utf8(b.clone()) {
+                            match std::str::from_utf8(b) {
                                 Ok(s) => s.to_uppercase(),
                                 Err(_) => String::new(),
                             }
@@ -312,7 +519,7 @@ async fn listen_for_propagated_commands_with_streams(
                         let subcommand = match &commands[1] {
                             RespValue::BulkString(s) | RespValue::SimpleString(s) => s.to_uppercase(),
                             RespValue::BinaryBulkString(b) => {
-                                match String::from_utf8(b.clone()) {
+                                match std::str::from_utf8(b) {
                                     Ok(s) => s.to_uppercase(),
                                     Err(_) => String::new(),
                                 }
@@ -330,8 +537,8 @@ async fn listen_for_propagated_commands_with_streams(
                             ]);
                             
                             let encoded_response = RespCodec::encode(&ack_response);
-                            master_writer.write_all(&encoded_response)?;
-                            master_writer.flush()?;
+                            master_writer.write_all(&encoded_response).await?;
+                            master_writer.flush().await?;
                             println!("Sent REPLCONF ACK {} response to master", offset);
                             
                             // Now update the offset to include this GETACK command
@@ -348,8 +555,16 @@ async fn listen_for_propagated_commands_with_streams(
                     }
                 }
                 
-                // Process other commands and update offset
-                process_command_for_replica(commands, &data_store);
+                // Process other commands through the same command table as clients do;
+                // the master does not expect replies to propagated writes
+                match commands::to_argv(&commands) {
+                    Ok(argv) => {
+                        if let CommandResponse::Normal(RespValue::Error(e)) = commands::dispatch(&state, &argv, CommandOrigin::Master) {
+                            eprintln!("Replica failed to apply propagated command: {}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("Replica received malformed command: {}", e),
+                }
                 offset += command_byte_length;
             }
             Ok(other) => {
@@ -368,67 +583,74 @@ async fn listen_for_propagated_commands_with_streams(
     Ok(())
 }
 
-// Move listen_for_propagated_commands outside the impl block and make it a standalone function
-async fn listen_for_propagated_commands(master_stream: TcpStream, data_store: Arc<Mutex<CacheStore>>) -> std::io::Result<()> {
-    let reader_stream = master_stream.try_clone()?;
-    let writer_stream = master_stream;
-    
-    let master_reader = BufReader::new(reader_stream);
-    let master_writer = BufWriter::new(writer_stream);
-    
-    listen_for_propagated_commands_with_streams(master_reader, master_writer, data_store).await
-}
-
-async fn handle_client(
-    stream: TcpStream, 
-    data_store: Arc<Mutex<CacheStore>>, 
-    replica_config: Option<ReplicaConfig>,
-    replica_connections: Arc<Mutex<Vec<ReplicaConnection>>>
-) -> std::io::Result<()> {
-    let stream_for_replica = stream.try_clone()?;
-    let mut redis_reader = BufReader::new(&stream);
-    let mut redis_writer = BufWriter::new(&stream);
+async fn handle_client(stream: TcpStream, state: Arc<ServerState>) -> std::io::Result<()> {
+    let (reader_stream, writer_stream) = stream.into_split();
+    let mut redis_reader = BufReader::new(reader_stream);
+    let mut redis_writer = BufWriter::new(writer_stream);
 
     loop {
-        match RespCodec::decode(&mut redis_reader) {
+        match RespCodec::decode(&mut redis_reader).await {
             Ok(RespValue::Array(commands)) => {
                 println!("handle_client: commands: {:?}", commands);
                 
-                let response = process_command(commands.clone(), &data_store, &replica_config, &replica_connections);
+                let argv = match commands::to_argv(&commands) {
+                    Ok(argv) => argv,
+                    Err(e) => {
+                        redis_writer.write_all(&RespCodec::encode(&RespValue::Error(e.to_string()))).await?;
+                        redis_writer.flush().await?;
+                        continue;
+                    }
+                };
+                let response = commands::dispatch(&state, &argv, CommandOrigin::Client);
                 
                 match response {
                     CommandResponse::Normal(resp_value) => {
-                        redis_writer.write_all(&RespCodec::encode(&resp_value))?;
-                        redis_writer.flush()?;
+                        redis_writer.write_all(&RespCodec::encode(&resp_value)).await?;
+                        // Pipelined requests already sitting in the read buffer are answered
+                        // with a single flush once the buffer drains
+                        if redis_reader.buffer().is_empty() {
+                            redis_writer.flush().await?;
+                        }
                         println!("handle_client: response: {:?}", resp_value);
                     }
-                    CommandResponse::PsyncWithRdb(resp_value) => {
+                    CommandResponse::PsyncWithRdb(resp_value, resync) => {
                         // First send the FULLRESYNC response
-                        redis_writer.write_all(&RespCodec::encode(&resp_value))?;
-                        redis_writer.flush()?;
+                        redis_writer.write_all(&RespCodec::encode(&resp_value)).await?;
+                        redis_writer.flush().await?;
                         println!("handle_client: FULLRESYNC response: {:?}", resp_value);
-                        
-                        // Then send the RDB file in the format: $<length>\r\n<binary_contents>
-                        let rdb_header = format!("${}\r\n", EMPTY_RDB_FILE.len());
-                        redis_writer.write_all(rdb_header.as_bytes())?;
-                        redis_writer.write_all(EMPTY_RDB_FILE)?;
-                        redis_writer.flush()?;
-                        println!("handle_client: sent RDB file ({} bytes)", EMPTY_RDB_FILE.len());
-                        
-                        // Add this connection to replica connections for command propagation
-                        let replica_conn = ReplicaConnection::new(stream_for_replica);
-                        replica_connections.lock().unwrap().push(replica_conn);
-                        println!("Added replica connection for command propagation");
-                        
-                        // Keep the connection alive for command propagation
-                        // The replica will keep reading commands from this connection
+
+                        // Then the RDB file in the format: $<length>\r\n<binary_contents>,
+                        // serialized off the runtime threads while writes keep being queued
+                        let FullResync { snapshot, backlog } = resync;
+                        let rdb_file = task::spawn_blocking(move || snapshot.encode())
+                            .await
+                            .map_err(std::io::Error::other)?;
+                        let rdb_header = format!("${}\r\n", rdb_file.len());
+                        redis_writer.write_all(rdb_header.as_bytes()).await?;
+                        redis_writer.write_all(&rdb_file).await?;
+                        redis_writer.flush().await?;
+                        println!("handle_client: sent RDB file ({} bytes)", rdb_file.len());
+
+                        // The writes executed since the snapshot follow, then the live stream.
+                        // The write half now belongs to the replica connection, which keeps
+                        // the socket open for command propagation
+                        ReplicaConnection::stream(redis_writer, backlog);
                         return Ok(());
                     }
-                }
-                
-                // If this is a write command and we're a master, propagate to replicas
-                if replica_config.is_none() && is_write_command(&commands) {
-                    propagate_to_replicas(&replica_connections, &RespValue::Array(commands));
+                    CommandResponse::Blocked(blocked) => {
+                        // Replies to requests pipelined before the blocking one go out first
+                        redis_writer.flush().await?;
+                        match wait_until_unblocked(&state, &mut redis_reader, blocked).await {
+                            Some(resp_value) => {
+                                redis_writer.write_all(&RespCodec::encode(&resp_value)).await?;
+                                redis_writer.flush().await?;
+                                println!("handle_client: response: {:?}", resp_value);
+                            }
+                            None => break,
+                        }
+                    }
+                    // Handlers ask to block, dispatch hands out Blocked instead
+                    CommandResponse::Block(_) => unreachable!("dispatch never returns Block"),
                 }
             }
             Ok(other) => {
@@ -442,6 +664,14 @@ async fn handle_client(
                 eprintln!("handle_client: Unexpected EOF: {}", e);
                 break;
             }
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                // A protocol error: the client is told why before the connection is closed
+                eprintln!("handle_client: {}", e);
+                let reply = RespValue::Error(format!("ERR {}", e));
+                redis_writer.write_all(&RespCodec::encode(&reply)).await?;
+                redis_writer.flush().await?;
+                break;
+            }
             Err(e) => {
                 eprintln!("handle_client: Error decoding command: {}", e);
                 return Err(e);
@@ -452,241 +682,112 @@ async fn handle_client(
     Ok(())
 }
 
-fn is_write_command(commands: &[RespValue]) -> bool {
-    if let Some(first_command) = commands.get(0) {
-        let cmd = match first_command {
-            RespValue::BulkString(s) | RespValue::SimpleString(s) => s.to_uppercase(),
-            RespValue::BinaryBulkString(b) => {
-                match String::from_utf8(b.clone()) {
-                    Ok(s) => s.to_uppercase(),
-                    Err(_) => return false,
-                }
-            },
-            _ => return false,
-        };
-        
-        match cmd.as_str() {
-            "SET" | "DEL" | "INCR" | "DECR" | "LPUSH" | "RPUSH" | "LPOP" | "RPOP" => true,
-            _ => false,
+/// Parks the connection on a blocking command until another client serves it, its deadline
+/// passes, or the client disconnects, in which case there is nothing to reply (None).
+async fn wait_until_unblocked(
+    state: &ServerState,
+    reader: &mut BufReader<OwnedReadHalf>,
+    blocked: Blocked,
+) -> Option<RespValue> {
+    let Blocked {
+        id,
+        mut receiver,
+        deadline,
+        timeout_reply,
+    } = blocked;
+    let timeout = async {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+            None => std::future::pending().await,
         }
-    } else {
-        false
-    }
-}
+    };
 
-fn propagate_to_replicas(replica_connections: &Arc<Mutex<Vec<ReplicaConnection>>>, command: &RespValue) {
-    let connections = replica_connections.lock().unwrap();
-    for replica in connections.iter() {
-        if let Err(e) = replica.propagate_command(command) {
-            eprintln!("Failed to propagate command to replica: {}", e);
+    tokio::select! {
+        reply = &mut receiver => return reply.ok(),
+        _ = timeout => {}
+        _ = client_closed(reader) => {
+            state.data_store.lock().unwrap().blocked_clients().unblock(id);
+            return None;
         }
     }
-}
 
-fn process_command(commands: Vec<RespValue>, data_store: &Arc<Mutex<CacheStore>>, replica_config: &Option<ReplicaConfig>, replica_connections: &Arc<Mutex<Vec<ReplicaConnection>>>) -> CommandResponse {
-    if commands.is_empty() {
-        return CommandResponse::Normal(RespValue::Error("ERR no command specified".to_string()));
+    let mut store = state.data_store.lock().unwrap();
+    if store.blocked_clients().unblock(id).is_some() {
+        return Some(timeout_reply);
     }
+    // Served right as the deadline passed, the reply is already waiting in the channel
+    receiver.try_recv().ok()
+}
 
-    let command = match &commands[0] {
-        RespValue::BulkString(s) | RespValue::SimpleString(s) => s.to_uppercase(),
-        RespValue::BinaryBulkString(b) => {
-            match String::from_utf8(b.clone()) {
-                Ok(s) => s.to_uppercase(),
-                Err(_) => return CommandResponse::Normal(RespValue::Error("ERR invalid command: non-UTF8 data".to_string())),
-            }
-        },
-        _ => return CommandResponse::Normal(RespValue::Error("ERR invalid command: expected string".to_string())),
-    };
-
-    match command.as_str() {
-        "PING" => CommandResponse::Normal(RespValue::SimpleString("PONG".to_string())),
-        "SET" => {
-            if commands.len() < 3 {
-                return CommandResponse::Normal(RespValue::Error("ERR wrong number of arguments for 'set' command: expected 3".to_string()));
-            }
-            let mut store = data_store.lock().unwrap();
-            let key = match &commands[1] {
-                RespValue::BulkString(s) | RespValue::SimpleString(s) => s.clone(),
-                RespValue::BinaryBulkString(b) => match String::from_utf8(b.clone()) {
-                    Ok(s) => s,
-                    Err(_) => return CommandResponse::Normal(RespValue::Error("ERR invalid key: non-UTF8 data".to_string())),
-                },
-                _ => return CommandResponse::Normal(RespValue::Error("ERR invalid key: expected string".to_string())),
-            };
-            let value = match &commands[2] {
-                RespValue::BulkString(s) | RespValue::SimpleString(s) => s.clone(),
-                RespValue::BinaryBulkString(b) => match String::from_utf8(b.clone()) {
-                    Ok(s) => s,
-                    Err(_) => return CommandResponse::Normal(RespValue::Error("ERR invalid value: non-UTF8 data".to_string())),
-                },
-                _ => return CommandResponse::Normal(RespValue::Error("ERR invalid value: expected string".to_string())),
-            };
-            
-            let expiry = if commands.len() > 3 {
-                parse_px(&commands[3..])
-            } else {
-                None
-            };
-
-            store.set(key, value, expiry);
-            CommandResponse::Normal(RespValue::SimpleString("OK".to_string()))
-        }
-        "GET" => {
-            if commands.len() < 2 {
-                return CommandResponse::Normal(RespValue::Error("ERR wrong number of arguments for 'get' command: expected 2".to_string()));
-            }
-
-            let key = match &commands[1] {
-                RespValue::BulkString(s) | RespValue::SimpleString(s) => s.clone(),
-                RespValue::BinaryBulkString(b) => match String::from_utf8(b.clone()) {
-                    Ok(s) => s,
-                    Err(_) => return CommandResponse::Normal(RespValue::Error("ERR invalid key: non-UTF8 data".to_string())),
-                },
-                _ => return CommandResponse::Normal(RespValue::Error("ERR invalid key: expected string".to_string())),
-            };
-            let store = data_store.lock().unwrap();
-            println!("process_command: store: {:?}", store);
-            match store.get(&key) {
-                Some(value) => {
-                    if value.is_empty() {
-                        return CommandResponse::Normal(RespValue::Null);
-                    }
-                    return CommandResponse::Normal(RespValue::BulkString(value.clone()));
-                },
-                None => CommandResponse::Normal(RespValue::Null),
-            }
-        }
-        "ECHO" => {
-            if commands.len() < 2 {
-                return CommandResponse::Normal(RespValue::Error("ERR wrong number of arguments for 'echo' command: expected 2".to_string()));
-            }
-            CommandResponse::Normal(commands[1].clone())
-        }
-        "REPLCONF" => {
-            // For the purposes of this challenge, we can safely ignore the arguments
-            // and just respond with +OK\r\n ("OK" encoded as a RESP Simple String)
-            CommandResponse::Normal(RespValue::SimpleString("OK".to_string()))
-        }
-        "PSYNC" => {
-            // The master responds with +FULLRESYNC <REPL_ID> 0\r\n
-            // FULLRESYNC means full resynchronization (not incremental)
-            // <REPL_ID> is the replication ID of the master
-            // 0 is the replication offset of the master
-            let repl_id = "8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb";
-            let response = format!("FULLRESYNC {} 0", repl_id);
-            CommandResponse::PsyncWithRdb(RespValue::SimpleString(response))
-        }
-        "INFO" => {
-            // TODO replica info 
-            println!("\n\nreplica_config: {:?}\n\n", replica_config.clone());
-            let role = if replica_config.is_some() {
-                "role:slave"
-            } else {
-                "role:master"
-            };
-            
-            // Add master replication ID and offset for master instances
-            let info_response = if replica_config.is_none() {
-                // This is a master instance
-                format!("{}\r\nmaster_replid:8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb\r\nmaster_repl_offset:0", role)
-            } else {
-                // This is a slave instance, only return role
-                role.to_string()
-            };
-            
-            CommandResponse::Normal(RespValue::BulkString(info_response))
-        }
-        "WAIT" => {
-            // WAIT numreplicas timeout
-            // Return the actual number of connected replicas
-            let replica_count = replica_connections.lock().unwrap().len() as u64;
-            CommandResponse::Normal(RespValue::Integer(replica_count))
-        }
-        _ => CommandResponse::Normal(RespValue::Error(format!("ERR unknown command: {}", command))),
+/// Resolves once the peer has closed the connection. Requests it pipelined behind a
+/// blocking command stay unread until the command completes.
+async fn client_closed(reader: &mut BufReader<OwnedReadHalf>) {
+    if !reader.buffer().is_empty() {
+        return std::future::pending().await;
+    }
+    match reader.get_mut().peek(&mut [0u8; 1]).await {
+        Ok(0) | Err(_) => {}
+        Ok(_) => std::future::pending().await,
     }
 }
 
-fn parse_px(args: &[RespValue]) -> Option<Duration> {
-    let px = match &args[0] {
-        RespValue::BulkString(s) | RespValue::SimpleString(s) => s.to_uppercase(),
-        RespValue::BinaryBulkString(b) => match String::from_utf8(b.clone()) {
-            Ok(s) => s.to_string().to_uppercase(),
-            Err(_) => return None,
-        },
-        _ => return None,
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if px != "PX" {
-        return None;
+    fn argv(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())).collect()
     }
 
-    let ms = match &args[1] {
-        RespValue::Integer(i) => *i as u64,
-        RespValue::BulkString(s) | RespValue::SimpleString(s) => s.parse().ok()?,
-        RespValue::BinaryBulkString(b) => match String::from_utf8(b.clone()) {
-            Ok(s) => s.parse().ok()?,
-            Err(_) => return None,
-        },
-        _ => return None,
-    };
-
-    println!("ms: {:?}", ms);
-
-    Some(Duration::from_millis(ms))
-}
-
-fn process_command_for_replica(commands: Vec<RespValue>, data_store: &Arc<Mutex<CacheStore>>) {
-    if commands.is_empty() {
-        return;
+    #[tokio::test]
+    async fn blocked_client_times_out() {
+        let state = ServerState::new(ServerConfig::default(), None);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (reader, _writer) = listener.accept().await.unwrap().0.into_split();
+        let mut reader = BufReader::new(reader);
+
+        let CommandResponse::Blocked(blocked) =
+            commands::dispatch(&state, &argv(&["BLPOP", "list", "0.05"]), CommandOrigin::Client)
+        else {
+            panic!("BLPOP on a missing key did not block");
+        };
+        let start = Instant::now();
+        let reply = wait_until_unblocked(&state, &mut reader, blocked).await;
+        assert_eq!(reply, Some(RespValue::NullArray));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // The client stopped waiting, so a push is not handed to it
+        commands::dispatch(&state, &argv(&["RPUSH", "list", "a"]), CommandOrigin::Client);
+        assert!(matches!(
+            commands::dispatch(&state, &argv(&["LLEN", "list"]), CommandOrigin::Client),
+            CommandResponse::Normal(RespValue::Integer(1))
+        ));
     }
 
-    let command = match &commands[0] {
-        RespValue::BulkString(s) | RespValue::SimpleString(s) => s.to_uppercase(),
-        RespValue::BinaryBulkString(b) => {
-            match String::from_utf8(b.clone()) {
-                Ok(s) => s.to_uppercase(),
-                Err(_) => return,
-            }
-        },
-        _ => return,
-    };
-
-    match command.as_str() {
-        "SET" => {
-            if commands.len() < 3 {
-                return;
-            }
-            let mut store = data_store.lock().unwrap();
-            let key = match &commands[1] {
-                RespValue::BulkString(s) | RespValue::SimpleString(s) => s.clone(),
-                RespValue::BinaryBulkString(b) => match String::from_utf8(b.clone()) {
-                    Ok(s) => s,
-                    Err(_) => return,
-                },
-                _ => return,
-            };
-            let value = match &commands[2] {
-                RespValue::BulkString(s) | RespValue::SimpleString(s) => s.clone(),
-                RespValue::BinaryBulkString(b) => match String::from_utf8(b.clone()) {
-                    Ok(s) => s,
-                    Err(_) => return,
-                },
-                _ => return,
-            };
-            
-            let expiry = if commands.len() > 3 {
-                parse_px(&commands[3..])
-            } else {
-                None
-            };
+    #[test]
+    fn served_xread_is_not_propagated() {
+        let state = ServerState::new(ServerConfig::default(), None);
+        let (replica, mut queued) = ReplicaConnection::new();
+        state.replica_connections.lock().unwrap().push(replica);
 
-            store.set(key, value, expiry);
-            println!("Replica processed SET command");
-        }
-        _ => {
-            // For other write commands, we would implement them here
-            println!("Replica received command: {}", command);
-        }
+        let xread = argv(&["XREAD", "BLOCK", "0", "STREAMS", "stream", "$"]);
+        let CommandResponse::Blocked(mut blocked) = commands::dispatch(&state, &xread, CommandOrigin::Client) else {
+            panic!("XREAD BLOCK on a missing stream did not block");
+        };
+        let xadd = argv(&["XADD", "stream", "1-1", "field", "value"]);
+        let set = argv(&["SET", "key", "value"]);
+        commands::dispatch(&state, &xadd, CommandOrigin::Client);
+        assert!(blocked.receiver.try_recv().is_ok());
+        commands::dispatch(&state, &set, CommandOrigin::Client);
+
+        // The replica is queued the two writes back to back, the XREAD served in between is
+        // not replayed
+        let encode = |argv: &[Bytes]| {
+            RespCodec::encode(&RespValue::Array(argv.iter().cloned().map(RespValue::BinaryBulkString).collect()))
+        };
+        assert_eq!(queued.try_recv().unwrap(), encode(&xadd));
+        assert_eq!(queued.try_recv().unwrap(), encode(&set));
+        assert!(queued.try_recv().is_err());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,449 @@
+// Chained hash table with incremental rehashing and a stateless scan cursor.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/dict.c
+
+use super::random::random_below;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+const INITIAL_SIZE: usize = 4;
+// Buckets moved per rehash step, and empty buckets a single step may skip over
+const REHASH_EMPTY_VISITS: usize = 10;
+// Shrink once fewer than 1/8 of the buckets would be used
+const SHRINK_RATIO: usize = 8;
+
+type Bucket<K, V> = Vec<(K, V)>;
+
+#[derive(Debug, Clone)]
+struct Table<K, V> {
+    buckets: Vec<Bucket<K, V>>,
+    used: usize,
+}
+
+impl<K, V> Table<K, V> {
+    fn empty() -> Self {
+        Table {
+            buckets: Vec::new(),
+            used: 0,
+        }
+    }
+
+    fn with_size(size: usize) -> Self {
+        Table {
+            buckets: (0..size).map(|_| Vec::new()).collect(),
+            used: 0,
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        (self.buckets.len() as u64).wrapping_sub(1)
+    }
+}
+
+/// A hash table that grows and shrinks in small steps instead of all at once, like the Redis
+/// `dict`. While a resize is in progress entries live in two tables and every mutation moves
+/// one more bucket across. [`Dict::scan`] iterates with a reverse-binary cursor, which
+/// guarantees that every entry present for the whole iteration is returned at least once
+/// even if the table is resized between calls.
+#[derive(Debug, Clone)]
+pub struct Dict<K, V> {
+    tables: [Table<K, V>; 2],
+    rehash_index: Option<usize>,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    pub fn new() -> Self {
+        Dict {
+            tables: [Table::empty(), Table::empty()],
+            rehash_index: None,
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables[0].used + self.tables[1].used
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_rehashing(&self) -> bool {
+        self.rehash_index.is_some()
+    }
+
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<(usize, usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.is_empty() {
+            return None;
+        }
+        let hash = self.hash(key);
+        for (t, table) in self.tables.iter().enumerate() {
+            if table.buckets.is_empty() {
+                continue;
+            }
+            let index = (hash & table.mask()) as usize;
+            if let Some(position) = table.buckets[index].iter().position(|(k, _)| k.borrow() == key) {
+                return Some((t, index, position));
+            }
+            if !self.is_rehashing() {
+                break;
+            }
+        }
+        None
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key)
+            .map(|(t, index, position)| &self.tables[t].buckets[index][position].1)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).map(|(t, index, position)| {
+            let (k, v) = &self.tables[t].buckets[index][position];
+            (k, v)
+        })
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash_step();
+        let (t, index, position) = self.find(key)?;
+        Some(&mut self.tables[t].buckets[index][position].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Inserts or replaces an entry, returning the previous value.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.rehash_step();
+        if let Some((t, index, position)) = self.find(&key) {
+            return Some(std::mem::replace(&mut self.tables[t].buckets[index][position].1, value));
+        }
+
+        self.expand_if_needed();
+        let hash = self.hash(&key);
+        // New entries always go to the table being rehashed into
+        let t = if self.is_rehashing() { 1 } else { 0 };
+        let table = &mut self.tables[t];
+        let index = (hash & table.mask()) as usize;
+        table.buckets[index].push((key, value));
+        table.used += 1;
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, v)| v)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash_step();
+        let (t, index, position) = self.find(key)?;
+        let table = &mut self.tables[t];
+        let entry = table.buckets[index].swap_remove(position);
+        table.used -= 1;
+        self.shrink_if_needed();
+        Some(entry)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.tables
+            .iter()
+            .flat_map(|table| table.buckets.iter())
+            .flat_map(|bucket| bucket.iter().map(|(k, v)| (k, v)))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Picks a random entry by choosing random buckets until a non-empty one turns up, then a
+    /// random entry of its chain. Entries in long chains are slightly less likely to be picked.
+    pub fn random_entry(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        // Buckets of table 0 below the rehash index are known to be empty
+        let skip = self.rehash_index.unwrap_or(0);
+        let candidates = self.tables[0].buckets.len() - skip + self.tables[1].buckets.len();
+        loop {
+            let mut index = skip + random_below(candidates);
+            let table = if index < self.tables[0].buckets.len() {
+                &self.tables[0]
+            } else {
+                index -= self.tables[0].buckets.len();
+                &self.tables[1]
+            };
+            let bucket = &table.buckets[index];
+            if !bucket.is_empty() {
+                let (k, v) = &bucket[random_below(bucket.len())];
+                return Some((k, v));
+            }
+        }
+    }
+
+    /// Visits the entries of the bucket(s) addressed by `cursor` and returns the cursor to
+    /// continue from; 0 means the iteration is complete. The cursor is incremented on its
+    /// reversed bits, so a bucket visited in a small table is never visited again through
+    /// its expansions in a larger one, and vice versa.
+    pub fn scan<F>(&self, cursor: u64, mut visit: F) -> u64
+    where
+        F: FnMut(&K, &V),
+    {
+        if self.is_empty() {
+            return 0;
+        }
+        let mut v = cursor;
+
+        if !self.is_rehashing() {
+            let table = &self.tables[0];
+            let m0 = table.mask();
+            for (k, value) in &table.buckets[(v & m0) as usize] {
+                visit(k, value);
+            }
+            v |= !m0;
+            v = v.reverse_bits().wrapping_add(1).reverse_bits();
+            return v;
+        }
+
+        let (small, large) = if self.tables[0].buckets.len() <= self.tables[1].buckets.len() {
+            (&self.tables[0], &self.tables[1])
+        } else {
+            (&self.tables[1], &self.tables[0])
+        };
+        let m0 = small.mask();
+        let m1 = large.mask();
+
+        for (k, value) in &small.buckets[(v & m0) as usize] {
+            visit(k, value);
+        }
+        // Visit every bucket of the larger table that expands the small table's bucket
+        loop {
+            for (k, value) in &large.buckets[(v & m1) as usize] {
+                visit(k, value);
+            }
+            v |= !m1;
+            v = v.reverse_bits().wrapping_add(1).reverse_bits();
+            if v & (m0 ^ m1) == 0 {
+                break;
+            }
+        }
+        v
+    }
+
+    /// Performs up to `steps` bucket moves of an in-progress resize. Returns true while more
+    /// work is left.
+    pub fn rehash(&mut self, steps: usize) -> bool {
+        let mut index = match self.rehash_index {
+            Some(index) => index,
+            None => return false,
+        };
+        let mut empty_visits = steps * REHASH_EMPTY_VISITS;
+
+        for _ in 0..steps {
+            if self.tables[0].used == 0 {
+                break;
+            }
+            while self.tables[0].buckets[index].is_empty() {
+                index += 1;
+                empty_visits -= 1;
+                if empty_visits == 0 {
+                    self.rehash_index = Some(index);
+                    return true;
+                }
+            }
+            let bucket = std::mem::take(&mut self.tables[0].buckets[index]);
+            self.tables[0].used -= bucket.len();
+            for (key, value) in bucket {
+                let hash = self.hash(&key);
+                let table = &mut self.tables[1];
+                let target = (hash & table.mask()) as usize;
+                table.buckets[target].push((key, value));
+                table.used += 1;
+            }
+            index += 1;
+        }
+
+        if self.tables[0].used == 0 {
+            self.tables[0] = std::mem::replace(&mut self.tables[1], Table::empty());
+            self.rehash_index = None;
+            return false;
+        }
+        self.rehash_index = Some(index);
+        true
+    }
+
+    fn rehash_step(&mut self) {
+        if self.is_rehashing() {
+            self.rehash(1);
+        }
+    }
+
+    fn expand_if_needed(&mut self) {
+        if self.is_rehashing() {
+            return;
+        }
+        let size = self.tables[0].buckets.len();
+        if size == 0 {
+            self.tables[0] = Table::with_size(INITIAL_SIZE);
+        } else if self.tables[0].used >= size {
+            self.resize((self.tables[0].used + 1).next_power_of_two() * 2);
+        }
+    }
+
+    fn shrink_if_needed(&mut self) {
+        if self.is_rehashing() {
+            return;
+        }
+        let size = self.tables[0].buckets.len();
+        if size > INITIAL_SIZE && self.tables[0].used * SHRINK_RATIO < size {
+            self.resize(self.tables[0].used.max(INITIAL_SIZE).next_power_of_two());
+        }
+    }
+
+    fn resize(&mut self, size: usize) {
+        if size == self.tables[0].buckets.len() {
+            return;
+        }
+        self.tables[1] = Table::with_size(size);
+        self.rehash_index = Some(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Runs a full SCAN, calling `between` on the dict after every step.
+    fn scan_all(dict: &mut Dict<u64, u64>, mut between: impl FnMut(&mut Dict<u64, u64>)) -> HashSet<u64> {
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        loop {
+            cursor = dict.scan(cursor, |key, _| {
+                seen.insert(*key);
+            });
+            if cursor == 0 {
+                return seen;
+            }
+            between(dict);
+        }
+    }
+
+    fn dict_with_keys(keys: impl IntoIterator<Item = u64>) -> Dict<u64, u64> {
+        let mut dict = Dict::new();
+        for key in keys {
+            dict.insert(key, key);
+        }
+        dict
+    }
+
+    #[test]
+    fn scan_visits_every_key_while_a_rehash_is_in_progress() {
+        let mut dict = dict_with_keys(0..1000);
+        // Keep inserting until a grow starts, then leave it half done
+        let mut next = 1000;
+        while !dict.is_rehashing() {
+            dict.insert(next, next);
+            next += 1;
+        }
+        assert!(dict.is_rehashing());
+
+        let seen = scan_all(&mut dict, |_| {});
+        assert!((0..next).all(|key| seen.contains(&key)));
+    }
+
+    #[test]
+    fn scan_visits_every_key_present_throughout_while_the_table_grows() {
+        let mut dict = dict_with_keys(0..100);
+        let mut next = 100;
+        let mut rehashed = false;
+        let seen = scan_all(&mut dict, |dict| {
+            while next < 2000 && !dict.is_rehashing() {
+                dict.insert(next, next);
+                next += 1;
+            }
+            rehashed |= dict.is_rehashing();
+            dict.rehash(1);
+        });
+        assert!(rehashed);
+        assert!((0..100).all(|key| seen.contains(&key)));
+    }
+
+    #[test]
+    fn scan_visits_every_key_present_throughout_while_the_table_shrinks() {
+        let mut dict = dict_with_keys(0..4096);
+        let mut doomed = (100..4096).collect::<Vec<_>>();
+        let mut rehashed = false;
+        let seen = scan_all(&mut dict, |dict| {
+            for _ in 0..200 {
+                if let Some(key) = doomed.pop() {
+                    dict.remove(&key);
+                }
+            }
+            rehashed |= dict.is_rehashing();
+        });
+        assert!(rehashed);
+        assert!((0..100).all(|key| seen.contains(&key)));
+    }
+
+    #[test]
+    fn lookups_find_entries_in_both_tables_during_a_rehash() {
+        let mut dict = dict_with_keys(0..64);
+        let mut next = 64;
+        while !dict.is_rehashing() {
+            dict.insert(next, next);
+            next += 1;
+        }
+        assert!((0..next).all(|key| dict.get(&key) == Some(&key)));
+        assert_eq!(dict.remove(&3), Some(3));
+        assert_eq!(dict.insert(5, 50), Some(5));
+        assert_eq!(dict.get(&3), None);
+        assert_eq!(dict.get(&5), Some(&50));
+        assert_eq!(dict.len(), next as usize - 1);
+
+        while dict.rehash(100) {}
+        assert!(!dict.is_rehashing());
+        assert_eq!(dict.iter().count(), next as usize - 1);
+        assert_eq!(dict.get(&5), Some(&50));
+    }
+}
@@ -0,0 +1,187 @@
+// Glob-style pattern matching as used by KEYS, SCAN MATCH and friends.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/util.c (stringmatchlen)
+
+// Guards against patterns like "a*a*a*a*...b" blowing up the recursion
+const MAX_NESTING: usize = 1000;
+
+/// Matches `string` against a Redis glob pattern: `*`, `?`, `[abc]`, `[^abc]`, `[a-z]` and
+/// `\` to escape any of those.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let mut skip_longer_matches = false;
+    match_impl(pattern, string, &mut skip_longer_matches, 0)
+}
+
+fn match_impl(mut pattern: &[u8], mut string: &[u8], skip_longer_matches: &mut bool, nesting: usize) -> bool {
+    if nesting > MAX_NESTING {
+        return false;
+    }
+
+    while !pattern.is_empty() && !string.is_empty() {
+        match pattern[0] {
+            b'*' => {
+                while pattern.len() > 1 && pattern[1] == b'*' {
+                    pattern = &pattern[1..];
+                }
+                if pattern.len() == 1 {
+                    return true;
+                }
+                while !string.is_empty() {
+                    if match_impl(&pattern[1..], string, skip_longer_matches, nesting + 1) {
+                        return true;
+                    }
+                    // A deeper '*' already failed on every suffix, so longer ones cannot match
+                    if *skip_longer_matches {
+                        return false;
+                    }
+                    string = &string[1..];
+                }
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => {
+                string = &string[1..];
+            }
+            b'[' => {
+                pattern = &pattern[1..];
+                let negate = pattern.first() == Some(&b'^');
+                if negate {
+                    pattern = &pattern[1..];
+                }
+                let c = string[0];
+                let mut matched = false;
+                loop {
+                    match pattern {
+                        [b'\\', escaped, ..] => {
+                            pattern = &pattern[1..];
+                            if *escaped == c {
+                                matched = true;
+                            }
+                        }
+                        [b']', ..] => break,
+                        // Unterminated class: treat the end of the pattern as the closing bracket
+                        [] => break,
+                        [start, b'-', end, ..] => {
+                            let (start, end) = if start > end { (*end, *start) } else { (*start, *end) };
+                            pattern = &pattern[2..];
+                            if c >= start && c <= end {
+                                matched = true;
+                            }
+                        }
+                        [literal, ..] => {
+                            if *literal == c {
+                                matched = true;
+                            }
+                        }
+                    }
+                    pattern = &pattern[1..];
+                }
+                if negate {
+                    matched = !matched;
+                }
+                if !matched {
+                    return false;
+                }
+                string = &string[1..];
+                if pattern.is_empty() {
+                    // The class ran to the end of the pattern, there is no ']' to step over
+                    return string.is_empty();
+                }
+            }
+            b'\\' if pattern.len() >= 2 => {
+                pattern = &pattern[1..];
+                if pattern[0] != string[0] {
+                    return false;
+                }
+                string = &string[1..];
+            }
+            literal => {
+                if literal != string[0] {
+                    return false;
+                }
+                string = &string[1..];
+            }
+        }
+        pattern = &pattern[1..];
+        if string.is_empty() {
+            while pattern.first() == Some(&b'*') {
+                pattern = &pattern[1..];
+            }
+            break;
+        }
+    }
+
+    pattern.is_empty() && string.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    fn matches(pattern: &str, string: &str) -> bool {
+        glob_match(pattern.as_bytes(), string.as_bytes())
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*", "anything"));
+        // Like stringmatchlen, nothing matches the empty string; KEYS and SCAN special-case
+        // a lone '*' before matching
+        assert!(!matches("*", ""));
+        assert!(matches("h?llo", "hello"));
+        assert!(!matches("h?llo", "hllo"));
+        assert!(matches("h*llo", "hllo"));
+        assert!(matches("h*llo", "heeeello"));
+        assert!(matches("a*b*c", "aXXbYYc"));
+        assert!(!matches("a*b*c", "aXXbYY"));
+        assert!(matches("user:*", "user:1000"));
+        assert!(!matches("user:*", "users:1000"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("h[ae]llo", "hello"));
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+        assert!(!matches("h[ae]llo", "hllo"));
+    }
+
+    #[test]
+    fn ranges() {
+        assert!(matches("h[a-b]llo", "hallo"));
+        assert!(matches("h[a-b]llo", "hbllo"));
+        assert!(!matches("h[a-b]llo", "hcllo"));
+        // Reversed bounds are swapped
+        assert!(matches("h[b-a]llo", "hallo"));
+        assert!(matches("key[0-9]", "key7"));
+        assert!(!matches("key[^0-9]", "key7"));
+        assert!(matches("[a-cx-z]", "y"));
+        assert!(!matches("[a-cx-z]", "m"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches(r"a\*b", "a*b"));
+        assert!(!matches(r"a\*b", "aXb"));
+        assert!(matches(r"a\?b", "a?b"));
+        assert!(!matches(r"a\?b", "aXb"));
+        assert!(matches(r"\[x]", "[x]"));
+        assert!(matches(r"[\]]", "]"));
+        assert!(matches(r"[\-a]", "-"));
+        assert!(!matches(r"[\-a]", "b"));
+    }
+
+    #[test]
+    fn unterminated_class_ends_at_the_end_of_the_pattern() {
+        assert!(matches("ab[c", "abc"));
+        assert!(!matches("ab[c", "abd"));
+        assert!(!matches("ab[c", "abcd"));
+    }
+
+    #[test]
+    fn pathological_patterns_terminate() {
+        let pattern = "a*".repeat(50) + "b";
+        assert!(!matches(&pattern, &"a".repeat(100)));
+    }
+}
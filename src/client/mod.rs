@@ -1,4 +1,15 @@
+pub mod aof;
+pub mod blocking;
 pub mod cache_store;
 pub mod codec;
+pub mod commands;
+pub mod config;
 pub mod connection;
+pub mod dict;
+pub mod error;
+pub mod glob;
 pub mod model;
+pub mod persistence;
+pub mod random;
+pub mod rdb;
+pub mod types;
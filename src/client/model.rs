@@ -1,12 +1,14 @@
 // referred source code: https://github.com/redis/node-redis/blob/master/packages/client/lib/commands/index.ts#L9
 // referred source code: https://github.dev/iorust/resp/tree/master/src
 
+use bytes::Bytes;
+
 /// Represents a RESP value, see [Redis Protocol specification](http://redis.io/topics/protocol).
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub enum RespValue {
     SimpleString(String), // For Simple Strings the first byte of the reply is "+".
-    Integer(u64), // For Integers the first byte of the reply is ":".
-    BinaryBulkString(Vec<u8>), // For Bulk <binary> Strings the first byte of the reply is "$".
+    Integer(i64), // For Integers the first byte of the reply is ":".
+    BinaryBulkString(Bytes), // For Bulk <binary> Strings the first byte of the reply is "$".
     BulkString(String),   // For Bulk Strings the first byte of the reply is "$".
     Error(String),        // For Errors the first byte of the reply is "-".
     Null,                 // Null bulk reply, `$-1\r\n`
@@ -15,6 +17,15 @@ pub enum RespValue {
 }
 
 impl RespValue {
+    /// Returns the raw payload of any string-like value, without assuming it is UTF-8.
+    pub fn to_bytes(&self) -> Option<Bytes> {
+        match self {
+            RespValue::BinaryBulkString(b) => Some(b.clone()),
+            RespValue::BulkString(s) | RespValue::SimpleString(s) => Some(Bytes::copy_from_slice(s.as_bytes())),
+            _ => None,
+        }
+    }
+
     // pub fn is_null(&self) -> bool {
     //     match self {
     //         RespValue::Null => true,
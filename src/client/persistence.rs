@@ -0,0 +1,119 @@
+// Snapshotting the dataset to the RDB file: SAVE, BGSAVE, and the save points that trigger
+// a BGSAVE on their own.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/rdb.c
+
+use super::cache_store::{now_ms, CacheStore};
+use super::connection::ServerState;
+use super::rdb;
+use std::sync::Arc;
+use tokio::task;
+
+// Seconds to wait before a save point retries after a failed BGSAVE
+const BGSAVE_RETRY_DELAY: u64 = 5;
+
+/// Where snapshotting stands. Locked after the keyspace when both are needed.
+#[derive(Debug)]
+pub struct Persistence {
+    // Writes since the last successful save
+    pub dirty: u64,
+    // Unix time in seconds of the last successful save, or of the start of the server
+    pub last_save: u64,
+    pub bgsave_in_progress: bool,
+    // BGSAVE SCHEDULE asked for one while another was running
+    pub bgsave_scheduled: bool,
+    pub last_bgsave_ok: bool,
+    last_bgsave_try: u64,
+}
+
+impl Default for Persistence {
+    fn default() -> Self {
+        Persistence {
+            dirty: 0,
+            last_save: unix_time(),
+            bgsave_in_progress: false,
+            bgsave_scheduled: false,
+            last_bgsave_ok: true,
+            last_bgsave_try: 0,
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    now_ms() / 1000
+}
+
+/// Writes the RDB file in the foreground, blocking every client until it is done.
+pub fn save(state: &ServerState, store: &CacheStore) -> std::io::Result<()> {
+    let path = state.config.rdb_path();
+    if let Err(e) = rdb::write_file(&path, &rdb::encode(store)) {
+        eprintln!("Failed saving the DB to {}: {}", path.display(), e);
+        return Err(e);
+    }
+    println!("DB saved on disk");
+    let mut persistence = state.persistence.lock().unwrap();
+    persistence.dirty = 0;
+    persistence.last_save = unix_time();
+    Ok(())
+}
+
+/// Writes the RDB file from a snapshot of the keyspace in a blocking task, while clients
+/// keep being served. Returns false if a background save is already running.
+pub fn background_save(state: &ServerState, store: &CacheStore) -> bool {
+    let dirty_before_save = {
+        let mut persistence = state.persistence.lock().unwrap();
+        if persistence.bgsave_in_progress {
+            return false;
+        }
+        persistence.bgsave_in_progress = true;
+        persistence.bgsave_scheduled = false;
+        persistence.last_bgsave_try = unix_time();
+        persistence.dirty
+    };
+    let snapshot = rdb::Snapshot::capture(store);
+    let path = state.config.rdb_path();
+    let persistence = Arc::clone(&state.persistence);
+    task::spawn_blocking(move || {
+        let result = rdb::write_file(&path, &snapshot.encode());
+        let mut persistence = persistence.lock().unwrap();
+        persistence.bgsave_in_progress = false;
+        persistence.last_bgsave_ok = result.is_ok();
+        match result {
+            Ok(()) => {
+                println!("Background saving terminated with success");
+                // Writes that arrived during the save are not in the file
+                persistence.dirty = persistence.dirty.saturating_sub(dirty_before_save);
+                persistence.last_save = unix_time();
+            }
+            Err(e) => eprintln!("Background saving error writing {}: {}", path.display(), e),
+        }
+    });
+    println!("Background saving started");
+    true
+}
+
+/// Starts a background save if one was scheduled or a save point is reached. Called from
+/// the server cron with the keyspace locked.
+pub fn check_save_points(state: &ServerState, store: &CacheStore) {
+    let now = unix_time();
+    let due = {
+        let persistence = state.persistence.lock().unwrap();
+        if persistence.bgsave_in_progress {
+            return;
+        }
+        // After a failure, give whatever went wrong some time before trying again
+        let may_retry = persistence.last_bgsave_ok || now.saturating_sub(persistence.last_bgsave_try) > BGSAVE_RETRY_DELAY;
+        let elapsed = now.saturating_sub(persistence.last_save);
+        let reached = state
+            .config
+            .save_points
+            .iter()
+            .find(|point| persistence.dirty >= point.changes && elapsed > point.seconds && may_retry);
+        if let Some(point) = reached {
+            println!("{} changes in {} seconds. Saving...", point.changes, point.seconds);
+        }
+        reached.is_some() || persistence.bgsave_scheduled
+    };
+    if due {
+        background_save(state, store);
+    }
+}
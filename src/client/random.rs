@@ -0,0 +1,36 @@
+// Small non-cryptographic PRNG for sampling (RANDOMKEY, SPOP, skiplist levels, ...).
+// Cargo.toml pins our dependencies, so this is a thread-local xorshift64* instead of `rand`.
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+thread_local! {
+    static STATE: Cell<u64> = Cell::new(seed());
+}
+
+fn seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    // RandomState is seeded from the OS, mixing it in keeps threads from sharing a sequence
+    RandomState::new().hash_one(nanos) | 1
+}
+
+pub fn random_u64() -> u64 {
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
+
+/// Uniform-ish value in `0..bound`. `bound` must be non-zero.
+pub fn random_below(bound: usize) -> usize {
+    (random_u64() % bound as u64) as usize
+}
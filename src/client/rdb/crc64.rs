@@ -0,0 +1,48 @@
+// The CRC64 trailer of RDB files: the Jones polynomial, reflected, with no final xor.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/crc64.c
+
+// 0xad93d23594c935a9 with its bits reversed, as the CRC is computed LSB first
+const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+const TABLE: [u64; 256] = build_table();
+
+const fn build_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Extends `crc` over `data`. A whole payload is checksummed starting from 0.
+pub fn crc64(mut crc: u64, data: &[u8]) -> u64 {
+    for &byte in data {
+        crc = TABLE[((crc ^ byte as u64) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crc64;
+
+    #[test]
+    fn check_value() {
+        // The value crc64.c tests itself against
+        assert_eq!(crc64(0, b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+    }
+
+    #[test]
+    fn extends_across_calls() {
+        let data = b"This is a test of the emergency broadcast system.";
+        assert_eq!(crc64(crc64(0, &data[..10]), &data[10..]), crc64(0, data));
+    }
+}
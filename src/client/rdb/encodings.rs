@@ -0,0 +1,488 @@
+// Decoders for the compact encodings Redis keeps small values in, which RDB files embed as
+// plain strings: intsets, ziplists, listpacks and zipmaps. Integer entries are handed out in
+// their decimal form, like Redis does when it converts such a value. Listpacks, which stream
+// nodes have to be saved as, can also be encoded.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/listpack.c
+// referred source code: https://github.com/redis/redis/blob/unstable/src/ziplist.c
+
+use super::RdbError;
+use bytes::Bytes;
+
+/// Reads a little-endian two's complement integer of `bytes.len()` bytes.
+fn signed_le(bytes: &[u8]) -> i64 {
+    let value = bytes
+        .iter()
+        .enumerate()
+        .fold(0u64, |value, (i, &byte)| value | (byte as u64) << (8 * i));
+    sign_extend(value, bytes.len() as u32 * 8)
+}
+
+fn sign_extend(value: u64, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    ((value << shift) as i64) >> shift
+}
+
+fn slice<'a>(data: &'a [u8], start: usize, len: usize, what: &'static str) -> Result<&'a [u8], RdbError> {
+    start
+        .checked_add(len)
+        .and_then(|end| data.get(start..end))
+        .ok_or(RdbError::Corrupt(what))
+}
+
+fn integer_entry(value: i64) -> Bytes {
+    Bytes::from(value.to_string())
+}
+
+/// The members of an intset: a header with the width of its integers, 2, 4 or 8 bytes, and
+/// their count, followed by the integers in ascending order.
+pub fn intset_entries(data: &[u8]) -> Result<Vec<Bytes>, RdbError> {
+    let header = slice(data, 0, 8, "intset")?;
+    let width = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    if !matches!(width, 2 | 4 | 8) {
+        return Err(RdbError::Corrupt("intset"));
+    }
+    let values = slice(data, 8, width.saturating_mul(len), "intset")?;
+    Ok(values.chunks_exact(width).map(|value| integer_entry(signed_le(value))).collect())
+}
+
+/// The entries of a ziplist, the encoding of small lists, hashes and sorted sets before
+/// Redis 7.
+pub fn ziplist_entries(data: &[u8]) -> Result<Vec<Bytes>, RdbError> {
+    const WHAT: &str = "ziplist";
+    // zlbytes, zltail and zllen
+    let mut i = 10;
+    let mut entries = Vec::new();
+    loop {
+        let prev_len = *data.get(i).ok_or(RdbError::Corrupt(WHAT))?;
+        if prev_len == 0xff {
+            return Ok(entries);
+        }
+        i += if prev_len == 0xfe { 5 } else { 1 };
+        let encoding = *data.get(i).ok_or(RdbError::Corrupt(WHAT))?;
+        let (header, len) = match encoding >> 6 {
+            0b00 => (1, (encoding & 0x3f) as usize),
+            0b01 => {
+                let next = *data.get(i + 1).ok_or(RdbError::Corrupt(WHAT))?;
+                (2, ((encoding & 0x3f) as usize) << 8 | next as usize)
+            }
+            0b10 => {
+                let len = slice(data, i + 1, 4, WHAT)?;
+                (5, u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+            }
+            _ => {
+                let width = match encoding {
+                    0xc0 => 2,
+                    0xd0 => 4,
+                    0xe0 => 8,
+                    0xf0 => 3,
+                    0xfe => 1,
+                    // 4 bit immediates 1..=13 that stand for 0..=12
+                    0xf1..=0xfd => 0,
+                    _ => return Err(RdbError::Corrupt(WHAT)),
+                };
+                let value = match width {
+                    0 => (encoding & 0x0f) as i64 - 1,
+                    width => signed_le(slice(data, i + 1, width, WHAT)?),
+                };
+                entries.push(integer_entry(value));
+                i += 1 + width;
+                continue;
+            }
+        };
+        entries.push(Bytes::copy_from_slice(slice(data, i + header, len, WHAT)?));
+        i += header + len;
+    }
+}
+
+/// The entries of a listpack, the encoding of small collections and of stream nodes since
+/// Redis 7. Every entry is followed by its own length, for walking backwards, which is
+/// skipped here.
+pub fn listpack_entries(data: &[u8]) -> Result<Vec<Bytes>, RdbError> {
+    const WHAT: &str = "listpack";
+    // Total bytes and number of entries
+    let mut i = 6;
+    let mut entries = Vec::new();
+    loop {
+        let encoding = *data.get(i).ok_or(RdbError::Corrupt(WHAT))?;
+        if encoding == 0xff {
+            return Ok(entries);
+        }
+        let byte = |offset: usize| data.get(i + offset).copied().ok_or(RdbError::Corrupt(WHAT));
+        let (entry, len) = if encoding & 0x80 == 0 {
+            (integer_entry((encoding & 0x7f) as i64), 1)
+        } else if encoding & 0xc0 == 0x80 {
+            let len = (encoding & 0x3f) as usize;
+            (Bytes::copy_from_slice(slice(data, i + 1, len, WHAT)?), 1 + len)
+        } else if encoding & 0xe0 == 0xc0 {
+            let value = ((encoding & 0x1f) as u64) << 8 | byte(1)? as u64;
+            (integer_entry(sign_extend(value, 13)), 2)
+        } else if encoding & 0xf0 == 0xe0 {
+            let len = ((encoding & 0x0f) as usize) << 8 | byte(1)? as usize;
+            (Bytes::copy_from_slice(slice(data, i + 2, len, WHAT)?), 2 + len)
+        } else if encoding == 0xf0 {
+            let len = slice(data, i + 1, 4, WHAT)?;
+            let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+            (Bytes::copy_from_slice(slice(data, i + 5, len, WHAT)?), 5 + len)
+        } else {
+            let width = match encoding {
+                0xf1 => 2,
+                0xf2 => 3,
+                0xf3 => 4,
+                0xf4 => 8,
+                _ => return Err(RdbError::Corrupt(WHAT)),
+            };
+            (integer_entry(signed_le(slice(data, i + 1, width, WHAT)?)), 1 + width)
+        };
+        entries.push(entry);
+        i += len + backlen_size(len);
+    }
+}
+
+/// How many bytes the trailing length of a listpack entry of `len` bytes takes.
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+/// The integer a string stands for, if it is exactly how that integer is written in
+/// decimal, so that storing the integer instead loses nothing.
+pub fn canonical_integer(value: &[u8]) -> Option<i64> {
+    if value.len() > 20 {
+        return None;
+    }
+    let integer = std::str::from_utf8(value).ok()?.parse::<i64>().ok()?;
+    (integer.to_string().as_bytes() == value).then_some(integer)
+}
+
+/// Builds a listpack. Strings that are canonical integers are stored as integers, like
+/// Redis does, in the smallest encoding that fits them.
+pub struct ListpackWriter {
+    data: Vec<u8>,
+    len: usize,
+}
+
+impl ListpackWriter {
+    // Total bytes and number of entries
+    const HEADER_SIZE: usize = 6;
+
+    pub fn new() -> Self {
+        ListpackWriter {
+            data: vec![0; Self::HEADER_SIZE],
+            len: 0,
+        }
+    }
+
+    /// Bytes taken so far, without the terminator.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, value: &[u8]) {
+        if let Some(integer) = canonical_integer(value) {
+            return self.push_integer(integer);
+        }
+        let start = self.data.len();
+        match value.len() {
+            len @ 0..=63 => self.data.push(0x80 | len as u8),
+            len @ 64..=4095 => self.data.extend_from_slice(&[0xe0 | (len >> 8) as u8, len as u8]),
+            len => {
+                self.data.push(0xf0);
+                self.data.extend_from_slice(&(len as u32).to_le_bytes());
+            }
+        }
+        self.data.extend_from_slice(value);
+        self.end_entry(start);
+    }
+
+    pub fn push_integer(&mut self, value: i64) {
+        let start = self.data.len();
+        match value {
+            0..=127 => self.data.push(value as u8),
+            -4096..=4095 => {
+                let value = value as u64 & 0x1fff;
+                self.data.extend_from_slice(&[0xc0 | (value >> 8) as u8, value as u8]);
+            }
+            _ => {
+                let (encoding, width) = match value {
+                    -32768..=32767 => (0xf1, 2),
+                    -8388608..=8388607 => (0xf2, 3),
+                    -2147483648..=2147483647 => (0xf3, 4),
+                    _ => (0xf4, 8),
+                };
+                self.data.push(encoding);
+                self.data.extend_from_slice(&value.to_le_bytes()[..width]);
+            }
+        }
+        self.end_entry(start);
+    }
+
+    // Appends the length of the entry that starts at `start`, most significant 7 bits first,
+    // with the top bit set on every byte but the first so it can be read backwards
+    fn end_entry(&mut self, start: usize) {
+        let len = self.data.len() - start;
+        let size = backlen_size(len);
+        for i in (0..size).rev() {
+            let byte = (len >> (7 * i)) as u8 & 0x7f;
+            self.data.push(if i == size - 1 { byte } else { byte | 0x80 });
+        }
+        self.len += 1;
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.data.push(0xff);
+        let total = self.data.len() as u32;
+        self.data[0..4].copy_from_slice(&total.to_le_bytes());
+        // Counts past the 16 bit field are unknown and have to be walked
+        let len = u16::try_from(self.len).unwrap_or(u16::MAX);
+        self.data[4..6].copy_from_slice(&len.to_le_bytes());
+        self.data
+    }
+}
+
+/// The fields and values of a zipmap, the encoding of small hashes before Redis 2.6, in
+/// alternating order.
+pub fn zipmap_entries(data: &[u8]) -> Result<Vec<Bytes>, RdbError> {
+    const WHAT: &str = "zipmap";
+    // The number of entries, only meaningful below 254
+    let mut i = 1;
+    let mut entries = Vec::new();
+    loop {
+        let first = *data.get(i).ok_or(RdbError::Corrupt(WHAT))?;
+        if first == 0xff {
+            return Ok(entries);
+        }
+        let is_value = entries.len() % 2 == 1;
+        let len = match first {
+            0..=253 => {
+                i += 1;
+                first as usize
+            }
+            _ => {
+                let len = slice(data, i + 1, 4, WHAT)?;
+                i += 5;
+                u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize
+            }
+        };
+        // Values are followed by unused bytes left behind by updates
+        let free = if is_value {
+            let free = *data.get(i).ok_or(RdbError::Corrupt(WHAT))? as usize;
+            i += 1;
+            free
+        } else {
+            0
+        };
+        entries.push(Bytes::copy_from_slice(slice(data, i, len, WHAT)?));
+        i += len + free;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(entries: &[Bytes]) -> Vec<&str> {
+        entries.iter().map(|entry| std::str::from_utf8(entry).unwrap()).collect()
+    }
+
+    /// A ziplist of the given raw entries, each an encoding byte and its data, with the
+    /// previous-entry lengths and the header filled in.
+    fn ziplist(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0; 10];
+        let mut prev_len = 0;
+        for entry in entries {
+            let start = data.len();
+            if prev_len < 254 {
+                data.push(prev_len as u8);
+            } else {
+                data.push(0xfe);
+                data.extend_from_slice(&(prev_len as u32).to_le_bytes());
+            }
+            data.extend_from_slice(entry);
+            prev_len = data.len() - start;
+        }
+        data.push(0xff);
+        let total = data.len() as u32;
+        data[0..4].copy_from_slice(&total.to_le_bytes());
+        data[8..10].copy_from_slice(&(entries.len() as u16).to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn intset() {
+        let mut data = vec![2, 0, 0, 0, 3, 0, 0, 0];
+        data.extend_from_slice(&[0xff, 0xff, 0x05, 0x00, 0x2c, 0x01]);
+        assert_eq!(strings(&intset_entries(&data).unwrap()), ["-1", "5", "300"]);
+
+        let mut data = vec![8, 0, 0, 0, 2, 0, 0, 0];
+        data.extend_from_slice(&i64::MIN.to_le_bytes());
+        data.extend_from_slice(&i64::MAX.to_le_bytes());
+        assert_eq!(
+            strings(&intset_entries(&data).unwrap()),
+            ["-9223372036854775808", "9223372036854775807"]
+        );
+    }
+
+    #[test]
+    fn corrupt_intset() {
+        // Width 3 does not exist
+        assert!(intset_entries(&[3, 0, 0, 0, 1, 0, 0, 0, 1, 2, 3]).is_err());
+        // Fewer integers than the header counts
+        assert!(intset_entries(&[4, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0]).is_err());
+        assert!(intset_entries(&[2, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn ziplist_from_the_redis_docs() {
+        // The ziplist holding 2 and 5 from the comment atop ziplist.c, then with "Hello World"
+        let data = [0x0f, 0, 0, 0, 0x0c, 0, 0, 0, 0x02, 0, 0x00, 0xf3, 0x02, 0xf6, 0xff];
+        assert_eq!(strings(&ziplist_entries(&data).unwrap()), ["2", "5"]);
+        let mut data = vec![0x1c, 0, 0, 0, 0x17, 0, 0, 0, 0x03, 0, 0x00, 0xf3, 0x02, 0xf6, 0x02, 0x0b];
+        data.extend_from_slice(b"Hello World");
+        data.push(0xff);
+        assert_eq!(strings(&ziplist_entries(&data).unwrap()), ["2", "5", "Hello World"]);
+    }
+
+    #[test]
+    fn ziplist_encodings() {
+        let medium = "m".repeat(300);
+        let mut medium_entry = vec![0x40 | 0x01, 0x2c];
+        medium_entry.extend_from_slice(medium.as_bytes());
+        let long = "l".repeat(20000);
+        let mut long_entry = vec![0x80];
+        long_entry.extend_from_slice(&20000u32.to_be_bytes());
+        long_entry.extend_from_slice(long.as_bytes());
+        let entries = [
+            vec![0x03, b'a', b'b', b'c'],
+            vec![0xf1],
+            vec![0xfd],
+            vec![0xfe, 0x80],
+            vec![0xc0, 0x00, 0x80],
+            vec![0xf0, 0xff, 0xff, 0x7f],
+            vec![0xd0, 0x00, 0x00, 0x00, 0x80],
+            [vec![0xe0], i64::MAX.to_le_bytes().to_vec()].concat(),
+            medium_entry,
+            // Follows a 300 byte entry, so its previous length takes 5 bytes
+            vec![0x00],
+            long_entry,
+        ];
+        assert_eq!(
+            strings(&ziplist_entries(&ziplist(&entries)).unwrap()),
+            [
+                "abc",
+                "0",
+                "12",
+                "-128",
+                "-32768",
+                "8388607",
+                "-2147483648",
+                "9223372036854775807",
+                &medium,
+                "",
+                &long,
+            ]
+        );
+    }
+
+    #[test]
+    fn corrupt_ziplist() {
+        // Unknown integer encoding
+        assert!(ziplist_entries(&ziplist(&[vec![0xc1, 0, 0]])).is_err());
+        // String longer than the data
+        assert!(ziplist_entries(&ziplist(&[vec![0x05, b'a']])).is_err());
+        // No terminator
+        let mut data = ziplist(&[vec![0x01, b'a']]);
+        data.pop();
+        assert!(ziplist_entries(&data).is_err());
+    }
+
+    #[test]
+    fn listpack_by_hand() {
+        // "hello" as a 6 bit string and 1024 as a 13 bit integer, each followed by its length
+        let data = [
+            17, 0, 0, 0, 2, 0, 0x85, b'h', b'e', b'l', b'l', b'o', 6, 0xc4, 0x00, 2, 0xff,
+        ];
+        assert_eq!(strings(&listpack_entries(&data).unwrap()), ["hello", "1024"]);
+
+        let mut writer = ListpackWriter::new();
+        writer.push(b"hello");
+        writer.push(b"1024");
+        assert_eq!(writer.finish(), data);
+    }
+
+    #[test]
+    fn listpack_round_trip() {
+        let medium = "m".repeat(200);
+        let long = "l".repeat(5000);
+        let values = [
+            "", "a", "0", "127", "128", "-1", "4095", "-4096", "4096", "32767", "-32768", "32768",
+            "8388607", "-8388608", "2147483647", "-2147483648", "2147483648",
+            "9223372036854775807", "-9223372036854775808",
+            // Not canonical integers, so kept as strings
+            "007", "+5", "-0", "9223372036854775808",
+            &medium, &long,
+        ];
+        let mut writer = ListpackWriter::new();
+        for value in values {
+            writer.push(value.as_bytes());
+        }
+        let data = writer.finish();
+        assert_eq!(u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize, data.len());
+        assert_eq!(u16::from_le_bytes([data[4], data[5]]) as usize, values.len());
+        assert_eq!(strings(&listpack_entries(&data).unwrap()), values);
+    }
+
+    #[test]
+    fn corrupt_listpack() {
+        assert!(listpack_entries(&[7, 0, 0, 0, 1, 0, 0x85, b'h', 2, 0xff]).is_err());
+        // 0xf5 to 0xfe are not encodings
+        assert!(listpack_entries(&[8, 0, 0, 0, 1, 0, 0xf5, 1, 0xff]).is_err());
+        assert!(listpack_entries(&[6, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn zipmap_from_the_redis_docs() {
+        // "foo" => "bar", "hello" => "world", as laid out in the comment atop zipmap.c
+        let data = b"\x02\x03foo\x03\x00bar\x05hello\x05\x00world\xff";
+        assert_eq!(strings(&zipmap_entries(data).unwrap()), ["foo", "bar", "hello", "world"]);
+    }
+
+    #[test]
+    fn zipmap_free_bytes_and_long_lengths() {
+        // A value shrunk in place leaves a free byte behind
+        let mut data = b"\x02\x01a\x02\x01xyZ".to_vec();
+        let long = "v".repeat(300);
+        data.extend_from_slice(b"\x01b\xfe");
+        data.extend_from_slice(&300u32.to_le_bytes());
+        data.push(0);
+        data.extend_from_slice(long.as_bytes());
+        data.push(0xff);
+        assert_eq!(strings(&zipmap_entries(&data).unwrap()), ["a", "xy", "b", &long]);
+    }
+
+    #[test]
+    fn corrupt_zipmap() {
+        assert!(zipmap_entries(b"\x01\x03foo\x03\x00ba").is_err());
+        assert!(zipmap_entries(b"\x01\x03foo").is_err());
+    }
+
+    #[test]
+    fn canonical_integers() {
+        assert_eq!(canonical_integer(b"-42"), Some(-42));
+        assert_eq!(canonical_integer(b"0"), Some(0));
+        assert_eq!(canonical_integer(b"042"), None);
+        assert_eq!(canonical_integer(b"+42"), None);
+        assert_eq!(canonical_integer(b"-0"), None);
+        assert_eq!(canonical_integer(b" 42"), None);
+        assert_eq!(canonical_integer(b"99999999999999999999"), None);
+    }
+}
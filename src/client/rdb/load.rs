@@ -0,0 +1,736 @@
+// Loading RDB files into the keyspace.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/rdb.c
+
+use super::crc64::crc64;
+use super::encodings::{intset_entries, listpack_entries, ziplist_entries, zipmap_entries};
+use super::*;
+use crate::client::cache_store::{now_ms, CacheStore, CacheValue};
+use crate::client::types::hash::Hash;
+use crate::client::types::set::Set;
+use crate::client::types::stream::{ConsumerGroup, Consumer, Fields, PendingEntry, Stream, StreamId};
+use crate::client::types::zset::SortedSet;
+use bytes::Bytes;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Loads the RDB file at `path` into `store`. A missing file is not an error, the server
+/// then starts with an empty dataset like Redis does. Returns the number of keys loaded.
+pub fn load_file(path: &Path, store: &mut CacheStore) -> Result<usize, RdbError> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    load(&data, store)
+}
+
+/// Loads an RDB payload into `store`, on top of what it already holds. Keys, and hash
+/// fields, whose TTL has already passed are left out. Only database 0 is loaded, since the
+/// server has a single keyspace; the keys of other databases are read past. Returns the
+/// number of keys loaded.
+pub fn load(data: &[u8], store: &mut CacheStore) -> Result<usize, RdbError> {
+    load_prefix(data, store).map(|(loaded, _)| loaded)
+}
+
+/// Like [`load`], for an RDB payload that other data follows, such as the preamble of an
+/// AOF. Also returns the length of the payload.
+pub fn load_prefix(data: &[u8], store: &mut CacheStore) -> Result<(usize, usize), RdbError> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.bytes(5)? != b"REDIS" {
+        return Err(RdbError::WrongSignature);
+    }
+    let version = std::str::from_utf8(reader.bytes(4)?)
+        .ok()
+        .and_then(|version| version.parse::<u32>().ok())
+        .ok_or(RdbError::WrongSignature)?;
+    if !(1..=RDB_VERSION).contains(&version) {
+        return Err(RdbError::UnsupportedVersion(version));
+    }
+
+    let now = now_ms();
+    let mut db = 0;
+    let mut expires_at = None;
+    let mut loaded = 0;
+    loop {
+        let kind = reader.u8()?;
+        match kind {
+            OPCODE_EXPIRETIME => expires_at = Some(reader.u32_le()? as u64 * 1000),
+            OPCODE_EXPIRETIME_MS => expires_at = Some(reader.millisecond_time()?),
+            // LRU and LFU hints, meaningless without an eviction policy
+            OPCODE_IDLE => {
+                reader.length()?;
+            }
+            OPCODE_FREQ => {
+                reader.u8()?;
+            }
+            OPCODE_SELECTDB => db = reader.length()?,
+            // Sizing hints: the number of keys and of keys with a TTL
+            OPCODE_RESIZEDB => {
+                reader.length()?;
+                reader.length()?;
+            }
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    reader.length()?;
+                }
+            }
+            OPCODE_AUX => {
+                let name = reader.string()?;
+                let value = reader.string()?;
+                if name.as_ref() == b"redis-ver" {
+                    println!("Loading RDB produced by version {}", String::from_utf8_lossy(&value));
+                }
+            }
+            // Function libraries are skipped, there is no scripting to run them
+            OPCODE_FUNCTION2 => {
+                reader.string()?;
+            }
+            OPCODE_FUNCTION_PRE_GA => return Err(RdbError::Unsupported("Pre-release function libraries")),
+            OPCODE_MODULE_AUX => return Err(RdbError::Unsupported("Module data")),
+            OPCODE_EOF => break,
+            kind => {
+                let key = reader.string()?;
+                let value = read_value(&mut reader, kind, now)?;
+                let expires_at = expires_at.take();
+                if db != 0 || expires_at.is_some_and(|expires_at| expires_at <= now) {
+                    continue;
+                }
+                // A hash whose every field has expired
+                let Some(value) = value else {
+                    continue;
+                };
+                store.insert_entry(key, value, expires_at);
+                loaded += 1;
+            }
+        }
+    }
+    // What follows is the CRC64 of the file since version 5, zero when it was not computed
+    if version >= 5 {
+        let end = reader.pos;
+        let expected = u64::from_le_bytes(reader.array()?);
+        let got = crc64(0, &data[..end]);
+        if expected != 0 && expected != got {
+            return Err(RdbError::WrongChecksum { expected, got });
+        }
+    }
+    Ok((loaded, reader.pos))
+}
+
+/// A cursor over the bytes of an RDB payload.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+/// What the length prefix of a string says: its length, or the special encoding it is
+/// stored in.
+enum Length {
+    Plain(u64),
+    Encoded(u8),
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], RdbError> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or(RdbError::Corrupt("unexpected end of file"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], RdbError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, RdbError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32_le(&mut self) -> Result<u32, RdbError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    /// A Unix time in milliseconds, such as an expiry deadline.
+    fn millisecond_time(&mut self) -> Result<u64, RdbError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    /// The length prefix of strings and collections: 6 bits, 14 bits, or a 32 or 64 bit
+    /// big-endian length, told apart by the two top bits of the first byte.
+    fn length_or_encoding(&mut self) -> Result<Length, RdbError> {
+        let first = self.u8()?;
+        Ok(match first >> 6 {
+            0b00 => Length::Plain((first & 0x3f) as u64),
+            0b01 => Length::Plain(((first & 0x3f) as u64) << 8 | self.u8()? as u64),
+            0b11 => Length::Encoded(first & 0x3f),
+            _ => match first {
+                0x80 => Length::Plain(u32::from_be_bytes(self.array()?) as u64),
+                0x81 => Length::Plain(u64::from_be_bytes(self.array()?)),
+                _ => return Err(RdbError::Corrupt("unknown length encoding")),
+            },
+        })
+    }
+
+    fn length(&mut self) -> Result<u64, RdbError> {
+        match self.length_or_encoding()? {
+            Length::Plain(len) => Ok(len),
+            Length::Encoded(_) => Err(RdbError::Corrupt("encoded string where a length was expected")),
+        }
+    }
+
+    fn usize_length(&mut self) -> Result<usize, RdbError> {
+        usize::try_from(self.length()?).map_err(|_| RdbError::Corrupt("length out of range"))
+    }
+
+    /// A string, which may be stored as an integer or LZF compressed.
+    fn string(&mut self) -> Result<Bytes, RdbError> {
+        let integer = match self.length_or_encoding()? {
+            Length::Plain(len) => {
+                let len = usize::try_from(len).map_err(|_| RdbError::Corrupt("length out of range"))?;
+                return Ok(Bytes::copy_from_slice(self.bytes(len)?));
+            }
+            Length::Encoded(ENC_INT8) => self.u8()? as i8 as i64,
+            Length::Encoded(ENC_INT16) => i16::from_le_bytes(self.array()?) as i64,
+            Length::Encoded(ENC_INT32) => i32::from_le_bytes(self.array()?) as i64,
+            Length::Encoded(ENC_LZF) => {
+                let compressed_len = self.usize_length()?;
+                let len = self.usize_length()?;
+                let compressed = self.bytes(compressed_len)?;
+                let data = lzf::decompress(compressed, len).ok_or(RdbError::Corrupt("invalid LZF compressed string"))?;
+                return Ok(Bytes::from(data));
+            }
+            Length::Encoded(_) => return Err(RdbError::Corrupt("unknown string encoding")),
+        };
+        Ok(Bytes::from(integer.to_string()))
+    }
+
+    /// A score of the old ZSET type, in text form behind a one byte length, with the
+    /// lengths 253 to 255 standing for NaN, inf and -inf.
+    fn text_double(&mut self) -> Result<f64, RdbError> {
+        match self.u8()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => parse_double(self.bytes(len as usize)?),
+        }
+    }
+
+    fn binary_double(&mut self) -> Result<f64, RdbError> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    /// A stream ID as the 128 bit big-endian number stream nodes and PELs are keyed by.
+    fn raw_stream_id(&mut self) -> Result<StreamId, RdbError> {
+        raw_stream_id(self.bytes(16)?)
+    }
+
+    /// A stream ID saved as two lengths.
+    fn stream_id(&mut self) -> Result<StreamId, RdbError> {
+        Ok(StreamId {
+            ms: self.length()?,
+            seq: self.length()?,
+        })
+    }
+}
+
+fn raw_stream_id(bytes: &[u8]) -> Result<StreamId, RdbError> {
+    let id = u128::from_be_bytes(bytes.try_into().map_err(|_| RdbError::Corrupt("invalid stream ID"))?);
+    Ok(StreamId {
+        ms: (id >> 64) as u64,
+        seq: id as u64,
+    })
+}
+
+fn parse_double(text: &[u8]) -> Result<f64, RdbError> {
+    std::str::from_utf8(text)
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or(RdbError::Corrupt("invalid double"))
+}
+
+fn parse_integer(entry: &[u8]) -> Result<i64, RdbError> {
+    std::str::from_utf8(entry)
+        .ok()
+        .and_then(|entry| entry.parse().ok())
+        .ok_or(RdbError::Corrupt("invalid integer"))
+}
+
+/// Reads a value of the given type. None for a hash whose fields have all expired.
+fn read_value(reader: &mut Reader<'_>, kind: u8, now: u64) -> Result<Option<CacheValue>, RdbError> {
+    let value = match kind {
+        TYPE_STRING => CacheValue::String(reader.string()?),
+        TYPE_LIST => {
+            let len = reader.usize_length()?;
+            CacheValue::List((0..len).map(|_| reader.string()).collect::<Result<_, _>>()?)
+        }
+        TYPE_LIST_ZIPLIST => CacheValue::List(ziplist_entries(&reader.string()?)?.into()),
+        TYPE_LIST_QUICKLIST | TYPE_LIST_QUICKLIST_2 => {
+            let mut list = VecDeque::new();
+            for _ in 0..reader.length()? {
+                let container = if kind == TYPE_LIST_QUICKLIST_2 { reader.length()? } else { QUICKLIST_NODE_PACKED };
+                let node = reader.string()?;
+                match container {
+                    QUICKLIST_NODE_PLAIN => list.push_back(node),
+                    QUICKLIST_NODE_PACKED if kind == TYPE_LIST_QUICKLIST => list.extend(ziplist_entries(&node)?),
+                    QUICKLIST_NODE_PACKED => list.extend(listpack_entries(&node)?),
+                    _ => return Err(RdbError::Corrupt("unknown quicklist node container")),
+                }
+            }
+            CacheValue::List(list)
+        }
+        TYPE_SET => {
+            let len = reader.usize_length()?;
+            CacheValue::Set((0..len).map(|_| reader.string()).collect::<Result<_, _>>()?)
+        }
+        TYPE_SET_INTSET => CacheValue::Set(intset_entries(&reader.string()?)?.into_iter().collect()),
+        TYPE_SET_LISTPACK => CacheValue::Set(listpack_entries(&reader.string()?)?.into_iter().collect::<Set>()),
+        TYPE_ZSET | TYPE_ZSET_2 => {
+            let mut zset = SortedSet::default();
+            for _ in 0..reader.length()? {
+                let member = reader.string()?;
+                let score = if kind == TYPE_ZSET_2 { reader.binary_double()? } else { reader.text_double()? };
+                zset.insert(member, score);
+            }
+            CacheValue::SortedSet(zset)
+        }
+        TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+            let entries = if kind == TYPE_ZSET_ZIPLIST {
+                ziplist_entries(&reader.string()?)?
+            } else {
+                listpack_entries(&reader.string()?)?
+            };
+            let mut zset = SortedSet::default();
+            for pair in entries.chunks_exact(2) {
+                zset.insert(pair[0].clone(), parse_double(&pair[1])?);
+            }
+            CacheValue::SortedSet(zset)
+        }
+        TYPE_HASH => {
+            let mut hash = Hash::default();
+            for _ in 0..reader.length()? {
+                let field = reader.string()?;
+                hash.insert(field, reader.string()?);
+            }
+            CacheValue::Hash(hash)
+        }
+        TYPE_HASH_ZIPMAP | TYPE_HASH_ZIPLIST | TYPE_HASH_LISTPACK => {
+            let data = reader.string()?;
+            let entries = match kind {
+                TYPE_HASH_ZIPMAP => zipmap_entries(&data)?,
+                TYPE_HASH_ZIPLIST => ziplist_entries(&data)?,
+                _ => listpack_entries(&data)?,
+            };
+            let mut hash = Hash::default();
+            for pair in entries.chunks_exact(2) {
+                hash.insert(pair[0].clone(), pair[1].clone());
+            }
+            CacheValue::Hash(hash)
+        }
+        TYPE_HASH_METADATA | TYPE_HASH_LISTPACK_EX => {
+            let hash = read_hash_with_ttls(reader, kind, now)?;
+            if hash.is_empty() {
+                return Ok(None);
+            }
+            CacheValue::Hash(hash)
+        }
+        TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+            CacheValue::Stream(read_stream(reader, kind)?)
+        }
+        TYPE_HASH_METADATA_PRE_GA | TYPE_HASH_LISTPACK_EX_PRE_GA => {
+            return Err(RdbError::Unsupported("Hash field TTLs saved by Redis 7.4 release candidates"))
+        }
+        TYPE_MODULE_PRE_GA | TYPE_MODULE_2 => return Err(RdbError::Unsupported("Module data types")),
+        kind => return Err(RdbError::UnknownType(kind)),
+    };
+    Ok(Some(value))
+}
+
+/// Reads a hash with field TTLs, leaving out the fields that have already expired. The
+/// deadlines are saved relative to the earliest one, which comes first, 0 meaning none.
+fn read_hash_with_ttls(reader: &mut Reader<'_>, kind: u8, now: u64) -> Result<Hash, RdbError> {
+    let min_expires_at = reader.millisecond_time()?;
+    let mut fields = Vec::new();
+    if kind == TYPE_HASH_METADATA {
+        for _ in 0..reader.length()? {
+            let ttl = reader.length()?;
+            let expires_at = (ttl != 0).then(|| (ttl + min_expires_at).saturating_sub(1));
+            let field = reader.string()?;
+            fields.push((field, reader.string()?, expires_at));
+        }
+    } else {
+        // Triplets of field, value and absolute deadline
+        for triplet in listpack_entries(&reader.string()?)?.chunks_exact(3) {
+            let expires_at = parse_integer(&triplet[2])? as u64;
+            fields.push((triplet[0].clone(), triplet[1].clone(), (expires_at != 0).then_some(expires_at)));
+        }
+    }
+
+    let mut hash = Hash::default();
+    for (field, value, expires_at) in fields {
+        if expires_at.is_some_and(|expires_at| expires_at <= now) {
+            continue;
+        }
+        hash.insert(field.clone(), value);
+        hash.set_expires_at(&field, expires_at);
+    }
+    Ok(hash)
+}
+
+/// Reads a stream: its entries, packed by node into listpacks, its metadata, and its
+/// consumer groups with their PELs.
+fn read_stream(reader: &mut Reader<'_>, kind: u8) -> Result<Stream, RdbError> {
+    let mut entries = BTreeMap::new();
+    for _ in 0..reader.length()? {
+        let master_id = raw_stream_id(&reader.string()?)?;
+        let node = listpack_entries(&reader.string()?)?;
+        read_stream_node(master_id, &node, &mut entries)?;
+    }
+    let len = reader.length()?;
+    let last_id = reader.stream_id()?;
+    let (max_deleted_id, entries_added) = if kind >= TYPE_STREAM_LISTPACKS_2 {
+        let _first_id = reader.stream_id()?;
+        (reader.stream_id()?, reader.length()?)
+    } else {
+        (StreamId::MIN, len)
+    };
+    let mut stream = Stream::from_parts(entries, last_id, max_deleted_id, entries_added);
+
+    for _ in 0..reader.length()? {
+        let name = reader.string()?;
+        let last_id = reader.stream_id()?;
+        let entries_read = if kind >= TYPE_STREAM_LISTPACKS_2 {
+            // -1, unknown, is saved as the largest length
+            Some(reader.length()?).filter(|&entries_read| entries_read != u64::MAX)
+        } else {
+            stream.estimate_entries_read(last_id)
+        };
+        let mut group = ConsumerGroup::new(last_id, entries_read);
+
+        // The PEL of the group, then the consumers each list which of its entries are theirs
+        for _ in 0..reader.length()? {
+            let id = reader.raw_stream_id()?;
+            let entry = PendingEntry {
+                consumer: Bytes::new(),
+                delivery_time: reader.millisecond_time()?,
+                delivery_count: reader.length()?,
+            };
+            group.pending.insert(id, entry);
+        }
+        for _ in 0..reader.length()? {
+            let consumer_name = reader.string()?;
+            let seen_time = reader.millisecond_time()?;
+            let active_time = if kind >= TYPE_STREAM_LISTPACKS_3 {
+                // -1, never active, is saved as is
+                Some(reader.millisecond_time()?).filter(|&active_time| active_time != u64::MAX)
+            } else {
+                Some(seen_time)
+            };
+            let mut consumer = Consumer {
+                seen_time,
+                active_time,
+                ..Default::default()
+            };
+            for _ in 0..reader.length()? {
+                let id = reader.raw_stream_id()?;
+                let entry = group
+                    .pending
+                    .get_mut(&id)
+                    .ok_or(RdbError::Corrupt("consumer pending entry missing from the group PEL"))?;
+                entry.consumer = consumer_name.clone();
+                consumer.pending.insert(id);
+            }
+            group.consumers.insert(consumer_name, consumer);
+        }
+        if group.pending.values().any(|entry| entry.consumer.is_empty()) {
+            return Err(RdbError::Corrupt("group pending entry without a consumer"));
+        }
+        if !stream.create_group(&name, group) {
+            return Err(RdbError::Corrupt("duplicated consumer group name"));
+        }
+    }
+    Ok(stream)
+}
+
+/// Reads the entries of one stream node. The listpack starts with a master entry: the
+/// number of live and deleted entries and the fields of the first entry. Every entry then
+/// holds its flags, its ID relative to the master ID, its fields unless they are the
+/// master fields, its values, and finally its own number of listpack elements.
+fn read_stream_node(
+    master_id: StreamId,
+    node: &[Bytes],
+    entries: &mut BTreeMap<StreamId, Fields>,
+) -> Result<(), RdbError> {
+    let mut items = node.iter();
+    let mut next = || items.next().ok_or(RdbError::Corrupt("truncated stream node"));
+    let count = parse_integer(next()?)?;
+    let deleted = parse_integer(next()?)?;
+    let master_field_count = parse_integer(next()?)?;
+    let master_fields = (0..master_field_count)
+        .map(|_| next().cloned())
+        .collect::<Result<Vec<Bytes>, RdbError>>()?;
+    // The terminator of the master entry
+    next()?;
+
+    for _ in 0..count + deleted {
+        let flags = parse_integer(next()?)?;
+        let id = StreamId {
+            ms: master_id.ms.wrapping_add(parse_integer(next()?)? as u64),
+            seq: master_id.seq.wrapping_add(parse_integer(next()?)? as u64),
+        };
+        let fields: Fields = if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+            master_fields
+                .iter()
+                .map(|field| Ok((field.clone(), next()?.clone())))
+                .collect::<Result<_, RdbError>>()?
+        } else {
+            let field_count = parse_integer(next()?)?;
+            (0..field_count)
+                .map(|_| Ok((next()?.clone(), next()?.clone())))
+                .collect::<Result<_, RdbError>>()?
+        };
+        // The element count that lets the node be walked backwards
+        next()?;
+        if flags & STREAM_ITEM_FLAG_DELETED == 0 {
+            entries.insert(id, fields);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::rdb::encode;
+
+    // An empty dataset saved by redis-server 7.2.0, checksum included
+    const REDIS_7_2_EMPTY: [u8; 88] = [
+        0x52, 0x45, 0x44, 0x49, 0x53, 0x30, 0x30, 0x31, 0x31, 0xfa, 0x09, 0x72, 0x65, 0x64, 0x69, 0x73,
+        0x2d, 0x76, 0x65, 0x72, 0x05, 0x37, 0x2e, 0x32, 0x2e, 0x30, 0xfa, 0x0a, 0x72, 0x65, 0x64, 0x69,
+        0x73, 0x2d, 0x62, 0x69, 0x74, 0x73, 0xc0, 0x40, 0xfa, 0x05, 0x63, 0x74, 0x69, 0x6d, 0x65, 0xc2,
+        0x6d, 0x08, 0xbc, 0x65, 0xfa, 0x08, 0x75, 0x73, 0x65, 0x64, 0x2d, 0x6d, 0x65, 0x6d, 0xc2, 0xb0,
+        0xc4, 0x10, 0x00, 0xfa, 0x08, 0x61, 0x6f, 0x66, 0x2d, 0x62, 0x61, 0x73, 0x65, 0xc0, 0x00, 0xff,
+        0xf0, 0x6e, 0x3b, 0xfe, 0xc0, 0xff, 0x5a, 0xa2,
+    ];
+
+    // A deadline that stays in the future for as long as these tests run
+    const LATER: u64 = 4_102_444_800_000;
+
+    fn string_entry(payload: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+        payload.push(TYPE_STRING);
+        payload.push(key.len() as u8);
+        payload.extend_from_slice(key);
+        payload.push(value.len() as u8);
+        payload.extend_from_slice(value);
+    }
+
+    /// Every live key with its TTL and its contents, in a form that does not depend on the
+    /// order hashes and sets happen to iterate in.
+    fn dump(store: &CacheStore) -> Vec<String> {
+        let mut entries: Vec<_> = store
+            .entries()
+            .map(|(key, value, expires_at)| {
+                let value = match value {
+                    CacheValue::String(value) => format!("string {:?}", value),
+                    CacheValue::List(list) => format!("list {:?}", list),
+                    CacheValue::Set(set) => {
+                        let mut members: Vec<_> = set.iter().collect();
+                        members.sort();
+                        format!("set {:?}", members)
+                    }
+                    CacheValue::SortedSet(zset) => format!("zset {:?}", zset.iter().collect::<Vec<_>>()),
+                    CacheValue::Hash(hash) => {
+                        let mut fields: Vec<_> = hash
+                            .iter()
+                            .map(|(field, value)| (field, value, hash.expires_at(field)))
+                            .collect();
+                        fields.sort();
+                        format!("hash {:?}", fields)
+                    }
+                    CacheValue::Stream(stream) => format!(
+                        "stream {:?} last {:?} deleted {:?} added {} groups {:?}",
+                        stream.range(StreamId::MIN, StreamId::MAX, false).collect::<Vec<_>>(),
+                        stream.last_id(),
+                        stream.max_deleted_id(),
+                        stream.entries_added(),
+                        stream.groups(),
+                    ),
+                };
+                format!("{:?} ttl {:?} {}", key, expires_at, value)
+            })
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    fn sample_store() -> CacheStore {
+        let now = now_ms();
+        let mut store = CacheStore::new();
+        store.set(Bytes::from("string"), Bytes::from("hello"), None);
+        store.set(Bytes::from("integer"), Bytes::from("-12345"), None);
+        store.set(Bytes::from("large"), Bytes::from("x".repeat(100_000)), None);
+        store.set(Bytes::from("volatile"), Bytes::from("soon"), Some(now + 3_600_000));
+
+        let list = store.list_or_create(&Bytes::from("list")).unwrap();
+        list.extend(["a", "1", "", "-7"].map(Bytes::from));
+        let long_list = store.list_or_create(&Bytes::from("long-list")).unwrap();
+        long_list.extend((0..1000).map(|i| Bytes::from(format!("item-{}", i))));
+
+        let set = store.set_value_or_create(&Bytes::from("intset")).unwrap();
+        for member in ["1", "-5", "300", "70000"] {
+            set.insert(Bytes::from(member));
+        }
+        let set = store.set_value_or_create(&Bytes::from("set")).unwrap();
+        for member in ["a", "b", "1"] {
+            set.insert(Bytes::from(member));
+        }
+        let set = store.set_value_or_create(&Bytes::from("large-set")).unwrap();
+        for i in 0..1000 {
+            set.insert(Bytes::from(format!("m{}", i)));
+        }
+
+        let zset = store.zset_or_create(&Bytes::from("zset")).unwrap();
+        zset.insert(Bytes::from("a"), 1.5);
+        zset.insert(Bytes::from("b"), f64::NEG_INFINITY);
+        zset.insert(Bytes::from("c"), 1e300);
+        zset.insert(Bytes::from("d"), 0.1);
+        let zset = store.zset_or_create(&Bytes::from("large-zset")).unwrap();
+        for i in 0..500 {
+            zset.insert(Bytes::from(format!("m{}", i)), i as f64 / 3.0);
+        }
+
+        let hash = store.hash_or_create(&Bytes::from("hash")).unwrap();
+        hash.insert(Bytes::from("f1"), Bytes::from("v1"));
+        hash.insert(Bytes::from("f2"), Bytes::from("2"));
+        store
+            .set_hash_field_expires_at(&Bytes::from("hash"), b"f2", Some(now + 3_600_000))
+            .unwrap();
+        let hash = store.hash_or_create(&Bytes::from("large-hash")).unwrap();
+        for i in 0..200 {
+            hash.insert(Bytes::from(format!("f{}", i)), Bytes::from("v".repeat(i)));
+        }
+
+        let stream = store.stream_or_create(&Bytes::from("stream")).unwrap();
+        for ms in 1..=3 {
+            let fields = vec![(Bytes::from("field"), Bytes::from(format!("value-{}", ms)))];
+            stream.append(StreamId { ms, seq: 0 }, fields);
+        }
+        stream.remove(StreamId { ms: 2, seq: 0 });
+        let group_name = Bytes::from("group");
+        stream.create_group(&group_name, ConsumerGroup::new(StreamId { ms: 3, seq: 0 }, Some(3)));
+        let group = stream.group_mut(b"group").unwrap();
+        let consumer = Bytes::from("alice");
+        group.create_consumer(&consumer, now);
+        group.assign(StreamId { ms: 1, seq: 0 }, &consumer, now);
+        store
+    }
+
+    #[test]
+    fn redis_server_fixture() {
+        let mut store = CacheStore::new();
+        assert_eq!(load_prefix(&REDIS_7_2_EMPTY, &mut store).unwrap(), (0, REDIS_7_2_EMPTY.len()));
+        assert!(store.keys().is_empty());
+    }
+
+    #[test]
+    fn round_trip() {
+        let store = sample_store();
+        let payload = encode(&store);
+        let mut loaded = CacheStore::new();
+        assert_eq!(load(&payload, &mut loaded).unwrap(), store.keys().len());
+        assert_eq!(dump(&loaded), dump(&store));
+    }
+
+    #[test]
+    fn legacy_encodings() {
+        let mut payload = b"REDIS0006".to_vec();
+        payload.extend_from_slice(&[OPCODE_SELECTDB, 0]);
+        // Strings stored as integers, and one LZF compressed
+        payload.extend_from_slice(&[TYPE_STRING, 4, b'i', b'n', b't', b'8', 0xc0 | ENC_INT8, 0x85]);
+        payload.extend_from_slice(&[TYPE_STRING, 5, b'i', b'n', b't', b'1', b'6', 0xc0 | ENC_INT16]);
+        payload.extend_from_slice(&(-1000i16).to_le_bytes());
+        payload.extend_from_slice(&[TYPE_STRING, 5, b'i', b'n', b't', b'3', b'2', 0xc0 | ENC_INT32]);
+        payload.extend_from_slice(&100_000i32.to_le_bytes());
+        payload.extend_from_slice(&[TYPE_STRING, 3, b'l', b'z', b'f', 0xc0 | ENC_LZF, 5, 10]);
+        payload.extend_from_slice(&[0x00, b'x', 0xe0, 0x00, 0x00]);
+        // A key that expired long ago and one that has yet to
+        payload.push(OPCODE_EXPIRETIME_MS);
+        payload.extend_from_slice(&1u64.to_le_bytes());
+        string_entry(&mut payload, b"gone", b"x");
+        payload.push(OPCODE_EXPIRETIME_MS);
+        payload.extend_from_slice(&LATER.to_le_bytes());
+        string_entry(&mut payload, b"later", b"y");
+        // The ziplist from ziplist.c, an intset and the zipmap from zipmap.c
+        payload.extend_from_slice(&[TYPE_LIST_ZIPLIST, 2, b'z', b'l', 15]);
+        payload.extend_from_slice(&[0x0f, 0, 0, 0, 0x0c, 0, 0, 0, 0x02, 0, 0x00, 0xf3, 0x02, 0xf6, 0xff]);
+        payload.extend_from_slice(&[TYPE_SET_INTSET, 2, b'i', b's', 12]);
+        payload.extend_from_slice(&[2, 0, 0, 0, 2, 0, 0, 0, 0xfb, 0xff, 0x07, 0x00]);
+        let zipmap = b"\x02\x03foo\x03\x00bar\x05hello\x05\x00world\xff";
+        payload.extend_from_slice(&[TYPE_HASH_ZIPMAP, 2, b'z', b'm', zipmap.len() as u8]);
+        payload.extend_from_slice(zipmap);
+        // Other databases are read past
+        payload.extend_from_slice(&[OPCODE_SELECTDB, 1]);
+        string_entry(&mut payload, b"elsewhere", b"z");
+        // A zero checksum was not computed, and is not checked
+        payload.push(OPCODE_EOF);
+        payload.extend_from_slice(&[0; 8]);
+
+        let mut store = CacheStore::new();
+        assert_eq!(load(&payload, &mut store).unwrap(), 8);
+        assert_eq!(store.get(b"int8").unwrap(), Some(Bytes::from("-123")));
+        assert_eq!(store.get(b"int16").unwrap(), Some(Bytes::from("-1000")));
+        assert_eq!(store.get(b"int32").unwrap(), Some(Bytes::from("100000")));
+        assert_eq!(store.get(b"lzf").unwrap(), Some(Bytes::from("x".repeat(10))));
+        assert!(!store.contains_key(b"gone"));
+        assert_eq!(store.expires_at(b"later"), Some(Some(LATER)));
+        assert!(!store.contains_key(b"elsewhere"));
+        let list = store.list(b"zl").unwrap().unwrap();
+        assert_eq!(list, &VecDeque::from([Bytes::from("2"), Bytes::from("5")]));
+        let set = store.set_value(b"is").unwrap().unwrap();
+        assert!(set.contains(b"-5") && set.contains(b"7") && set.len() == 2);
+        let hash = store.hash(b"zm").unwrap().unwrap();
+        assert_eq!(hash.get(b"foo"), Some(&Bytes::from("bar")));
+        assert_eq!(hash.get(b"hello"), Some(&Bytes::from("world")));
+    }
+
+    #[test]
+    fn checksum_is_verified() {
+        let mut payload = encode(&sample_store());
+        let end = payload.len() - 8;
+        assert_eq!(u64::from_le_bytes(payload[end..].try_into().unwrap()), crc64(0, &payload[..end]));
+
+        // Flip a byte of a value, which still parses but no longer matches the checksum
+        let at = payload.windows(5).position(|window| window == b"hello").unwrap();
+        payload[at] = b'j';
+        assert!(matches!(
+            load(&payload, &mut CacheStore::new()),
+            Err(RdbError::WrongChecksum { .. })
+        ));
+
+        // Unless the checksum is zero, meaning it was not computed
+        payload[end..].fill(0);
+        let mut store = CacheStore::new();
+        load(&payload, &mut store).unwrap();
+        assert_eq!(store.get(b"string").unwrap(), Some(Bytes::from("jello")));
+    }
+
+    #[test]
+    fn truncated_payload() {
+        let payload = encode(&sample_store());
+        for len in [0, 5, 9, payload.len() / 2, payload.len() - 1] {
+            assert!(load(&payload[..len], &mut CacheStore::new()).is_err());
+        }
+        assert!(matches!(
+            load(b"RREDIS0011", &mut CacheStore::new()),
+            Err(RdbError::WrongSignature)
+        ));
+        assert!(matches!(
+            load(b"REDIS0099\xff", &mut CacheStore::new()),
+            Err(RdbError::UnsupportedVersion(99))
+        ));
+    }
+}
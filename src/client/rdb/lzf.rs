@@ -0,0 +1,75 @@
+// LZF decompression for the compressed strings of RDB files.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/lzf_d.c
+
+/// Decompresses `input` into a buffer of exactly `len` bytes. None if the data is corrupt or
+/// does not decompress to `len` bytes.
+pub fn decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    // `len` comes from the file: a corrupt one must not allocate more than the input justifies
+    let mut output = Vec::with_capacity(len.min(input.len()));
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 32 {
+            // A run of ctrl + 1 literal bytes
+            let literal = input.get(i..i + ctrl + 1)?;
+            output.extend_from_slice(literal);
+            i += ctrl + 1;
+        } else {
+            // A back reference: copy `len` bytes from `offset` bytes back, which may overlap
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(i)? as usize;
+                i += 1;
+            }
+            let offset = ((ctrl & 0x1f) << 8) + *input.get(i)? as usize + 1;
+            i += 1;
+            let start = output.len().checked_sub(offset)?;
+            for j in 0..run + 2 {
+                output.push(output[start + j]);
+            }
+        }
+        if output.len() > len {
+            return None;
+        }
+    }
+    (output.len() == len).then_some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decompress;
+
+    #[test]
+    fn literal_runs() {
+        assert_eq!(decompress(&[0x02, b'a', b'b', b'c'], 3).unwrap(), b"abc");
+        assert_eq!(decompress(&[0x00, b'a', 0x01, b'b', b'c'], 3).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn back_references() {
+        // "ab", then 3 bytes from 2 back, overlapping the bytes being written
+        assert_eq!(decompress(&[0x01, b'a', b'b', 0x20, 0x01], 5).unwrap(), b"ababa");
+        // A run of 7 + 2 needs the extra length byte
+        assert_eq!(decompress(&[0x00, b'x', 0xe0, 0x00, 0x00], 10).unwrap(), b"x".repeat(10));
+        assert_eq!(decompress(&[0x00, b'x', 0xe0, 0x10, 0x00], 26).unwrap(), b"x".repeat(26));
+    }
+
+    #[test]
+    fn corrupt_input() {
+        // Literal run past the end of the input
+        assert_eq!(decompress(&[0x05, b'a'], 6), None);
+        // Back reference before the start of the output
+        assert_eq!(decompress(&[0x00, b'a', 0x20, 0x01], 4), None);
+        // Missing offset byte
+        assert_eq!(decompress(&[0x00, b'a', 0x20], 4), None);
+    }
+
+    #[test]
+    fn length_must_match() {
+        assert_eq!(decompress(&[0x02, b'a', b'b', b'c'], 2), None);
+        assert_eq!(decompress(&[0x02, b'a', b'b', b'c'], 4), None);
+        // A bogus length does not get allocated up front
+        assert_eq!(decompress(&[0x02, b'a', b'b', b'c'], usize::MAX), None);
+    }
+}
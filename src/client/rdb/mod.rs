@@ -0,0 +1,90 @@
+// RDB snapshot files: the format redis-server persists its dataset in and ships to replicas.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/rdb.h
+
+mod crc64;
+mod encodings;
+mod load;
+mod lzf;
+mod save;
+
+pub use load::{load, load_file, load_prefix};
+pub use save::{encode, write_file, Snapshot};
+
+use thiserror::Error;
+
+// Newest format version we understand, the one of Redis 7.4, and the one we write
+const RDB_VERSION: u32 = 12;
+
+// Opcodes that may appear where a value type is expected
+const OPCODE_SLOT_INFO: u8 = 244;
+const OPCODE_FUNCTION2: u8 = 245;
+const OPCODE_FUNCTION_PRE_GA: u8 = 246;
+const OPCODE_MODULE_AUX: u8 = 247;
+const OPCODE_IDLE: u8 = 248;
+const OPCODE_FREQ: u8 = 249;
+const OPCODE_AUX: u8 = 250;
+const OPCODE_RESIZEDB: u8 = 251;
+const OPCODE_EXPIRETIME_MS: u8 = 252;
+const OPCODE_EXPIRETIME: u8 = 253;
+const OPCODE_SELECTDB: u8 = 254;
+const OPCODE_EOF: u8 = 255;
+
+// Value types
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_MODULE_PRE_GA: u8 = 6;
+const TYPE_MODULE_2: u8 = 7;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+const TYPE_HASH_METADATA_PRE_GA: u8 = 22;
+const TYPE_HASH_LISTPACK_EX_PRE_GA: u8 = 23;
+const TYPE_HASH_METADATA: u8 = 24;
+const TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+// Special encodings of strings, flagged by a length whose two top bits are set
+const ENC_INT8: u8 = 0;
+const ENC_INT16: u8 = 1;
+const ENC_INT32: u8 = 2;
+const ENC_LZF: u8 = 3;
+
+// Nodes of a LIST_QUICKLIST_2
+const QUICKLIST_NODE_PLAIN: u64 = 1;
+const QUICKLIST_NODE_PACKED: u64 = 2;
+
+// Flags of the entries in the listpacks of a stream
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+/// Why an RDB file could not be loaded.
+#[derive(Debug, Error)]
+pub enum RdbError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("Wrong signature trying to load DB from file")]
+    WrongSignature,
+    #[error("Can't handle RDB format version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Unknown RDB encoding type {0}")]
+    UnknownType(u8),
+    #[error("{0} are not supported")]
+    Unsupported(&'static str),
+    #[error("Short read or corrupt data loading DB: {0}")]
+    Corrupt(&'static str),
+    #[error("Wrong RDB checksum expected: ({expected:x}) got ({got:x})")]
+    WrongChecksum { expected: u64, got: u64 },
+}
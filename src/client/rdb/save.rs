@@ -0,0 +1,332 @@
+// Writing the keyspace as an RDB file.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/rdb.c
+
+use super::crc64::crc64;
+use super::encodings::{canonical_integer, ListpackWriter};
+use super::*;
+use crate::client::cache_store::{now_ms, CacheStore, CacheValue};
+use crate::client::types::hash::Hash;
+use crate::client::types::stream::{Stream, StreamId, NODE_MAX_ENTRIES};
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+// Reported in the redis-ver auxiliary field: the first release that writes RDB_VERSION
+const REDIS_VERSION: &str = "7.4.0";
+// list-max-listpack-size -2: packed list nodes hold up to 8 kb
+const LIST_NODE_MAX_SIZE: usize = 8192;
+// Longer strings are never stored as integers
+const INTEGER_STRING_MAX_LEN: usize = 11;
+
+/// The keyspace as it was at one point in time, which can be written out while the server
+/// goes on serving clients, like the forked child of a BGSAVE does in Redis. Values are
+/// shared with the keyspace rather than copied: a write copies the value it modifies if a
+/// snapshot still holds it, much like the pages of a forked process.
+#[derive(Debug)]
+pub struct Snapshot {
+    entries: Vec<(Bytes, Arc<CacheValue>, Option<u64>)>,
+}
+
+impl Snapshot {
+    pub fn capture(store: &CacheStore) -> Self {
+        let entries = store
+            .shared_entries()
+            .map(|(key, value, expires_at)| (key.clone(), Arc::clone(value), expires_at))
+            .collect();
+        Snapshot { entries }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        encode_entries(
+            self.entries
+                .iter()
+                .map(|(key, value, expires_at)| (key, value.as_ref(), *expires_at))
+                .collect(),
+        )
+    }
+}
+
+/// Serializes the keyspace as an RDB payload, checksum included.
+pub fn encode(store: &CacheStore) -> Vec<u8> {
+    encode_entries(store.entries().collect())
+}
+
+fn encode_entries(mut entries: Vec<(&Bytes, &CacheValue, Option<u64>)>) -> Vec<u8> {
+    let now = now_ms();
+    // A hash whose every field has expired is about to be deleted
+    entries.retain(|(_, value, _)| match value {
+        CacheValue::Hash(hash) => live_fields(hash, now).next().is_some(),
+        _ => true,
+    });
+    let mut writer = Writer::default();
+    writer.bytes(format!("REDIS{:04}", RDB_VERSION).as_bytes());
+    writer.aux("redis-ver", REDIS_VERSION);
+    writer.aux("redis-bits", "64");
+    writer.aux("ctime", &(now / 1000).to_string());
+    writer.aux("aof-base", "0");
+
+    writer.u8(OPCODE_SELECTDB);
+    writer.length(0);
+    writer.u8(OPCODE_RESIZEDB);
+    writer.length(entries.len() as u64);
+    writer.length(entries.iter().filter(|(_, _, expires_at)| expires_at.is_some()).count() as u64);
+    for (key, value, expires_at) in entries {
+        if let Some(expires_at) = expires_at {
+            writer.u8(OPCODE_EXPIRETIME_MS);
+            writer.millisecond_time(expires_at);
+        }
+        writer.value(key, value, now);
+    }
+    writer.u8(OPCODE_EOF);
+
+    let checksum = crc64(0, &writer.data);
+    writer.bytes(&checksum.to_le_bytes());
+    writer.data
+}
+
+/// Writes an RDB payload to `path` through a temporary file renamed over it, so that the
+/// file is never seen half written. The temporary file is named after `path`, which lets the
+/// AOF preamble be written while a BGSAVE runs.
+pub fn write_file(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!("temp-{}-{}", std::process::id(), file_name));
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn live_fields(hash: &Hash, now: u64) -> impl Iterator<Item = (&Bytes, &Bytes, Option<u64>)> {
+    hash.iter()
+        .map(|(field, value)| (field, value, hash.expires_at(field)))
+        .filter(move |(_, _, expires_at)| expires_at.is_none_or(|expires_at| expires_at > now))
+}
+
+/// The bytes of an RDB payload being written.
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn millisecond_time(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    /// A length in the smallest of the 6, 14, 32 and 64 bit forms that fits it.
+    fn length(&mut self, len: u64) {
+        match len {
+            0..=0x3f => self.u8(len as u8),
+            0x40..=0x3fff => self.bytes(&[0x40 | (len >> 8) as u8, len as u8]),
+            0x4000..=0xffff_ffff => {
+                self.u8(0x80);
+                self.bytes(&(len as u32).to_be_bytes());
+            }
+            _ => {
+                self.u8(0x81);
+                self.bytes(&len.to_be_bytes());
+            }
+        }
+    }
+
+    /// A string, stored as an integer of up to 32 bits when it is the decimal form of one.
+    fn string(&mut self, value: &[u8]) {
+        let integer = (value.len() <= INTEGER_STRING_MAX_LEN)
+            .then(|| canonical_integer(value))
+            .flatten();
+        if let Some(integer) = integer {
+            if let Ok(integer) = i8::try_from(integer) {
+                return self.bytes(&[0xc0 | ENC_INT8, integer as u8]);
+            }
+            if let Ok(integer) = i16::try_from(integer) {
+                self.u8(0xc0 | ENC_INT16);
+                return self.bytes(&integer.to_le_bytes());
+            }
+            if let Ok(integer) = i32::try_from(integer) {
+                self.u8(0xc0 | ENC_INT32);
+                return self.bytes(&integer.to_le_bytes());
+            }
+        }
+        self.length(value.len() as u64);
+        self.bytes(value);
+    }
+
+    fn aux(&mut self, name: &str, value: &str) {
+        self.u8(OPCODE_AUX);
+        self.string(name.as_bytes());
+        self.string(value.as_bytes());
+    }
+
+    /// A stream ID as the 128 bit big-endian number stream nodes and PELs are keyed by.
+    fn raw_stream_id(&mut self, id: StreamId) {
+        self.bytes(&raw_stream_id(id));
+    }
+
+    fn stream_id(&mut self, id: StreamId) {
+        self.length(id.ms);
+        self.length(id.seq);
+    }
+
+    fn value(&mut self, key: &[u8], value: &CacheValue, now: u64) {
+        match value {
+            CacheValue::String(value) => {
+                self.u8(TYPE_STRING);
+                self.string(key);
+                self.string(value);
+            }
+            CacheValue::List(list) => {
+                self.u8(TYPE_LIST_QUICKLIST_2);
+                self.string(key);
+                self.list(list);
+            }
+            CacheValue::Set(set) => {
+                self.u8(TYPE_SET);
+                self.string(key);
+                self.length(set.len() as u64);
+                set.iter().for_each(|member| self.string(&member));
+            }
+            CacheValue::SortedSet(zset) => {
+                self.u8(TYPE_ZSET_2);
+                self.string(key);
+                self.length(zset.len() as u64);
+                for (member, score) in zset.iter() {
+                    self.string(&member);
+                    self.bytes(&score.to_le_bytes());
+                }
+            }
+            CacheValue::Hash(hash) => self.hash(key, hash, now),
+            CacheValue::Stream(stream) => {
+                self.u8(TYPE_STREAM_LISTPACKS_3);
+                self.string(key);
+                self.stream(stream);
+            }
+        }
+    }
+
+    /// A list as a quicklist of listpacks of up to 8 kb each.
+    fn list(&mut self, list: &VecDeque<Bytes>) {
+        let mut nodes = Vec::new();
+        let mut node = ListpackWriter::new();
+        for element in list {
+            if !node.is_empty() && node.size() + element.len() > LIST_NODE_MAX_SIZE {
+                nodes.push(std::mem::replace(&mut node, ListpackWriter::new()).finish());
+            }
+            node.push(element);
+        }
+        if !node.is_empty() {
+            nodes.push(node.finish());
+        }
+        self.length(nodes.len() as u64);
+        for node in nodes {
+            self.length(QUICKLIST_NODE_PACKED);
+            self.string(&node);
+        }
+    }
+
+    /// A hash, with the deadlines of its fields when some have one. Those are saved relative
+    /// to the earliest one, plus one since zero stands for no deadline.
+    fn hash(&mut self, key: &[u8], hash: &Hash, now: u64) {
+        let fields: Vec<_> = live_fields(hash, now).collect();
+        let min_expires_at = fields.iter().filter_map(|(_, _, expires_at)| *expires_at).min();
+        self.u8(if min_expires_at.is_some() { TYPE_HASH_METADATA } else { TYPE_HASH });
+        self.string(key);
+        if let Some(min_expires_at) = min_expires_at {
+            self.millisecond_time(min_expires_at);
+        }
+        self.length(fields.len() as u64);
+        for (field, value, expires_at) in fields {
+            if let Some(min_expires_at) = min_expires_at {
+                self.length(expires_at.map_or(0, |expires_at| expires_at - min_expires_at + 1));
+            }
+            self.string(field);
+            self.string(value);
+        }
+    }
+
+    /// A stream: its entries packed by node into listpacks, its metadata, and its consumer
+    /// groups with their PELs. See `read_stream_node` for the layout of a node.
+    fn stream(&mut self, stream: &Stream) {
+        let entries: Vec<_> = stream.range(StreamId::MIN, StreamId::MAX, false).collect();
+        self.length(entries.len().div_ceil(NODE_MAX_ENTRIES) as u64);
+        for node in entries.chunks(NODE_MAX_ENTRIES) {
+            let (&master_id, master_fields) = node[0];
+            let mut listpack = ListpackWriter::new();
+            listpack.push_integer(node.len() as i64);
+            listpack.push_integer(0);
+            listpack.push_integer(master_fields.len() as i64);
+            master_fields.iter().for_each(|(field, _)| listpack.push(field));
+            listpack.push_integer(0);
+
+            for &(id, fields) in node {
+                let same_fields = fields.len() == master_fields.len()
+                    && fields.iter().zip(master_fields).all(|((field, _), (master, _))| field == master);
+                listpack.push_integer(if same_fields { STREAM_ITEM_FLAG_SAMEFIELDS } else { 0 });
+                listpack.push_integer(id.ms.wrapping_sub(master_id.ms) as i64);
+                listpack.push_integer(id.seq.wrapping_sub(master_id.seq) as i64);
+                if same_fields {
+                    fields.iter().for_each(|(_, value)| listpack.push(value));
+                } else {
+                    listpack.push_integer(fields.len() as i64);
+                    for (field, value) in fields {
+                        listpack.push(field);
+                        listpack.push(value);
+                    }
+                }
+                // The elements of the entry before this one: flags, the ID, and the fields
+                let fields_len = if same_fields { fields.len() } else { 2 * fields.len() + 1 };
+                listpack.push_integer(3 + fields_len as i64);
+            }
+            self.string(&raw_stream_id(master_id));
+            self.string(&listpack.finish());
+        }
+
+        self.length(stream.len() as u64);
+        self.stream_id(stream.last_id());
+        self.stream_id(stream.first_id());
+        self.stream_id(stream.max_deleted_id());
+        self.length(stream.entries_added());
+
+        self.length(stream.groups().len() as u64);
+        for (name, group) in stream.groups() {
+            self.string(name);
+            self.stream_id(group.last_id);
+            // Unknown is -1, which saves as the largest length
+            self.length(group.entries_read.unwrap_or(u64::MAX));
+            self.length(group.pending.len() as u64);
+            for (&id, entry) in &group.pending {
+                self.raw_stream_id(id);
+                self.millisecond_time(entry.delivery_time);
+                self.length(entry.delivery_count);
+            }
+            self.length(group.consumers.len() as u64);
+            for (name, consumer) in &group.consumers {
+                self.string(name);
+                self.millisecond_time(consumer.seen_time);
+                self.millisecond_time(consumer.active_time.unwrap_or(u64::MAX));
+                self.length(consumer.pending.len() as u64);
+                consumer.pending.iter().for_each(|&id| self.raw_stream_id(id));
+            }
+        }
+    }
+}
+
+fn raw_stream_id(id: StreamId) -> [u8; 16] {
+    ((id.ms as u128) << 64 | id.seq as u128).to_be_bytes()
+}
@@ -0,0 +1,191 @@
+// Hash values: a flat list of pairs while small, a Dict once they grow, and optional
+// per-field deadlines.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/t_hash.c
+
+use crate::client::dict::Dict;
+use crate::client::random::random_below;
+use bytes::Bytes;
+use std::collections::{BTreeSet, HashMap};
+
+// hash-max-listpack-entries and hash-max-listpack-value
+const MAX_LISTPACK_ENTRIES: usize = 128;
+const MAX_LISTPACK_VALUE: usize = 64;
+
+/// A hash value. Hashes with few, short fields and values are kept as a flat list of pairs,
+/// which is compact and at that size as fast to search as a table. The first write beyond
+/// those limits converts the hash to a [`Dict`] for good, like Redis' listpack and
+/// hashtable encodings.
+///
+/// Fields may carry a deadline (Unix milliseconds). The hash itself never hides or drops
+/// expired fields, the keyspace reclaims them through [`Hash::remove_expired`] before the
+/// hash is handed out.
+#[derive(Debug, Clone, Default)]
+pub struct Hash {
+    entries: Entries,
+    field_expires: HashMap<Bytes, u64>,
+    // The same deadlines ordered by time, so the next one to expire is always at hand
+    expiry_order: BTreeSet<(u64, Bytes)>,
+}
+
+#[derive(Debug, Clone)]
+enum Entries {
+    Listpack(Vec<(Bytes, Bytes)>),
+    Table(Dict<Bytes, Bytes>),
+}
+
+impl Default for Entries {
+    fn default() -> Self {
+        Entries::Listpack(Vec::new())
+    }
+}
+
+impl Hash {
+    pub fn len(&self) -> usize {
+        match &self.entries {
+            Entries::Listpack(entries) => entries.len(),
+            Entries::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        match &self.entries {
+            Entries::Listpack(entries) => entries.iter().find(|(f, _)| f == field).map(|(_, v)| v),
+            Entries::Table(table) => table.get(field),
+        }
+    }
+
+    pub fn contains(&self, field: &[u8]) -> bool {
+        self.get(field).is_some()
+    }
+
+    /// Sets a field, returning true if it did not exist before. Like any overwrite this
+    /// clears the field's TTL.
+    pub fn insert(&mut self, field: Bytes, value: Bytes) -> bool {
+        self.set_expires_at(&field, None);
+        if let Entries::Listpack(entries) = &mut self.entries {
+            let fits = field.len() <= MAX_LISTPACK_VALUE && value.len() <= MAX_LISTPACK_VALUE;
+            match entries.iter().position(|(f, _)| *f == field) {
+                Some(i) if fits => {
+                    entries[i].1 = value;
+                    return false;
+                }
+                None if fits && entries.len() < MAX_LISTPACK_ENTRIES => {
+                    entries.push((field, value));
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        self.convert_to_table().insert(field, value).is_none()
+    }
+
+    /// Removes a field, returning true if it existed. A table never converts back.
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        self.set_expires_at(field, None);
+        match &mut self.entries {
+            Entries::Listpack(entries) => match entries.iter().position(|(f, _)| f == field) {
+                Some(i) => {
+                    entries.remove(i);
+                    true
+                }
+                None => false,
+            },
+            Entries::Table(table) => table.remove(field).is_some(),
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&Bytes, &Bytes)> + '_> {
+        match &self.entries {
+            Entries::Listpack(entries) => Box::new(entries.iter().map(|(f, v)| (f, v))),
+            Entries::Table(table) => Box::new(table.iter()),
+        }
+    }
+
+    pub fn random_entry(&self) -> Option<(&Bytes, &Bytes)> {
+        match &self.entries {
+            Entries::Listpack(entries) if entries.is_empty() => None,
+            Entries::Listpack(entries) => {
+                let (f, v) = &entries[random_below(entries.len())];
+                Some((f, v))
+            }
+            Entries::Table(table) => table.random_entry(),
+        }
+    }
+
+    /// The deadline of a field, if it has one.
+    pub fn expires_at(&self, field: &[u8]) -> Option<u64> {
+        self.field_expires.get(field).copied()
+    }
+
+    /// Sets or clears the deadline of a field. Returns false if the field does not exist.
+    pub fn set_expires_at(&mut self, field: &[u8], expires_at: Option<u64>) -> bool {
+        if let Some((field, old)) = self.field_expires.remove_entry(field) {
+            self.expiry_order.remove(&(old, field));
+        }
+        let Some(expires_at) = expires_at else {
+            return self.contains(field);
+        };
+        let Some(field) = self.get_key(field) else {
+            return false;
+        };
+        self.field_expires.insert(field.clone(), expires_at);
+        self.expiry_order.insert((expires_at, field));
+        true
+    }
+
+    /// The earliest deadline among the fields, if any field has one.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.expiry_order.first().map(|(expires_at, _)| *expires_at)
+    }
+
+    /// Removes every field whose deadline is at or before `now` and returns their names.
+    pub fn remove_expired(&mut self, now: u64) -> Vec<Bytes> {
+        let mut expired = Vec::new();
+        while let Some((_, field)) = self.expiry_order.first().filter(|(expires_at, _)| *expires_at <= now) {
+            let field = field.clone();
+            self.remove(&field);
+            expired.push(field);
+        }
+        expired
+    }
+
+    // The stored copy of a field name, so deadlines share it instead of the caller's copy
+    fn get_key(&self, field: &[u8]) -> Option<Bytes> {
+        match &self.entries {
+            Entries::Listpack(entries) => entries.iter().find(|(f, _)| f == field).map(|(f, _)| f.clone()),
+            Entries::Table(table) => table.get_key_value(field).map(|(f, _)| f.clone()),
+        }
+    }
+
+    /// One HSCAN step, see [`Dict::scan`]. A small hash is returned whole in a single step.
+    pub fn scan<F>(&self, cursor: u64, mut visit: F) -> u64
+    where
+        F: FnMut(&Bytes, &Bytes),
+    {
+        match &self.entries {
+            Entries::Listpack(entries) => {
+                entries.iter().for_each(|(f, v)| visit(f, v));
+                0
+            }
+            Entries::Table(table) => table.scan(cursor, visit),
+        }
+    }
+
+    fn convert_to_table(&mut self) -> &mut Dict<Bytes, Bytes> {
+        if let Entries::Listpack(entries) = &mut self.entries {
+            let mut table = Dict::new();
+            for (field, value) in entries.drain(..) {
+                table.insert(field, value);
+            }
+            self.entries = Entries::Table(table);
+        }
+        match &mut self.entries {
+            Entries::Table(table) => table,
+            Entries::Listpack(_) => unreachable!("converted above"),
+        }
+    }
+}
@@ -0,0 +1,6 @@
+// Collection value types whose representation depends on their size.
+
+pub mod hash;
+pub mod set;
+pub mod stream;
+pub mod zset;
@@ -0,0 +1,155 @@
+// Set values: a sorted array of integers while every member is a small integer, a Dict
+// otherwise.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/t_set.c
+//                       https://github.com/redis/redis/blob/unstable/src/intset.c
+
+use crate::client::dict::Dict;
+use crate::client::random::random_below;
+use bytes::Bytes;
+
+// set-max-intset-entries
+const MAX_INTSET_ENTRIES: usize = 512;
+
+/// A set value. Sets whose members are all integers are kept as a sorted array of `i64`,
+/// like Redis' intset encoding: lookups are a binary search and members take eight bytes
+/// each instead of a table entry. The first member that is not an integer, or the one past
+/// the size limit, converts the set to a [`Dict`] for good.
+#[derive(Debug, Clone, Default)]
+pub struct Set {
+    members: Members,
+}
+
+#[derive(Debug, Clone)]
+enum Members {
+    Intset(Vec<i64>),
+    Table(Dict<Bytes, ()>),
+}
+
+impl Default for Members {
+    fn default() -> Self {
+        Members::Intset(Vec::new())
+    }
+}
+
+/// The integer a member stands for, if it is one in canonical form. "007" or "+7" must stay
+/// strings, otherwise SMEMBERS would hand back a different member than the one added.
+fn as_integer(member: &[u8]) -> Option<i64> {
+    let value: i64 = std::str::from_utf8(member).ok()?.parse().ok()?;
+    (value.to_string().as_bytes() == member).then_some(value)
+}
+
+fn integer_member(value: i64) -> Bytes {
+    Bytes::from(value.to_string())
+}
+
+impl Set {
+    pub fn len(&self) -> usize {
+        match &self.members {
+            Members::Intset(values) => values.len(),
+            Members::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match &self.members {
+            Members::Intset(values) => as_integer(member).is_some_and(|value| values.binary_search(&value).is_ok()),
+            Members::Table(table) => table.contains_key(member),
+        }
+    }
+
+    /// Adds a member, returning true if it was not there yet.
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        if let Members::Intset(values) = &mut self.members {
+            if let Some(value) = as_integer(&member) {
+                match values.binary_search(&value) {
+                    Ok(_) => return false,
+                    Err(i) if values.len() < MAX_INTSET_ENTRIES => {
+                        values.insert(i, value);
+                        return true;
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+        self.convert_to_table().insert(member, ()).is_none()
+    }
+
+    /// Removes a member, returning true if it was there. A table never converts back.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.members {
+            Members::Intset(values) => match as_integer(member).map(|value| values.binary_search(&value)) {
+                Some(Ok(i)) => {
+                    values.remove(i);
+                    true
+                }
+                _ => false,
+            },
+            Members::Table(table) => table.remove(member).is_some(),
+        }
+    }
+
+    /// The members, in ascending order for an intset and in table order otherwise.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Bytes> + '_> {
+        match &self.members {
+            Members::Intset(values) => Box::new(values.iter().map(|&value| integer_member(value))),
+            Members::Table(table) => Box::new(table.keys().cloned()),
+        }
+    }
+
+    pub fn random_member(&self) -> Option<Bytes> {
+        match &self.members {
+            Members::Intset(values) if values.is_empty() => None,
+            Members::Intset(values) => Some(integer_member(values[random_below(values.len())])),
+            Members::Table(table) => table.random_entry().map(|(member, _)| member.clone()),
+        }
+    }
+
+    /// Removes and returns a random member.
+    pub fn pop_random(&mut self) -> Option<Bytes> {
+        let member = self.random_member()?;
+        self.remove(&member);
+        Some(member)
+    }
+
+    /// One SSCAN step, see [`Dict::scan`]. An intset is returned whole in a single step.
+    pub fn scan<F>(&self, cursor: u64, mut visit: F) -> u64
+    where
+        F: FnMut(Bytes),
+    {
+        match &self.members {
+            Members::Intset(values) => {
+                values.iter().for_each(|&value| visit(integer_member(value)));
+                0
+            }
+            Members::Table(table) => table.scan(cursor, |member, _| visit(member.clone())),
+        }
+    }
+
+    fn convert_to_table(&mut self) -> &mut Dict<Bytes, ()> {
+        if let Members::Intset(values) = &mut self.members {
+            let mut table = Dict::new();
+            for value in values.drain(..) {
+                table.insert(integer_member(value), ());
+            }
+            self.members = Members::Table(table);
+        }
+        match &mut self.members {
+            Members::Table(table) => table,
+            Members::Intset(_) => unreachable!("converted above"),
+        }
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(members: I) -> Self {
+        let mut set = Set::default();
+        for member in members {
+            set.insert(member);
+        }
+        set
+    }
+}
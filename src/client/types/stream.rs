@@ -0,0 +1,422 @@
+// Stream values: an append-only log of field-value entries keyed by `<ms>-<seq>` IDs.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/t_stream.c
+
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+// stream-node-max-entries: approximate trimming only drops whole nodes of this many entries
+pub const NODE_MAX_ENTRIES: usize = 100;
+// Entries an approximate trim may drop when no LIMIT is given
+const DEFAULT_TRIM_LIMIT: usize = 100 * NODE_MAX_ENTRIES;
+
+/// The ID of a stream entry: the creation time in milliseconds and a sequence number for
+/// entries created within the same millisecond.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// The smallest ID greater than this one.
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => self.ms.checked_add(1).map(|ms| StreamId { ms, seq: 0 }),
+        }
+    }
+
+    /// The greatest ID smaller than this one.
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => self.ms.checked_sub(1).map(|ms| StreamId { ms, seq: u64::MAX }),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The field-value pairs of one entry, in the order they were given to XADD.
+pub type Fields = Vec<(Bytes, Bytes)>;
+
+/// Which entries XTRIM, or XADD with a trimming option, evicts from the head of a stream.
+#[derive(Debug, Clone, Copy)]
+pub enum TrimThreshold {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// An entry delivered to a consumer of a group that the consumer did not acknowledge yet.
+#[derive(Debug, Clone)]
+pub struct PendingEntry {
+    pub consumer: Bytes,
+    pub delivery_time: u64, // ms since the Unix epoch
+    pub delivery_count: u64,
+}
+
+/// A consumer of a group, created on its first read or claim.
+#[derive(Debug, Clone, Default)]
+pub struct Consumer {
+    pub seen_time: u64,           // last attempt to read or claim
+    pub active_time: Option<u64>, // last read or claim that got something
+    pub pending: BTreeSet<StreamId>,
+}
+
+/// A consumer group: how far it has read the stream, and the pending entries list (PEL) of
+/// entries delivered but not acknowledged, which each consumer also indexes for its own.
+#[derive(Debug, Clone)]
+pub struct ConsumerGroup {
+    pub last_id: StreamId,
+    // Entries of the stream read so far, None when it is unknown after deletions
+    pub entries_read: Option<u64>,
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<Bytes, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_id: StreamId, entries_read: Option<u64>) -> Self {
+        ConsumerGroup {
+            last_id,
+            entries_read,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a consumer unless it exists, returning true if it did not.
+    pub fn create_consumer(&mut self, name: &Bytes, now: u64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        let consumer = Consumer {
+            seen_time: now,
+            ..Default::default()
+        };
+        self.consumers.insert(name.clone(), consumer);
+        true
+    }
+
+    /// Looks up a consumer that is about to read or claim, creating it if needed, and
+    /// returns true if it was created.
+    pub fn touch_consumer(&mut self, name: &Bytes, now: u64) -> bool {
+        let created = self.create_consumer(name, now);
+        if let Some(consumer) = self.consumers.get_mut(name) {
+            consumer.seen_time = now;
+        }
+        created
+    }
+
+    /// Deletes a consumer together with its pending entries, returning how many it had.
+    pub fn delete_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    /// Hands a pending entry over to `consumer`, which must exist, or adds it to the PEL.
+    /// Returns the entry so the caller can update its delivery time and count.
+    pub fn assign(&mut self, id: StreamId, consumer: &Bytes, now: u64) -> &mut PendingEntry {
+        if let Some(previous) = self.pending.get(&id).map(|entry| entry.consumer.clone()) {
+            if let Some(previous) = self.consumers.get_mut(&previous) {
+                previous.pending.remove(&id);
+            }
+        }
+        if let Some(owner) = self.consumers.get_mut(consumer) {
+            owner.pending.insert(id);
+        }
+        let entry = self.pending.entry(id).or_insert_with(|| PendingEntry {
+            consumer: consumer.clone(),
+            delivery_time: now,
+            delivery_count: 1,
+        });
+        entry.consumer = consumer.clone();
+        entry
+    }
+
+    /// Removes an entry from the PEL, returning it if it was pending.
+    pub fn remove_pending(&mut self, id: StreamId) -> Option<PendingEntry> {
+        let entry = self.pending.remove(&id)?;
+        if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+            consumer.pending.remove(&id);
+        }
+        Some(entry)
+    }
+}
+
+/// A stream value. The entries are indexed by ID in a B-tree, which keeps the log ordered
+/// for range queries and cheap to trim from the head.
+///
+/// Like Redis it remembers the last ID ever added, so IDs keep growing after the newest
+/// entries are deleted, and an empty stream stays in the keyspace.
+#[derive(Debug, Clone, Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    last_id: StreamId,
+    max_deleted_id: StreamId,
+    entries_added: u64,
+    groups: BTreeMap<Bytes, ConsumerGroup>,
+}
+
+impl Stream {
+    /// Rebuilds a stream from the parts an RDB file saves, groups aside.
+    pub fn from_parts(
+        entries: BTreeMap<StreamId, Fields>,
+        last_id: StreamId,
+        max_deleted_id: StreamId,
+        entries_added: u64,
+    ) -> Self {
+        Stream {
+            entries,
+            last_id,
+            max_deleted_id,
+            entries_added,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// The ID of the oldest entry, 0-0 when the stream is empty.
+    pub fn first_id(&self) -> StreamId {
+        self.entries.keys().next().copied().unwrap_or(StreamId::MIN)
+    }
+
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    /// How many entries were ever added, including the ones deleted or trimmed since.
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<&Fields> {
+        self.entries.get(&id)
+    }
+
+    pub fn first_entry(&self) -> Option<(&StreamId, &Fields)> {
+        self.entries.first_key_value()
+    }
+
+    pub fn last_entry(&self) -> Option<(&StreamId, &Fields)> {
+        self.entries.last_key_value()
+    }
+
+    /// The ID `*` stands for when adding an entry at `now`: the current millisecond, or the
+    /// next ID after the last one if the clock has not moved past it. None once the stream
+    /// has used up the last possible ID.
+    pub fn next_id(&self, now: u64) -> Option<StreamId> {
+        if now > self.last_id.ms {
+            Some(StreamId { ms: now, seq: 0 })
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    /// Appends an entry. The caller makes sure `id` is greater than [`Stream::last_id`].
+    pub fn append(&mut self, id: StreamId, fields: Fields) {
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    /// The entries with IDs within `start..=end`, newest first when `reverse`.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        reverse: bool,
+    ) -> Box<dyn Iterator<Item = (&StreamId, &Fields)> + '_> {
+        if start > end {
+            return Box::new(std::iter::empty());
+        }
+        let entries = self.entries.range(start..=end);
+        if reverse {
+            Box::new(entries.rev())
+        } else {
+            Box::new(entries)
+        }
+    }
+
+    /// Deletes an entry, returning true if it existed.
+    pub fn remove(&mut self, id: StreamId) -> bool {
+        if self.entries.remove(&id).is_none() {
+            return false;
+        }
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    /// Evicts entries from the head until `threshold` holds and returns how many went.
+    ///
+    /// An `approximate` trim (`~`) only evicts in steps of whole nodes, like Redis which
+    /// never splits one of its listpack nodes, and at most `limit` entries, 0 meaning no
+    /// limit. It may leave a few more entries than asked for, but is far cheaper on a
+    /// stream that grows by a single entry at a time.
+    pub fn trim(&mut self, threshold: TrimThreshold, approximate: bool, limit: Option<usize>) -> usize {
+        let mut count = match threshold {
+            TrimThreshold::MaxLen(max_len) => self.len().saturating_sub(max_len),
+            TrimThreshold::MinId(min_id) => self.entries.range(..min_id).count(),
+        };
+        if approximate {
+            let limit = limit.unwrap_or(DEFAULT_TRIM_LIMIT);
+            if limit > 0 {
+                count = count.min(limit);
+            }
+            count -= count % NODE_MAX_ENTRIES;
+        }
+        for _ in 0..count {
+            self.entries.pop_first();
+        }
+        count
+    }
+
+    /// How many listpack nodes Redis would spread the entries over, reported by XINFO in
+    /// place of the size of its radix tree.
+    pub fn node_count(&self) -> usize {
+        self.len().div_ceil(NODE_MAX_ENTRIES)
+    }
+
+    pub fn groups(&self) -> &BTreeMap<Bytes, ConsumerGroup> {
+        &self.groups
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Adds a consumer group unless one with that name exists, returning true if it did not.
+    pub fn create_group(&mut self, name: &Bytes, group: ConsumerGroup) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+        self.groups.insert(name.clone(), group);
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Whether entries from `start` onwards were deleted, which makes counting the entries
+    /// between two IDs impossible without walking them.
+    fn has_tombstones_from(&self, start: StreamId) -> bool {
+        !self.entries.is_empty() && self.max_deleted_id != StreamId::MIN && start <= self.max_deleted_id
+    }
+
+    /// How many entries were added up to and including `id`, when that can be told without
+    /// walking the stream: at either end of it, or anywhere if nothing was deleted from its
+    /// middle. See streamEstimateDistanceFromFirstEverEntry() in Redis.
+    pub fn estimate_entries_read(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if self.entries.is_empty() && id <= self.last_id {
+            return Some(self.entries_added);
+        }
+        if id == self.last_id {
+            return Some(self.entries_added);
+        }
+        if id > self.last_id {
+            return None;
+        }
+        let first_id = self.first_id();
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first_id {
+            let before_first = self.entries_added - self.len() as u64;
+            if id < first_id {
+                return Some(before_first);
+            }
+            if id == first_id {
+                return Some(before_first + 1);
+            }
+        }
+        None
+    }
+
+    /// How many entries a group has yet to read, None when that cannot be told cheaply.
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(entries_read) if !self.has_tombstones_from(group.last_id) => Some(entries_read),
+            _ => self.estimate_entries_read(group.last_id),
+        };
+        entries_read.map(|entries_read| self.entries_added.saturating_sub(entries_read))
+    }
+
+    /// Delivers to `consumer` up to `count` entries the group has not read yet and moves its
+    /// read position past them. Unless `no_ack`, they go to the PEL of the consumer, which
+    /// must exist. Returns the entries together with their IDs.
+    pub fn read_group(
+        &mut self,
+        group_name: &[u8],
+        consumer: &Bytes,
+        count: usize,
+        no_ack: bool,
+        now: u64,
+    ) -> Vec<(StreamId, Fields)> {
+        let Some(group) = self.groups.get(group_name) else {
+            return Vec::new();
+        };
+        let delivered: Vec<(StreamId, Fields)> = match group.last_id.next() {
+            Some(start) => self
+                .range(start, StreamId::MAX, false)
+                .take(count)
+                .map(|(id, fields)| (*id, fields.clone()))
+                .collect(),
+            None => Vec::new(),
+        };
+        let mut entries_read = group.entries_read;
+        for (id, _) in &delivered {
+            entries_read = match entries_read {
+                Some(entries_read) if !self.has_tombstones_from(*id) => Some(entries_read + 1),
+                _ => self.estimate_entries_read(*id),
+            };
+        }
+
+        let Some(group) = self.groups.get_mut(group_name) else {
+            return Vec::new();
+        };
+        let Some(&(last_id, _)) = delivered.last() else {
+            return delivered;
+        };
+        group.last_id = last_id;
+        group.entries_read = entries_read;
+        if !no_ack {
+            for (id, _) in &delivered {
+                let entry = group.assign(*id, consumer, now);
+                entry.delivery_time = now;
+                entry.delivery_count = 1;
+            }
+        }
+        if let Some(consumer) = group.consumers.get_mut(consumer) {
+            consumer.active_time = Some(now);
+        }
+        delivered
+    }
+}
@@ -0,0 +1,642 @@
+// Sorted set values: a skiplist ordered by (score, member) for ranges and ranks, and a
+// Dict from member to score for point lookups.
+// referred source code: https://github.com/redis/redis/blob/unstable/src/t_zset.c
+
+use crate::client::dict::Dict;
+use crate::client::random::random_below;
+use bytes::Bytes;
+
+// ZSKIPLIST_MAXLEVEL, enough for 2^64 elements with P = 1/4
+const MAX_LEVEL: usize = 32;
+// The header node, which holds no element and has every level
+const HEAD: usize = 0;
+
+#[derive(Debug, Clone, Default)]
+struct Level {
+    forward: Option<usize>,
+    // Number of level 0 links the forward link jumps over, which is what makes ranks O(log n)
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Level>,
+}
+
+impl Node {
+    /// Whether this node sorts before the element (score, member).
+    fn precedes(&self, score: f64, member: &[u8]) -> bool {
+        self.score < score || (self.score == score && self.member.as_ref() < member)
+    }
+}
+
+/// The skiplist of Redis' `zskiplist`, with the nodes kept in an arena and linked by index.
+/// Freed slots are reused by later inserts.
+#[derive(Debug, Clone)]
+struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+    len: usize,
+    level: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        SkipList {
+            nodes: vec![Node {
+                member: Bytes::new(),
+                score: 0.0,
+                backward: None,
+                levels: vec![Level::default(); MAX_LEVEL],
+            }],
+            free: Vec::new(),
+            tail: None,
+            len: 0,
+            level: 1,
+        }
+    }
+}
+
+fn random_level() -> usize {
+    let mut level = 1;
+    while level < MAX_LEVEL && random_below(4) == 0 {
+        level += 1;
+    }
+    level
+}
+
+impl SkipList {
+    fn forward(&self, node: usize, level: usize) -> Option<usize> {
+        self.nodes[node].levels[level].forward
+    }
+
+    fn first(&self) -> Option<usize> {
+        self.forward(HEAD, 0)
+    }
+
+    /// Inserts an element that is known not to be in the list yet.
+    fn insert(&mut self, score: f64, member: Bytes) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.forward(x, i).filter(|&next| self.nodes[next].precedes(score, &member)) {
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            member,
+            score,
+            backward: (update[0] != HEAD).then_some(update[0]),
+            levels: vec![Level::default(); level],
+        };
+        let x = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let previous = self.nodes[update[i]].levels[i].clone();
+            self.nodes[x].levels[i] = Level {
+                forward: previous.forward,
+                span: previous.span - (rank[0] - rank[i]),
+            };
+            self.nodes[update[i]].levels[i] = Level {
+                forward: Some(x),
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        // Levels above the new node now jump over one more element
+        for (i, &node) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[node].levels[i].span += 1;
+        }
+
+        match self.forward(x, 0) {
+            Some(next) => self.nodes[next].backward = Some(x),
+            None => self.tail = Some(x),
+        }
+        self.len += 1;
+    }
+
+    /// Removes the element (score, member). Returns false if it is not in the list.
+    fn delete(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i).filter(|&next| self.nodes[next].precedes(score, member)) {
+                x = next;
+            }
+            update[i] = x;
+        }
+        let Some(x) = self
+            .forward(x, 0)
+            .filter(|&x| self.nodes[x].score == score && self.nodes[x].member.as_ref() == member)
+        else {
+            return false;
+        };
+
+        for (i, &node) in update.iter().enumerate().take(self.level) {
+            if self.forward(node, i) == Some(x) {
+                let removed = self.nodes[x].levels[i].clone();
+                let level = &mut self.nodes[node].levels[i];
+                level.span += removed.span;
+                level.span -= 1;
+                level.forward = removed.forward;
+            } else {
+                self.nodes[node].levels[i].span -= 1;
+            }
+        }
+        let backward = self.nodes[x].backward;
+        match self.forward(x, 0) {
+            Some(next) => self.nodes[next].backward = backward,
+            None => self.tail = backward,
+        }
+        while self.level > 1 && self.forward(HEAD, self.level - 1).is_none() {
+            self.level -= 1;
+        }
+
+        self.nodes[x].member = Bytes::new();
+        self.nodes[x].levels = Vec::new();
+        self.free.push(x);
+        self.len -= 1;
+        true
+    }
+
+    /// The 1-based rank of the element (score, member), 0 if it is not in the list.
+    fn rank(&self, score: f64, member: &[u8]) -> usize {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i).filter(|&next| {
+                let node = &self.nodes[next];
+                node.precedes(score, member) || (node.score == score && node.member.as_ref() == member)
+            }) {
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if x != HEAD && self.nodes[x].member.as_ref() == member {
+                return rank;
+            }
+        }
+        0
+    }
+
+    /// The node at a 1-based rank.
+    fn by_rank(&self, rank: usize) -> Option<usize> {
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i).filter(|_| traversed + self.nodes[x].levels[i].span <= rank) {
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == rank {
+                return (x != HEAD).then_some(x);
+            }
+        }
+        None
+    }
+
+    /// The first node inside `range`.
+    fn first_in_range<R: SortedRange>(&self, range: &R) -> Option<usize> {
+        if range.is_empty() {
+            return None;
+        }
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i).filter(|&next| !self.above_min(range, next)) {
+                x = next;
+            }
+        }
+        self.forward(x, 0).filter(|&x| self.below_max(range, x))
+    }
+
+    /// The last node inside `range`.
+    fn last_in_range<R: SortedRange>(&self, range: &R) -> Option<usize> {
+        if range.is_empty() {
+            return None;
+        }
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i).filter(|&next| self.below_max(range, next)) {
+                x = next;
+            }
+        }
+        Some(x).filter(|&x| x != HEAD && self.above_min(range, x))
+    }
+
+    fn above_min<R: SortedRange>(&self, range: &R, node: usize) -> bool {
+        range.above_min(self.nodes[node].score, &self.nodes[node].member)
+    }
+
+    fn below_max<R: SortedRange>(&self, range: &R, node: usize) -> bool {
+        range.below_max(self.nodes[node].score, &self.nodes[node].member)
+    }
+}
+
+/// A range of a sorted set, by score or by member. Ranges are walked from the first element
+/// above their minimum, which must hold for every element after it too.
+pub trait SortedRange {
+    fn is_empty(&self) -> bool;
+    fn above_min(&self, score: f64, member: &[u8]) -> bool;
+    fn below_max(&self, score: f64, member: &[u8]) -> bool;
+}
+
+/// A score interval such as `(1 5`, where either end may be exclusive or infinite.
+#[derive(Debug, Clone, Copy)]
+pub struct ScoreRange {
+    pub min: f64,
+    pub max: f64,
+    pub min_exclusive: bool,
+    pub max_exclusive: bool,
+}
+
+impl SortedRange for ScoreRange {
+    fn is_empty(&self) -> bool {
+        self.min > self.max || (self.min == self.max && (self.min_exclusive || self.max_exclusive))
+    }
+
+    fn above_min(&self, score: f64, _: &[u8]) -> bool {
+        if self.min_exclusive {
+            score > self.min
+        } else {
+            score >= self.min
+        }
+    }
+
+    fn below_max(&self, score: f64, _: &[u8]) -> bool {
+        if self.max_exclusive {
+            score < self.max
+        } else {
+            score <= self.max
+        }
+    }
+}
+
+/// One end of a lexicographical range: `-`, `+`, `[member` or `(member`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+/// A member interval such as `[a (c`. Only meaningful when all elements share a score,
+/// the order then being byte order of the members.
+#[derive(Debug, Clone)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+impl SortedRange for LexRange {
+    fn is_empty(&self) -> bool {
+        match (&self.min, &self.max) {
+            (LexBound::Max, _) | (_, LexBound::Min) => true,
+            (LexBound::Min, _) | (_, LexBound::Max) => false,
+            (LexBound::Inclusive(min), LexBound::Inclusive(max)) => min > max,
+            (LexBound::Inclusive(min) | LexBound::Exclusive(min), LexBound::Inclusive(max) | LexBound::Exclusive(max)) => {
+                min >= max
+            }
+        }
+    }
+
+    fn above_min(&self, _: f64, member: &[u8]) -> bool {
+        match &self.min {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(min) => member >= min.as_ref(),
+            LexBound::Exclusive(min) => member > min.as_ref(),
+        }
+    }
+
+    fn below_max(&self, _: f64, member: &[u8]) -> bool {
+        match &self.max {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(max) => member <= max.as_ref(),
+            LexBound::Exclusive(max) => member < max.as_ref(),
+        }
+    }
+}
+
+/// A sorted set value: members ordered by score, ties broken by the byte order of the
+/// members. Like Redis' skiplist encoding it pairs a skiplist, which serves ranges and ranks
+/// in O(log n), with a [`Dict`] from member to score for O(1) ZSCORE.
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    list: SkipList,
+    scores: Dict<Bytes, f64>,
+}
+
+impl SortedSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Adds a member or moves it to a new score. Returns true if it was not there yet.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        match self.scores.insert(member.clone(), score) {
+            Some(old) if old == score => false,
+            Some(old) => {
+                self.list.delete(old, &member);
+                self.list.insert(score, member);
+                false
+            }
+            None => {
+                self.list.insert(score, member);
+                true
+            }
+        }
+    }
+
+    /// Removes a member, returning true if it was there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.delete(score, member),
+            None => false,
+        }
+    }
+
+    /// The 0-based rank of a member, counted from the highest score when `reverse`.
+    pub fn rank(&self, member: &[u8], reverse: bool) -> Option<usize> {
+        let score = self.score(member)?;
+        let rank = self.list.rank(score, member) - 1;
+        Some(if reverse { self.len() - 1 - rank } else { rank })
+    }
+
+    /// The elements with 0-based ranks `start..=end`, which the caller keeps within bounds.
+    pub fn range_by_rank(&self, start: usize, end: usize, reverse: bool) -> Vec<(Bytes, f64)> {
+        let first = if reverse { self.len() - start } else { start + 1 };
+        let node = self.list.by_rank(first);
+        self.walk(node, reverse).take(end - start + 1).collect()
+    }
+
+    /// The elements inside `range` in order, or in reverse order when `reverse`, skipping
+    /// the first `offset` and returning at most `limit`.
+    pub fn range<R: SortedRange>(&self, range: &R, reverse: bool, offset: usize, limit: Option<usize>) -> Vec<(Bytes, f64)> {
+        let node = if reverse {
+            self.list.last_in_range(range)
+        } else {
+            self.list.first_in_range(range)
+        };
+        self.walk(node, reverse)
+            .skip(offset)
+            .take_while(|(member, score)| {
+                if reverse {
+                    range.above_min(*score, member)
+                } else {
+                    range.below_max(*score, member)
+                }
+            })
+            .take(limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// The number of elements inside `range`, computed from the ranks of its ends.
+    pub fn count<R: SortedRange>(&self, range: &R) -> usize {
+        let Some(first) = self.list.first_in_range(range) else {
+            return 0;
+        };
+        let Some(last) = self.list.last_in_range(range) else {
+            return 0;
+        };
+        let rank = |node: usize| self.list.rank(self.list.nodes[node].score, &self.list.nodes[node].member);
+        rank(last) - rank(first) + 1
+    }
+
+    /// The elements in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (Bytes, f64)> + '_ {
+        self.walk(self.list.first(), false)
+    }
+
+    /// Removes and returns the element with the lowest score, or the highest when `max`.
+    pub fn pop(&mut self, max: bool) -> Option<(Bytes, f64)> {
+        let node = if max { self.list.tail } else { self.list.first() }?;
+        let member = self.list.nodes[node].member.clone();
+        let score = self.list.nodes[node].score;
+        self.remove(&member);
+        Some((member, score))
+    }
+
+    fn walk(&self, start: Option<usize>, reverse: bool) -> impl Iterator<Item = (Bytes, f64)> + '_ {
+        std::iter::successors(start, move |&node| {
+            if reverse {
+                self.list.nodes[node].backward
+            } else {
+                self.list.forward(node, 0)
+            }
+        })
+        .map(|node| (self.list.nodes[node].member.clone(), self.list.nodes[node].score))
+    }
+}
+
+impl FromIterator<(Bytes, f64)> for SortedSet {
+    fn from_iter<I: IntoIterator<Item = (Bytes, f64)>>(elements: I) -> Self {
+        let mut zset = SortedSet::default();
+        for (member, score) in elements {
+            zset.insert(member, score);
+        }
+        zset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(i: usize) -> Bytes {
+        Bytes::from(format!("m{:03}", i))
+    }
+
+    /// A sorted set of 300 members on 40 distinct scores, so that ties are ordered by member,
+    /// with every third member removed again, and the elements it should hold in order.
+    fn zset_after_deletes() -> (SortedSet, Vec<(Bytes, f64)>) {
+        let mut zset = SortedSet::default();
+        for i in 0..300 {
+            assert!(zset.insert(member(i), (i * 7 % 40) as f64));
+        }
+        for i in (0..300).step_by(3) {
+            assert!(zset.remove(&member(i)));
+        }
+        assert!(!zset.remove(&member(0)));
+        let mut expected: Vec<_> = (0..300)
+            .filter(|i| i % 3 != 0)
+            .map(|i| (member(i), (i * 7 % 40) as f64))
+            .collect();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        (zset, expected)
+    }
+
+    fn score_range(min: f64, max: f64, min_exclusive: bool, max_exclusive: bool) -> ScoreRange {
+        ScoreRange {
+            min,
+            max,
+            min_exclusive,
+            max_exclusive,
+        }
+    }
+
+    #[test]
+    fn ranks_after_deletes() {
+        let (zset, expected) = zset_after_deletes();
+        assert_eq!(zset.len(), expected.len());
+        assert_eq!(zset.iter().collect::<Vec<_>>(), expected);
+        for (rank, (member, _)) in expected.iter().enumerate() {
+            assert_eq!(zset.rank(member, false), Some(rank));
+            assert_eq!(zset.rank(member, true), Some(expected.len() - 1 - rank));
+        }
+        assert_eq!(zset.rank(&member(3), false), None);
+    }
+
+    #[test]
+    fn ranges_by_rank_after_deletes() {
+        let (zset, expected) = zset_after_deletes();
+        let last = expected.len() - 1;
+        let reversed: Vec<_> = expected.iter().rev().cloned().collect();
+        for (start, end) in [(0, 0), (0, last), (5, 17), (last, last), (last - 3, last)] {
+            assert_eq!(zset.range_by_rank(start, end, false), expected[start..=end]);
+            assert_eq!(zset.range_by_rank(start, end, true), reversed[start..=end]);
+        }
+    }
+
+    #[test]
+    fn score_range_bounds_after_deletes() {
+        let (zset, expected) = zset_after_deletes();
+        let within = |range: &ScoreRange| -> Vec<(Bytes, f64)> {
+            expected
+                .iter()
+                .filter(|(member, score)| range.above_min(*score, member) && range.below_max(*score, member))
+                .cloned()
+                .collect()
+        };
+        let ranges = [
+            score_range(f64::NEG_INFINITY, f64::INFINITY, false, false),
+            score_range(5.0, 10.0, false, false),
+            score_range(5.0, 10.0, true, false),
+            score_range(5.0, 10.0, false, true),
+            score_range(5.0, 10.0, true, true),
+            score_range(7.0, 7.0, false, false),
+            score_range(39.0, f64::INFINITY, false, false),
+            score_range(f64::NEG_INFINITY, 0.0, false, false),
+            score_range(40.0, 50.0, false, false),
+        ];
+        for range in &ranges {
+            let forward = within(range);
+            assert!(!forward.is_empty() || range.min >= 40.0);
+            assert_eq!(zset.range(range, false, 0, None), forward);
+            assert_eq!(zset.range(range, true, 0, None), forward.iter().rev().cloned().collect::<Vec<_>>());
+            assert_eq!(zset.count(range), forward.len());
+            assert_eq!(zset.range(range, false, 2, Some(3)), forward.iter().skip(2).take(3).cloned().collect::<Vec<_>>());
+        }
+
+        for range in [
+            score_range(10.0, 5.0, false, false),
+            score_range(7.0, 7.0, true, false),
+            score_range(7.0, 7.0, false, true),
+        ] {
+            assert!(range.is_empty());
+            assert!(zset.range(&range, false, 0, None).is_empty());
+            assert_eq!(zset.count(&range), 0);
+        }
+    }
+
+    #[test]
+    fn updating_a_score_moves_the_member() {
+        let mut zset: SortedSet = [("a", 1.0), ("b", 2.0), ("c", 3.0)]
+            .into_iter()
+            .map(|(member, score)| (Bytes::from(member), score))
+            .collect();
+        assert!(!zset.insert(Bytes::from("a"), 10.0));
+        assert_eq!(zset.len(), 3);
+        assert_eq!(zset.score(b"a"), Some(10.0));
+        assert_eq!(zset.rank(b"a", false), Some(2));
+        assert_eq!(zset.pop(false), Some((Bytes::from("b"), 2.0)));
+        assert_eq!(zset.pop(true), Some((Bytes::from("a"), 10.0)));
+        assert_eq!(zset.iter().collect::<Vec<_>>(), vec![(Bytes::from("c"), 3.0)]);
+    }
+
+    #[test]
+    fn lexical_ranges() {
+        let zset: SortedSet = (b'a'..=b'g').map(|c| (Bytes::from(vec![c]), 0.0)).collect();
+        let lex = |min: LexBound, max: LexBound| LexRange { min, max };
+        let inclusive = |member: &'static str| LexBound::Inclusive(Bytes::from(member));
+        let exclusive = |member: &'static str| LexBound::Exclusive(Bytes::from(member));
+        let members = |range: &LexRange, reverse: bool| -> String {
+            zset.range(range, reverse, 0, None)
+                .into_iter()
+                .map(|(member, _)| member[0] as char)
+                .collect()
+        };
+
+        let cases = [
+            (lex(LexBound::Min, LexBound::Max), "abcdefg"),
+            (lex(inclusive("b"), inclusive("e")), "bcde"),
+            (lex(exclusive("b"), inclusive("e")), "cde"),
+            (lex(inclusive("b"), exclusive("e")), "bcd"),
+            (lex(exclusive("b"), exclusive("e")), "cd"),
+            (lex(LexBound::Min, exclusive("c")), "ab"),
+            (lex(inclusive("f"), LexBound::Max), "fg"),
+            (lex(inclusive("bb"), inclusive("dd")), "cd"),
+            (lex(inclusive("d"), inclusive("d")), "d"),
+            (lex(inclusive("x"), LexBound::Max), ""),
+        ];
+        for (range, expected) in &cases {
+            assert_eq!(members(range, false), *expected);
+            assert_eq!(members(range, true), expected.chars().rev().collect::<String>());
+            assert_eq!(zset.count(range), expected.len());
+        }
+        assert_eq!(
+            zset.range(&lex(LexBound::Min, LexBound::Max), false, 1, Some(2))
+                .into_iter()
+                .map(|(member, _)| member)
+                .collect::<Vec<_>>(),
+            vec![Bytes::from("b"), Bytes::from("c")]
+        );
+
+        for range in [
+            lex(inclusive("e"), inclusive("b")),
+            lex(exclusive("d"), inclusive("d")),
+            lex(inclusive("d"), exclusive("d")),
+            lex(LexBound::Max, LexBound::Max),
+            lex(LexBound::Min, LexBound::Min),
+        ] {
+            assert!(range.is_empty());
+            assert_eq!(members(&range, false), "");
+            assert_eq!(zset.count(&range), 0);
+        }
+    }
+}
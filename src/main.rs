@@ -2,6 +2,7 @@ mod client;
 
 use std::env;
 
+use client::config::{parse_save_points, parse_yes_no, AppendFsync, ServerConfig};
 use client::connection::{RedisServer, ReplicaConfig};
 
 #[tokio::main]
@@ -11,6 +12,7 @@ async fn main() -> std::io::Result<()> {
     // Parse port
     let mut master_port = 6379u16;
     let mut replica_config: Option<ReplicaConfig> = None;
+    let mut config = ServerConfig::default();
     let mut i = 1;
     while i < args.len() {
         println!("args[{}] = {:?}", i, args[i]);
@@ -26,6 +28,88 @@ async fn main() -> std::io::Result<()> {
                     i += 1;
                 }
             }
+            // directory of the RDB file
+            "--dir" => {
+                if i + 1 < args.len() {
+                    config.dir = args[i + 1].clone();
+                    i += 2;
+                } else {
+                    eprintln!("Error: --dir requires a value");
+                    i += 1;
+                }
+            }
+            // name of the RDB file
+            "--dbfilename" => {
+                if i + 1 < args.len() {
+                    config.dbfilename = args[i + 1].clone();
+                    i += 2;
+                } else {
+                    eprintln!("Error: --dbfilename requires a value");
+                    i += 1;
+                }
+            }
+            // snapshotting rules, "<seconds> <changes>" pairs
+            "--save" => {
+                if i + 1 < args.len() {
+                    match parse_save_points(&args[i + 1]) {
+                        Some(save_points) => config.save_points = save_points,
+                        None => eprintln!("Error: --save requires pairs of '<seconds> <changes>'"),
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("Error: --save requires a value");
+                    i += 1;
+                }
+            }
+            // whether writes are logged to the append-only file
+            "--appendonly" => {
+                if i + 1 < args.len() {
+                    match parse_yes_no(&args[i + 1]) {
+                        Some(appendonly) => config.appendonly = appendonly,
+                        None => eprintln!("Error: --appendonly requires 'yes' or 'no'"),
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("Error: --appendonly requires a value");
+                    i += 1;
+                }
+            }
+            // name of the append-only file
+            "--appendfilename" => {
+                if i + 1 < args.len() {
+                    config.appendfilename = args[i + 1].clone();
+                    i += 2;
+                } else {
+                    eprintln!("Error: --appendfilename requires a value");
+                    i += 1;
+                }
+            }
+            // when the append-only file is fsynced
+            "--appendfsync" => {
+                if i + 1 < args.len() {
+                    match AppendFsync::parse(&args[i + 1]) {
+                        Some(appendfsync) => config.appendfsync = appendfsync,
+                        None => eprintln!("Error: --appendfsync requires 'always', 'everysec' or 'no'"),
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("Error: --appendfsync requires a value");
+                    i += 1;
+                }
+            }
+            // whether an append-only file with a truncated last command is loaded
+            "--aof-load-truncated" => {
+                if i + 1 < args.len() {
+                    match parse_yes_no(&args[i + 1]) {
+                        Some(load_truncated) => config.aof_load_truncated = load_truncated,
+                        None => eprintln!("Error: --aof-load-truncated requires 'yes' or 'no'"),
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("Error: --aof-load-truncated requires a value");
+                    i += 1;
+                }
+            }
             // replication mode
             "--replicaof" => {
                 if i + 1 < args.len() {
@@ -72,6 +156,6 @@ async fn main() -> std::io::Result<()> {
         config
     });
     
-    let server = RedisServer::new("127.0.0.1".to_string(), master_port, updated_replica_config);
+    let server = RedisServer::new("127.0.0.1".to_string(), master_port, config, updated_replica_config);
     server.run().await
 }